@@ -0,0 +1,21 @@
+#[macro_use]
+extern crate criterion;
+extern crate mse_fmp4;
+
+use criterion::{black_box, Criterion};
+use mse_fmp4::avc::SpsSummary;
+
+// High profile, 1920x1080, level 4.0 (no scaling matrix).
+const HIGH_PROFILE_SPS: [u8; 27] = [
+    0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0xc0, 0x44, 0x00, 0x00, 0x03,
+    0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xf0, 0x3c, 0x60, 0xc6, 0x58,
+];
+
+fn read_high_profile_sps(c: &mut Criterion) {
+    c.bench_function("SpsSummary::read_from (High profile)", |b| {
+        b.iter(|| SpsSummary::read_from(black_box(&HIGH_PROFILE_SPS[1..])).expect("valid SPS"))
+    });
+}
+
+criterion_group!(benches, read_high_profile_sps);
+criterion_main!(benches);
@@ -0,0 +1,789 @@
+//! Access unit analysis and fragmentation.
+use crate::avc::{
+    nal_unit_type_of, AccessUnits, NalUnit, NalUnitType, ParameterSetStore, SliceHeaderSummary,
+    SliceType, SpsSummary,
+};
+use crate::codec::LengthSize;
+use crate::sei::has_recovery_point;
+use crate::{ErrorKind, Result};
+use std::cmp;
+use std::collections::BTreeMap;
+
+/// Summary of an access unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuInfo {
+    /// The NAL units of the access unit (without start codes).
+    pub nal_units: Vec<Vec<u8>>,
+
+    /// Whether the access unit contains an IDR picture (i.e., whether it is a sync sample).
+    pub is_sync: bool,
+
+    /// The duration of the access unit.
+    pub duration: u64,
+}
+impl AuInfo {
+    /// Returns the total size of the NAL units (excluding start codes or length prefixes).
+    pub fn size(&self) -> usize {
+        self.nal_units.iter().map(|nal_unit| nal_unit.len()).sum()
+    }
+}
+
+/// Returns the size of the given access unit in the length-prefixed (i.e., MP4 sample) format.
+///
+/// This is the `sample_size` of the sample made by writing each of `au_nals` with
+/// `LengthSize::write_nal_unit`: the sum of the sizes of the NAL units plus `length_size`
+/// bytes for each of them.
+///
+/// Unlike `(au_nals: &[&[u8]], length_size: usize)`, the signature takes:
+/// - any slice of byte buffers, so that both `&[&[u8]]` and `AuInfo::nal_units` are accepted, and
+/// - a `LengthSize` instead of a `usize`: a size other than `1`, `2` or `4` bytes is rejected
+///   with `ErrorKind::InvalidInput` by `LengthSize::new` before this function is called,
+///   so the result always matches the samples written with the same `LengthSize`.
+pub fn au_length_prefixed_size<T: AsRef<[u8]>>(au_nals: &[T], length_size: LengthSize) -> usize {
+    au_nals
+        .iter()
+        .map(|nal_unit| length_size.get() + nal_unit.as_ref().len())
+        .sum()
+}
+
+/// Splits the given H.264 Annex B stream into access units.
+///
+/// The duration of each access unit is `2 * num_units_in_tick` (in the `time_scale` units)
+/// of the VUI timing information of the most recent SPS, or `0` if it is not signaled.
+pub fn analyze_access_units(bytes: &[u8]) -> Result<Vec<AuInfo>> {
+    let mut duration = 0;
+    let mut aus = Vec::new();
+    for access_unit in track!(AccessUnits::new(bytes))? {
+        aus.push(track!(make_au_info(&access_unit, &mut duration))?);
+    }
+    Ok(aus)
+}
+
+/// Makes an `AuInfo` and updates `duration` if the access unit contains an SPS.
+fn make_au_info(access_unit: &[&[u8]], duration: &mut u64) -> Result<AuInfo> {
+    let mut is_sync = false;
+    for nal_unit in access_unit {
+        let nal_unit_type = match nal_unit.first() {
+            Some(&b) => nal_unit_type_of(b).ok(),
+            None => continue,
+        };
+        match nal_unit_type {
+            Some(NalUnitType::SequenceParameterSet) => {
+                let sps = track!(SpsSummary::read_from(&nal_unit[1..]))?;
+                *duration = sps
+                    .vui_parameters
+                    .as_ref()
+                    .and_then(|vui| vui.timing_info.as_ref())
+                    .map_or(0, |x| 2 * u64::from(x.num_units_in_tick));
+            }
+            Some(NalUnitType::CodedSliceOfAnIdrPicture) => {
+                is_sync = true;
+            }
+            _ => {}
+        }
+    }
+    Ok(AuInfo {
+        nal_units: access_unit.iter().map(|nal_unit| nal_unit.to_vec()).collect(),
+        is_sync,
+        duration: *duration,
+    })
+}
+
+/// Adapter that associates the access units of an H.264 Annex B stream
+/// with externally supplied timestamps.
+///
+/// Each item is `(au, dts, pts)`, where `dts` and `pts` are returned by the closure
+/// given the index of the access unit in decoding order.
+///
+/// The supplied PTSs are validated against the picture order counts of the stream:
+/// within a coded video sequence, the pictures must be presented in the order of their
+/// picture order counts. If this does not hold, an `ErrorKind::InvalidInput` error is yielded
+/// and the iteration ends.
+/// The validation is skipped for the pictures whose SPS uses `pic_order_cnt_type` `1`.
+#[derive(Debug)]
+pub struct TimedNalStream<'a, F> {
+    access_units: AccessUnits<'a>,
+    timestamps: F,
+    index: usize,
+    duration: u64,
+    poc_decoder: PocDecoder,
+    pts_by_poc: BTreeMap<i64, u64>,
+    is_failed: bool,
+}
+impl<'a, F> TimedNalStream<'a, F>
+where
+    F: FnMut(usize) -> (u64, u64),
+{
+    /// Makes a new `TimedNalStream` instance.
+    pub fn new(bytes: &'a [u8], timestamps: F) -> Result<Self> {
+        Ok(TimedNalStream {
+            access_units: track!(AccessUnits::new(bytes))?,
+            timestamps,
+            index: 0,
+            duration: 0,
+            poc_decoder: PocDecoder::default(),
+            pts_by_poc: BTreeMap::new(),
+            is_failed: false,
+        })
+    }
+
+    fn process(&mut self, access_unit: &[&[u8]]) -> Result<(AuInfo, u64, u64)> {
+        let au = track!(make_au_info(access_unit, &mut self.duration))?;
+        let (dts, pts) = (self.timestamps)(self.index);
+        if let Some(poc) = track!(self.poc_decoder.decode(access_unit))? {
+            if au.is_sync {
+                self.pts_by_poc.clear();
+            }
+            let prev = self.pts_by_poc.range(..poc).next_back();
+            let next = self.pts_by_poc.range(poc..).next();
+            let is_consistent = !matches!(prev, Some((_, &prev_pts)) if prev_pts >= pts)
+                && !matches!(next, Some((_, &next_pts)) if next_pts <= pts);
+            track_assert!(
+                is_consistent,
+                ErrorKind::InvalidInput,
+                "The PTS of the access unit #{} ({}) is inconsistent with its POC ({})",
+                self.index,
+                pts,
+                poc
+            );
+            self.pts_by_poc.insert(poc, pts);
+        }
+        Ok((au, dts, pts))
+    }
+}
+impl<'a, F> Iterator for TimedNalStream<'a, F>
+where
+    F: FnMut(usize) -> (u64, u64),
+{
+    type Item = Result<(AuInfo, u64, u64)>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_failed {
+            return None;
+        }
+        let access_unit = self.access_units.next()?;
+        let result = track!(self.process(&access_unit));
+        self.index += 1;
+        self.is_failed = result.is_err();
+        Some(result)
+    }
+}
+
+/// Derives the picture order counts of the access units given in decoding order.
+#[derive(Debug, Default)]
+struct PocDecoder {
+    parameter_sets: ParameterSetStore,
+    state: PocState,
+}
+impl PocDecoder {
+    /// Returns the picture order count of the primary coded picture of the given access unit
+    /// (`None` if it is not derived).
+    fn decode<T: AsRef<[u8]>>(&mut self, access_unit: &[T]) -> Result<Option<i64>> {
+        for nal_unit in access_unit {
+            track!(self.parameter_sets.ingest(nal_unit.as_ref()))?;
+        }
+        for nal_unit in access_unit {
+            let nal_unit = nal_unit.as_ref();
+            let header = match NalUnit::read_from(nal_unit) {
+                Ok(header) => header,
+                Err(_) => continue,
+            };
+            let is_idr = match header.nal_unit_type {
+                NalUnitType::CodedSliceOfAnIdrPicture => true,
+                NalUnitType::CodedSliceOfANonIdrPicture => false,
+                _ => continue,
+            };
+
+            let slice_header = track!(SliceHeaderSummary::read_from(&nal_unit[1..]))?;
+            let pps = track_assert_some!(
+                self.parameter_sets.active_pps(slice_header.pic_parameter_set_id),
+                ErrorKind::InvalidInput,
+                "Unknown PPS: {}",
+                slice_header.pic_parameter_set_id
+            );
+            let sps = track_assert_some!(
+                self.parameter_sets.active_sps(pps.seq_parameter_set_id),
+                ErrorKind::InvalidInput,
+                "Unknown SPS: {}",
+                pps.seq_parameter_set_id
+            );
+            let slice_header = track!(SliceHeaderSummary::read_with_parameter_sets(
+                &nal_unit[1..],
+                header.nal_unit_type,
+                sps,
+                pps
+            ))?;
+            return Ok(self.state.update(&header, is_idr, sps, &slice_header));
+        }
+        Ok(None)
+    }
+}
+
+/// The state of the picture order count decoding process (8.2.1, ISO/IEC 14496-10).
+///
+/// - `pic_order_cnt_type` `0`: the POC is derived from `pic_order_cnt_lsb` of the slice header
+///   and the MSB carried over from the previous reference picture (8.2.1.1).
+/// - `pic_order_cnt_type` `2`: the slice header has no POC fields.
+///   The POC is derived from `frame_num` as `2 * (FrameNumOffset + frame_num)`,
+///   minus one for non-reference pictures (8.2.1.3).
+///   Hence the output order is the same as the decoding order.
+///
+/// Since memory management control operations are not parsed,
+/// the POC is not reset by `memory_management_control_operation` `5`.
+#[derive(Debug, Default)]
+struct PocState {
+    prev_pic_order_cnt_msb: i64,
+    prev_pic_order_cnt_lsb: i64,
+    prev_frame_num: i64,
+    prev_frame_num_offset: i64,
+}
+impl PocState {
+    /// Returns the picture order count of the picture (`None` if it is not derived).
+    fn update(
+        &mut self,
+        header: &NalUnit,
+        is_idr: bool,
+        sps: &SpsSummary,
+        slice_header: &SliceHeaderSummary,
+    ) -> Option<i64> {
+        if is_idr {
+            *self = PocState::default();
+        }
+        match sps.pic_order_cnt_type() {
+            0 => {
+                let lsb = slice_header.pic_order_cnt_lsb? as i64;
+                let max_lsb = 1 << (sps.log2_max_pic_order_cnt_lsb_minus4()? + 4);
+                let prev_lsb = self.prev_pic_order_cnt_lsb;
+                let msb = if lsb < prev_lsb && prev_lsb - lsb >= max_lsb / 2 {
+                    self.prev_pic_order_cnt_msb + max_lsb
+                } else if lsb > prev_lsb && lsb - prev_lsb > max_lsb / 2 {
+                    self.prev_pic_order_cnt_msb - max_lsb
+                } else {
+                    self.prev_pic_order_cnt_msb
+                };
+                if header.nal_ref_idc != 0 {
+                    self.prev_pic_order_cnt_msb = msb;
+                    self.prev_pic_order_cnt_lsb = lsb;
+                }
+                let top = msb + lsb;
+                let bottom = top + slice_header.delta_pic_order_cnt_bottom.unwrap_or(0);
+                Some(top.min(bottom))
+            }
+            2 => {
+                let frame_num = slice_header.frame_num? as i64;
+                let frame_num_offset = if is_idr {
+                    0
+                } else if self.prev_frame_num > frame_num {
+                    self.prev_frame_num_offset + sps.max_frame_num() as i64
+                } else {
+                    self.prev_frame_num_offset
+                };
+                self.prev_frame_num = frame_num;
+                self.prev_frame_num_offset = frame_num_offset;
+                Some(if is_idr {
+                    0
+                } else if header.nal_ref_idc == 0 {
+                    2 * (frame_num_offset + frame_num) - 1
+                } else {
+                    2 * (frame_num_offset + frame_num)
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the minimum of the composition time minus the decoding time
+/// over the pictures of the first GOP.
+///
+/// The first GOP consists of the first sync sample and the access units preceding
+/// the next sync sample; the access units before the first sync sample are ignored.
+/// The decoding times are accumulated from the durations of the access units in decoding order,
+/// and the composition times in the order of their picture order counts.
+///
+/// The result is zero or negative when the stream contains reordered pictures.
+/// It is the offset to be applied to the composition times: `-baseline` is the `media_time`
+/// of an edit list entry if the composition offsets (`ctts` version 0) are shifted by `-baseline`,
+/// or the offsets can be signaled as is with `ctts` version 1.
+///
+/// With a single reorder depth (e.g., `I P B P B ...`), the minimum is reached by
+/// the first B picture and equals `-duration`.
+/// Deeper reorder (e.g., hierarchical B pictures) is handled in the same way as long as
+/// the first GOP is representative; a later GOP that reorders more deeply than the first one
+/// yields composition times earlier than the ones of the edit list.
+/// If the picture order counts cannot be derived (`pic_order_cnt_type` `1`, access units
+/// without slices, or slices and parameter sets that cannot be parsed), the pictures are
+/// assumed not to be reordered and `0` is returned.
+pub fn composition_offset_baseline(aus: &[AuInfo]) -> i64 {
+    let first = match aus.iter().position(|au| au.is_sync) {
+        Some(first) => first,
+        None => return 0,
+    };
+    let end = aus[first + 1..]
+        .iter()
+        .position(|au| au.is_sync)
+        .map_or(aus.len(), |i| first + 1 + i);
+    let gop = &aus[first..end];
+
+    let mut poc_decoder = PocDecoder::default();
+    for au in &aus[..first] {
+        for nal_unit in &au.nal_units {
+            // Malformed parameter sets are ignored since the GOPs may not refer to them.
+            let _ = poc_decoder.parameter_sets.ingest(nal_unit);
+        }
+    }
+    let mut pictures = Vec::with_capacity(gop.len());
+    let mut decoding_time = 0;
+    for au in gop {
+        match poc_decoder.decode(&au.nal_units) {
+            Ok(Some(poc)) => pictures.push((poc, decoding_time, au.duration as i64)),
+            _ => return 0,
+        }
+        decoding_time += au.duration as i64;
+    }
+
+    pictures.sort_by_key(|x| x.0);
+    let mut baseline = 0;
+    let mut composition_time = 0;
+    for (_, decoding_time, duration) in pictures {
+        baseline = cmp::min(baseline, composition_time - decoding_time);
+        composition_time += duration;
+    }
+    baseline
+}
+
+/// Returns the composition time offset (i.e., `PTS - DTS`) of each of the given access units.
+///
+/// Within each GOP (from a sync access unit to the next one), the pictures are presented
+/// in the order of their picture order counts, each lasting its `duration`, and the first
+/// presented picture starts at the decoding time of the sync access unit.
+/// Hence the offsets of reordered pictures may be negative (e.g., `-duration` for
+/// the first B picture of `I P B`), which `trun` version 1 can signal as is.
+/// The offsets of a GOP whose picture order counts cannot be derived (`pic_order_cnt_type` `1`,
+/// or access units without slices), and of the access units preceding the first sync
+/// access unit, are `0`.
+pub fn composition_time_offsets(aus: &[AuInfo]) -> Result<Vec<i64>> {
+    let mut offsets = vec![0; aus.len()];
+    let mut start = match aus.iter().position(|au| au.is_sync) {
+        Some(first) => first,
+        None => return Ok(offsets),
+    };
+
+    let mut poc_decoder = PocDecoder::default();
+    for au in &aus[..start] {
+        for nal_unit in &au.nal_units {
+            // Malformed parameter sets are ignored since the GOPs may not refer to them.
+            let _ = poc_decoder.parameter_sets.ingest(nal_unit);
+        }
+    }
+    while start < aus.len() {
+        let end = aus[start + 1..]
+            .iter()
+            .position(|au| au.is_sync)
+            .map_or(aus.len(), |i| start + 1 + i);
+
+        let mut pictures = Vec::with_capacity(end - start);
+        let mut decoding_time = 0;
+        for (i, au) in aus.iter().enumerate().take(end).skip(start) {
+            if let Some(poc) = track!(poc_decoder.decode(&au.nal_units))? {
+                pictures.push((poc, i, decoding_time, au.duration as i64));
+            }
+            decoding_time += au.duration as i64;
+        }
+        if pictures.len() == end - start {
+            pictures.sort_by_key(|x| x.0);
+            let mut composition_time = 0;
+            for (_, i, decoding_time, duration) in pictures {
+                offsets[i] = composition_time - decoding_time;
+                composition_time += duration;
+            }
+        }
+        start = end;
+    }
+    Ok(offsets)
+}
+
+/// GOP structure estimated by `estimate_gop()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GopInfo {
+    /// The number of scanned access units.
+    pub access_units: usize,
+
+    /// The number of access units from the first keyframe to the second one
+    /// (`None` if fewer than two keyframes are found).
+    ///
+    /// A keyframe is an access unit containing an IDR picture or a `recovery_point` SEI message.
+    pub keyframe_interval: Option<usize>,
+
+    /// The picture types (`I`, `P` or `B`) in output order,
+    /// from the first keyframe up to the next non-B picture (e.g., `"IBBP"`).
+    ///
+    /// This is empty if no keyframe is found.
+    pub pattern: String,
+
+    /// The maximum number of consecutive B pictures in decoding order.
+    pub max_consecutive_b_frames: usize,
+
+    /// Whether a keyframe is followed (in decoding order) by leading pictures,
+    /// i.e., pictures that precede the keyframe in output order.
+    pub is_open_gop: bool,
+}
+
+/// Estimates the GOP structure of the given H.264 Annex B stream
+/// by scanning up to `max_aus` access units.
+///
+/// The type of a picture is the `slice_type` of its first slice
+/// (SP and SI slices are regarded as P and I respectively).
+/// The output order is derived from the picture order counts;
+/// if they cannot be derived (`pic_order_cnt_type` `1`), the decoding order is used instead
+/// and leading pictures are not detected.
+pub fn estimate_gop(bytes: &[u8], max_aus: usize) -> Result<GopInfo> {
+    let mut poc_decoder = PocDecoder::default();
+    let mut access_units = 0;
+    let mut pictures = Vec::new();
+    let mut keyframes = Vec::new();
+    let mut keyframe_poc = None;
+    let mut is_open_gop = false;
+    let mut max_consecutive_b_frames = 0;
+    let mut consecutive_b_frames = 0;
+    for access_unit in track!(AccessUnits::new(bytes))?.take(max_aus) {
+        access_units += 1;
+        let poc = track!(poc_decoder.decode(&access_unit))?;
+
+        let mut slice_type = None;
+        let mut is_keyframe = false;
+        for nal_unit in &access_unit {
+            let nal_unit_type = match nal_unit.first() {
+                Some(&b) => nal_unit_type_of(b).ok(),
+                None => continue,
+            };
+            match nal_unit_type {
+                Some(NalUnitType::CodedSliceOfAnIdrPicture)
+                | Some(NalUnitType::CodedSliceOfANonIdrPicture) => {
+                    is_keyframe |= nal_unit_type == Some(NalUnitType::CodedSliceOfAnIdrPicture);
+                    if slice_type.is_none() {
+                        let header = track!(SliceHeaderSummary::read_from(&nal_unit[1..]))?;
+                        slice_type = Some(header.slice_type);
+                    }
+                }
+                Some(NalUnitType::SupplementalEnhancementInformation) => {
+                    is_keyframe |= has_recovery_point(&nal_unit[1..]);
+                }
+                _ => {}
+            }
+        }
+        let picture_type = match slice_type {
+            Some(SliceType::I) | Some(SliceType::Si) => 'I',
+            Some(SliceType::P) | Some(SliceType::Sp) => 'P',
+            Some(SliceType::B) => 'B',
+            None => continue,
+        };
+
+        if picture_type == 'B' {
+            consecutive_b_frames += 1;
+            max_consecutive_b_frames = cmp::max(max_consecutive_b_frames, consecutive_b_frames);
+        } else {
+            consecutive_b_frames = 0;
+        }
+        if is_keyframe {
+            keyframes.push((access_units - 1, pictures.len()));
+            keyframe_poc = poc;
+        } else if let (Some(poc), Some(keyframe_poc)) = (poc, keyframe_poc) {
+            is_open_gop |= poc < keyframe_poc;
+        }
+        pictures.push((poc, picture_type));
+    }
+
+    let mut pattern = String::new();
+    if let Some(&(_, first)) = keyframes.first() {
+        let end = keyframes.get(1).map_or(pictures.len(), |x| x.1);
+        let mut gop = pictures[first..end].to_vec();
+        if gop.iter().all(|x| x.0.is_some()) {
+            gop.sort_by_key(|x| x.0);
+        }
+        for (i, &(_, picture_type)) in gop.iter().enumerate() {
+            pattern.push(picture_type);
+            if i > 0 && picture_type != 'B' {
+                break;
+            }
+        }
+    }
+    Ok(GopInfo {
+        access_units,
+        keyframe_interval: keyframes.get(1).map(|x| x.0 - keyframes[0].0),
+        pattern,
+        max_consecutive_b_frames,
+        is_open_gop,
+    })
+}
+
+/// Returns the indices of the access units where fragments should start.
+///
+/// Every fragment starts on a sync sample, and each boundary is placed on the sync sample
+/// whose start time is the nearest to `target_duration` after the start of the fragment.
+/// If sync samples are sparse, fragments may be longer than `target_duration`.
+/// The last fragment covers the remaining access units, so it may be shorter.
+///
+/// The access units preceding the first sync sample are not covered by any fragment,
+/// and an empty list is returned if there is no sync sample.
+pub fn plan_fragment_boundaries(aus: &[AuInfo], target_duration: u64) -> Vec<usize> {
+    let mut start_times = Vec::with_capacity(aus.len());
+    let mut time = 0;
+    for au in aus {
+        start_times.push(time);
+        time += au.duration;
+    }
+
+    let mut boundaries = Vec::new();
+    let first = match aus.iter().position(|au| au.is_sync) {
+        Some(first) => first,
+        None => return boundaries,
+    };
+    boundaries.push(first);
+
+    // The last sync sample reached before `target_duration` elapses.
+    let mut candidate = None;
+    for (i, au) in aus.iter().enumerate().skip(first + 1) {
+        if !au.is_sync {
+            continue;
+        }
+        let start = boundaries[boundaries.len() - 1];
+        let elapsed = start_times[i] - start_times[start];
+        if elapsed < target_duration {
+            candidate = Some(i);
+            continue;
+        }
+
+        let cut = match candidate.take() {
+            Some(c) if target_duration - (start_times[c] - start_times[start])
+                < elapsed - target_duration =>
+            {
+                c
+            }
+            _ => i,
+        };
+        boundaries.push(cut);
+        if cut != i {
+            if start_times[i] - start_times[cut] < target_duration {
+                candidate = Some(i);
+            } else {
+                boundaries.push(i);
+            }
+        }
+    }
+
+    // The end of the stream is compared like the next sync sample.
+    if let Some(c) = candidate {
+        let start = boundaries[boundaries.len() - 1];
+        let elapsed = time - start_times[start];
+        if elapsed >= target_duration
+            && target_duration - (start_times[c] - start_times[start]) < elapsed - target_duration
+        {
+            boundaries.push(c);
+        }
+    }
+    boundaries
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// An Annex B stream of an IDR, a P and a B picture (Main profile, 1280x720).
+    pub(crate) const IBP_STREAM: [u8; 46] = [
+        0, 0, 0, 1, 0x67, 0x4d, 0x40, 0x1f, 0xec, 0x80, 0x28, 0x02, 0xdc, 0x80, // SPS
+        0, 0, 0, 1, 0x68, 0xee, 0x3c, 0x80, // PPS
+        0, 0, 0, 1, 0x65, 0x88, 0x84, 0x08, // IDR (pic_order_cnt_lsb=0)
+        0, 0, 0, 1, 0x41, 0x9a, 0x22, 0x40, // P (pic_order_cnt_lsb=4)
+        0, 0, 0, 1, 0x01, 0x9e, 0x41, 0x40, // B (pic_order_cnt_lsb=2)
+    ];
+
+    fn aus(sync_flags: &[bool]) -> Vec<AuInfo> {
+        sync_flags
+            .iter()
+            .map(|&is_sync| AuInfo {
+                nal_units: Vec::new(),
+                is_sync,
+                duration: 1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_analyze_access_units() {
+        let bytes = [
+            0, 0, 0, 1, 0x67, 0x42, 0xe0, 0x1e, 0xda, 0x02, 0xd0, 0xf6, 0xff, 0xc0, 0x02, 0x00,
+            0x02, 0x44, 0x00, 0x00, 0x2e, 0xec, 0x00, 0x0a, 0xfc, 0x82, 0x10, // SPS
+            0, 0, 0, 1, 0x68, 0xce, 0x33, 0xc8, // PPS
+            0, 0, 1, 0x65, 0b1000_1000, 0b1000_0000, // IDR
+            0, 0, 1, 0x41, 0b1001_1010, // non-IDR
+        ];
+        let aus = analyze_access_units(&bytes).unwrap();
+        assert_eq!(aus.len(), 2);
+        assert!(aus[0].is_sync);
+        assert_eq!(aus[0].nal_units.len(), 3);
+        assert_eq!(aus[0].size(), 23 + 4 + 3);
+        assert!(!aus[1].is_sync);
+        assert_eq!(aus[1].duration, 6006);
+    }
+
+    #[test]
+    fn test_timed_nal_stream() {
+        let pts = [0, 2000, 1000];
+        let items = TimedNalStream::new(&IBP_STREAM, |i| (i as u64 * 1000, pts[i]))
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(items[0].0.is_sync);
+        assert_eq!((items[2].1, items[2].2), (2000, 1000));
+
+        // The PTSs follow the decoding order although the B picture is reordered.
+        let mut stream = TimedNalStream::new(&IBP_STREAM, |i| (i as u64, i as u64)).unwrap();
+        assert!(stream.next().unwrap().is_ok());
+        assert!(stream.next().unwrap().is_ok());
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn test_composition_offset_baseline() {
+        let mut aus = analyze_access_units(&IBP_STREAM).unwrap();
+        for au in &mut aus {
+            au.duration = 1000;
+        }
+        assert_eq!(composition_offset_baseline(&aus), -1000);
+
+        // Without the B picture
+        assert_eq!(composition_offset_baseline(&aus[..2]), 0);
+        assert_eq!(composition_offset_baseline(&aus[1..]), 0);
+    }
+
+    #[test]
+    fn test_composition_time_offsets() {
+        let mut aus = analyze_access_units(&IBP_STREAM).unwrap();
+        for au in &mut aus {
+            au.duration = 1000;
+        }
+        assert_eq!(composition_time_offsets(&aus).unwrap(), [0, 1000, -1000]);
+        assert_eq!(composition_time_offsets(&aus[1..]).unwrap(), [0, 0]);
+    }
+
+    #[test]
+    fn test_estimate_gop() {
+        let gop = estimate_gop(&IBP_STREAM, 100).unwrap();
+        assert_eq!(
+            gop,
+            GopInfo {
+                access_units: 3,
+                keyframe_interval: None,
+                pattern: "IBP".to_owned(),
+                max_consecutive_b_frames: 1,
+                is_open_gop: false,
+            }
+        );
+
+        let mut two_gops = IBP_STREAM.to_vec();
+        two_gops.extend_from_slice(&[0, 0, 0, 1, 0x65, 0x88, 0x84, 0x08]);
+        let gop = estimate_gop(&two_gops, 100).unwrap();
+        assert_eq!((gop.access_units, gop.keyframe_interval), (4, Some(3)));
+
+        let gop = estimate_gop(&IBP_STREAM, 2).unwrap();
+        assert_eq!(gop.access_units, 2);
+        assert_eq!(gop.pattern, "IP");
+        assert_eq!(gop.max_consecutive_b_frames, 0);
+    }
+
+    #[test]
+    fn test_poc_type2() {
+        // Baseline profile, pic_order_cnt_type=2, log2_max_frame_num_minus4=0
+        let sps = [
+            0x42, 0xe0, 0x1e, 0xda, 0x02, 0xd0, 0xf6, 0xff, 0xc0, 0x02, 0x00, 0x02, 0x44, 0x00,
+            0x00, 0x2e, 0xec, 0x00, 0x0a, 0xfc, 0x82, 0x10,
+        ];
+        let sps = SpsSummary::read_from(&sps[..]).unwrap();
+        assert_eq!(sps.pic_order_cnt_type(), 2);
+
+        let mut state = PocState::default();
+        let mut poc = |nal_ref_idc, nal_unit_type, frame_num| {
+            let header = NalUnit {
+                nal_ref_idc,
+                nal_unit_type,
+            };
+            let slice_header = SliceHeaderSummary {
+                first_mb_in_slice: 0,
+                slice_type: SliceType::P,
+                pic_parameter_set_id: 0,
+                frame_num: Some(frame_num),
+                field_pic_flag: false,
+                bottom_field_flag: false,
+                idr_pic_id: None,
+                pic_order_cnt_lsb: None,
+                delta_pic_order_cnt_bottom: None,
+                delta_pic_order_cnt: [None; 2],
+            };
+            let is_idr = nal_unit_type == NalUnitType::CodedSliceOfAnIdrPicture;
+            state.update(&header, is_idr, &sps, &slice_header).unwrap()
+        };
+        let idr = NalUnitType::CodedSliceOfAnIdrPicture;
+        let non_idr = NalUnitType::CodedSliceOfANonIdrPicture;
+        assert_eq!(poc(3, idr, 0), 0);
+        assert_eq!(poc(2, non_idr, 1), 2);
+        assert_eq!(poc(0, non_idr, 2), 3);
+        assert_eq!(poc(2, non_idr, 2), 4);
+        assert_eq!(poc(2, non_idr, 15), 30);
+
+        // `frame_num` wraps around
+        assert_eq!(poc(2, non_idr, 0), 32);
+        assert_eq!(poc(3, idr, 0), 0);
+    }
+
+    #[test]
+    fn test_plan_fragment_boundaries() {
+        let mut flags = [false; 10];
+        for i in (0..10).step_by(3) {
+            flags[i] = true;
+        }
+        assert_eq!(plan_fragment_boundaries(&aus(&flags), 4), [0, 3, 6]);
+        assert_eq!(plan_fragment_boundaries(&aus(&flags), 6), [0, 6]);
+
+        // Sparse sync samples
+        let mut flags = [false; 10];
+        flags[1] = true;
+        flags[9] = true;
+        assert_eq!(plan_fragment_boundaries(&aus(&flags), 2), [1, 9]);
+
+        // A sparse tail following the last sync sample
+        let mut flags = [false; 100];
+        flags[0] = true;
+        flags[3] = true;
+        assert_eq!(plan_fragment_boundaries(&aus(&flags), 4), [0, 3]);
+        assert_eq!(plan_fragment_boundaries(&aus(&flags[..5]), 4), [0]);
+
+        assert!(plan_fragment_boundaries(&aus(&[false, false]), 2).is_empty());
+    }
+
+    #[test]
+    fn test_au_length_prefixed_size() {
+        // An access unit with an AUD and two slices
+        let au: [&[u8]; 3] = [&[0x09, 0xf0], &[0x65, 0x88, 0x84, 0x00], &[0x65, 0x88, 0x80]];
+        let mut sample = Vec::new();
+        for nal_unit in &au {
+            LengthSize::FOUR.write_nal_unit(&mut sample, nal_unit).unwrap();
+        }
+        assert_eq!(au_length_prefixed_size(&au, LengthSize::FOUR), sample.len());
+        assert_eq!(au_length_prefixed_size(&au, LengthSize::FOUR), 21);
+        assert_eq!(au_length_prefixed_size(&au, LengthSize::new(2).unwrap()), 15);
+
+        // Three-byte prefixes cannot be requested
+        let e = LengthSize::new(3).unwrap_err();
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+        let au_info = AuInfo {
+            nal_units: au.iter().map(|nal_unit| nal_unit.to_vec()).collect(),
+            is_sync: true,
+            duration: 1,
+        };
+        assert_eq!(au_length_prefixed_size(&au_info.nal_units, LengthSize::FOUR), 21);
+        assert_eq!(au_length_prefixed_size::<&[u8]>(&[], LengthSize::FOUR), 0);
+    }
+}
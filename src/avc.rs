@@ -1,88 +1,628 @@
 //! AVC (H.264) related constituent elements.
+use crate::codec::{serializes_identically, Codec, LengthSize, ParseOptions, SampleEntryMode};
 use crate::extended_configuration_data::{self, ExtendedConfigurationData};
-use crate::io::{AvcBitReader, AvcBitWriter};
+use crate::fmp4::{AvcConfigurationBox, Mp4Box, PixelAspectRatioBox};
+use crate::io::{escape_rbsp, AvcBitReader, AvcBitWriter, RbspReader};
+use crate::sei::SeiMessages;
 use crate::{ErrorKind, Result};
-use byteorder::ReadBytesExt;
-use core::panic;
+use byteorder::{BigEndian, ReadBytesExt};
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt::Write as _;
 use std::io::{Read, Write};
+use std::ops::Range;
 
 /// AVC decoder configuration record.
 #[allow(missing_docs)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AvcDecoderConfigurationRecord {
     pub profile_idc: u8,
     pub constraint_set_flag: u8,
     pub level_idc: u8,
+
+    /// The size of the NAL unit length field of each sample.
+    pub length_size: LengthSize,
+
     pub sequence_parameter_set: Vec<u8>,
+
+    /// PPS NAL unit (empty if the record has no PPS, e.g., when it is supplied in-band).
     pub picture_parameter_set: Vec<u8>,
     pub extended_configuration_data: Option<ExtendedConfigurationData>,
 }
 impl AvcDecoderConfigurationRecord {
+    /// Returns the codec that this record targets.
+    pub fn codec(&self) -> Codec {
+        Codec::H264
+    }
+
+    /// Returns the codec string of RFC 6381 for an `avc1` sample entry (e.g., `avc1.4d401f`).
+    ///
+    /// The string consists of `avc1` followed by `profile_idc`, `constraint_set_flag` and
+    /// `level_idc` as two hexadecimal digits each.
+    /// `constraint_set_flag` is used verbatim; players may pick a wrong decoder if it is zeroed.
+    pub fn codec_string(&self) -> String {
+        self.codec_string_for_mode(SampleEntryMode::OutOfBand)
+    }
+
+    /// Returns the codec string of RFC 6381 for the sample entry type selected by `mode`.
+    ///
+    /// This is the same as `codec_string()` except that the string begins with `avc3`
+    /// if `mode` is `SampleEntryMode::InBand`.
+    pub fn codec_string_for_mode(&self, mode: SampleEntryMode) -> String {
+        let fourcc = self.codec().sample_entry_fourcc(mode);
+        format!(
+            "{}.{:02x}{:02x}{:02x}",
+            String::from_utf8_lossy(&fourcc),
+            self.profile_idc,
+            self.constraint_set_flag,
+            self.level_idc
+        )
+    }
+
+    /// Makes a record from the given SPS and PPS NAL units (including their NAL unit headers).
+    pub fn from_parameter_sets(sps: &[u8], pps: &[u8]) -> Result<Self> {
+        track_assert!(!sps.is_empty(), ErrorKind::InvalidInput);
+        let sps_summary = track!(SpsSummary::read_from(&sps[1..]))?;
+        if !pps.is_empty() {
+            let pps_summary = track!(PpsSummary::read_from(&pps[1..]))?;
+            track!(validate_pps_references_sps(&pps_summary, &sps_summary))?;
+        }
+        Ok(AvcDecoderConfigurationRecord {
+            profile_idc: sps_summary.profile_idc,
+            constraint_set_flag: sps_summary.constraint_set_flag,
+            level_idc: sps_summary.level_idc,
+            length_size: LengthSize::FOUR,
+            sequence_parameter_set: sps.to_owned(),
+            picture_parameter_set: pps.to_owned(),
+            extended_configuration_data: sps_summary.extended_configuration_data,
+        })
+    }
+
+    /// Replaces the parameter sets of this record with the given SPS and PPS NAL units
+    /// (including their NAL unit headers).
+    ///
+    /// `profile_idc`, `constraint_set_flag`, `level_idc` and `extended_configuration_data`
+    /// are re-derived from the new SPS. `length_size` is kept.
+    pub fn with_parameter_sets(self, sps: Vec<u8>, pps: Vec<u8>) -> Result<Self> {
+        for (nal_unit, nal_unit_type) in &[
+            (&sps, NalUnitType::SequenceParameterSet),
+            (&pps, NalUnitType::PictureParameterSet),
+        ] {
+            let b = track_assert_some!(nal_unit.first(), ErrorKind::InvalidInput);
+            track_assert_eq!(
+                track!(nal_unit_type_of(*b))?,
+                *nal_unit_type,
+                ErrorKind::InvalidInput
+            );
+        }
+        let record = track!(Self::from_parameter_sets(&sps, &pps))?;
+        Ok(AvcDecoderConfigurationRecord {
+            length_size: self.length_size,
+            ..record
+        })
+    }
+
+    /// Decodes `constraint_set_flag`.
+    pub fn constraint_flags(&self) -> Result<ConstraintFlags> {
+        track!(ConstraintFlags::from_u8(self.constraint_set_flag))
+    }
+
+    /// Sets `constraint_set_flag` of this record.
+    pub fn set_constraint_flags(&mut self, flags: ConstraintFlags) {
+        self.constraint_set_flag = flags.as_u8();
+    }
+
+    /// Returns `true` if this record and `other` differ only in their levels.
+    ///
+    /// `profile_idc`, `constraint_set_flag`, `length_size`, `picture_parameter_set` and
+    /// `extended_configuration_data` are compared as is.
+    /// `sequence_parameter_set` is compared except for its `level_idc` byte
+    /// (the fourth byte, following the NAL unit header, `profile_idc` and `constraint_set_flag`).
+    /// `level_idc` is not compared.
+    pub fn differs_only_in_level(&self, other: &Self) -> bool {
+        fn without_level(sps: &[u8]) -> (&[u8], &[u8]) {
+            if sps.len() < 4 {
+                (sps, &[])
+            } else {
+                (&sps[..3], &sps[4..])
+            }
+        }
+
+        self.profile_idc == other.profile_idc
+            && self.constraint_set_flag == other.constraint_set_flag
+            && self.length_size == other.length_size
+            && without_level(&self.sequence_parameter_set)
+                == without_level(&other.sequence_parameter_set)
+            && self.picture_parameter_set == other.picture_parameter_set
+            && self.extended_configuration_data == other.extended_configuration_data
+    }
+
+    /// Returns `true` if switching from this record to `next` requires
+    /// a `SourceBuffer.changeType()` call in Media Source Extensions.
+    ///
+    /// Level changes and changes in the SPS/PPS that keep the decoding capabilities
+    /// (e.g., a resolution change) can be absorbed by appending a new initialization segment.
+    /// On the other hand, changes in `profile_idc`, `constraint_set_flag`,
+    /// `length_size` or `extended_configuration_data`
+    /// (the chroma format and the bit depths) require `changeType()`.
+    pub fn requires_change_type(&self, next: &AvcDecoderConfigurationRecord) -> bool {
+        if self == next || self.differs_only_in_level(next) {
+            return false;
+        }
+        let formats = |record: &Self| {
+            record.extended_configuration_data.as_ref().map(|x| {
+                (x.chroma_format, x.bit_depth_luma_minus_8, x.bit_depth_chroma_minus_8)
+            })
+        };
+        self.profile_idc != next.profile_idc
+            || self.constraint_set_flag != next.constraint_set_flag
+            || self.length_size != next.length_size
+            || formats(self) != formats(next)
+    }
+
+    /// Returns `true` if the `avcC` payloads of this record and `other` are byte-identical,
+    /// so that their segments can share one initialization segment.
+    ///
+    /// Unlike `requires_change_type()`, a level or SPS/PPS change makes this `false`,
+    /// as does a record that cannot be serialized.
+    pub fn is_append_compatible(&self, other: &Self) -> bool {
+        serializes_identically(self, other, |record, bytes| record.write_to(bytes))
+    }
+
+    /// Decides what to emit when the parameter sets change from this record to `next`
+    /// in out-of-band (`avc1`) packaging.
+    ///
+    /// `None` is returned if the records are identical (no new sample entry is needed).
+    /// Otherwise, the `avcC` box of `next` is returned for the new sample entry,
+    /// along with the result of `requires_change_type()`.
+    pub fn sample_entry_update(
+        &self,
+        next: &AvcDecoderConfigurationRecord,
+    ) -> Result<Option<SampleEntryUpdate>> {
+        if self == next {
+            return Ok(None);
+        }
+        let avcc_box = AvcConfigurationBox {
+            configuration: next.clone(),
+        };
+        let mut buf = Vec::new();
+        write_box!(buf, avcc_box);
+        Ok(Some(SampleEntryUpdate {
+            avcc_box: buf,
+            requires_change_type: self.requires_change_type(next),
+        }))
+    }
+
+    /// Returns the larger of the luma and chroma bit depths.
+    ///
+    /// The profiles without `extended_configuration_data` are always 8-bit.
+    pub fn max_bit_depth(&self) -> u8 {
+        self.extended_configuration_data
+            .as_ref()
+            .map_or(8, |x| x.max_bit_depth())
+    }
+
+    /// Returns the SPS and PPS of this record in Annex B format,
+    /// each prefixed with a four bytes start code.
+    ///
+    /// An empty PPS is omitted.
+    pub fn parameter_sets_annexb(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            8 + self.sequence_parameter_set.len() + self.picture_parameter_set.len(),
+        );
+        for nal_unit in &[&self.sequence_parameter_set, &self.picture_parameter_set] {
+            if nal_unit.is_empty() {
+                continue;
+            }
+            bytes.extend_from_slice(&[0, 0, 0, 1]);
+            bytes.extend_from_slice(nal_unit);
+        }
+        bytes
+    }
+
+    /// Sets `level_idc` of both this record and the embedded SPS.
+    pub fn set_level(&mut self, level_idc: u8) -> Result<()> {
+        // NAL unit header, profile_idc, constraint_set_flag and level_idc
+        track_assert!(
+            self.sequence_parameter_set.len() >= 4,
+            ErrorKind::InvalidInput,
+            "SPS is too short: {} bytes",
+            self.sequence_parameter_set.len()
+        );
+        track_assert!(
+            self.sequence_parameter_set[1..3] != [0, 0],
+            ErrorKind::Unsupported,
+            "SPS level_idc may be preceded by an emulation prevention byte"
+        );
+        self.sequence_parameter_set[3] = level_idc;
+        self.level_idc = level_idc;
+        Ok(())
+    }
+
+    /// Returns the size of the NAL unit length field of each sample in bytes.
+    pub fn length_size(&self) -> usize {
+        self.length_size.get()
+    }
+
+    /// Reads a record from the payload of an `avcC` box.
+    ///
+    /// Only the first SPS and PPS are kept if the record contains multiple parameter sets.
+    ///
+    /// Some muxers omit the trailer of High profile records (`chroma_format` and the following
+    /// fields). If the record ends right after the PPS array, `extended_configuration_data`
+    /// is left as `None`, and `write_to()` omits the trailer likewise.
+    ///
+    /// If the declared number of parameter sets do not fit in the record,
+    /// an `ErrorKind::InvalidInput` error is returned.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        track!(Self::read_from_with_options(reader, &ParseOptions::default()))
+    }
+
+    /// Reads a record from the payload of an `avcC` box with the given options.
+    ///
+    /// Every parameter set in the record is checked against `max_nal_size`.
+    /// In the strict mode, the following are also rejected:
+    /// - reserved bits not set to `1`
+    /// - entries of the SPS (or PPS) array that are not SPS (or PPS) NAL units
+    /// - bytes following the PPS array of a record without the trailer
+    pub fn read_from_with_options<R: Read>(mut reader: R, options: &ParseOptions) -> Result<Self> {
+        let mut payload = Vec::new();
+        track_io!(reader.read_to_end(&mut payload))?;
+        let mut reader = &payload[..];
+        track_assert!(reader.len() >= 6, ErrorKind::InvalidInput, "Too short record");
+
+        let configuration_version = track_io!(reader.read_u8())?;
+        track_assert_eq!(configuration_version, 1, ErrorKind::Unsupported);
+        let profile_idc = track_io!(reader.read_u8())?;
+        let constraint_set_flag = track_io!(reader.read_u8())?;
+        let level_idc = track_io!(reader.read_u8())?;
+        let b = track_io!(reader.read_u8())?;
+        track_assert!(
+            !options.strict || b & 0b1111_1100 == 0b1111_1100,
+            ErrorKind::InvalidInput,
+            "Reserved bits preceding lengthSizeMinusOne must be set"
+        );
+        let length_size = track!(LengthSize::from_length_size_minus_one(b & 0b0000_0011))?;
+
+        let b = track_io!(reader.read_u8())?;
+        track_assert!(
+            !options.strict || b & 0b1110_0000 == 0b1110_0000,
+            ErrorKind::InvalidInput,
+            "Reserved bits preceding numOfSequenceParameterSets must be set"
+        );
+        let num_of_sequence_parameter_sets = b & 0b0001_1111;
+        let sps_list = track!(read_parameter_sets(&mut reader, num_of_sequence_parameter_sets))?;
+        track_assert!(!reader.is_empty(), ErrorKind::InvalidInput, "Missing PPS array");
+        let num_of_picture_parameter_sets = track_io!(reader.read_u8())?;
+        let pps_list = track!(read_parameter_sets(&mut reader, num_of_picture_parameter_sets))?;
+
+        let arrays = [
+            (&sps_list, NalUnitType::SequenceParameterSet),
+            (&pps_list, NalUnitType::PictureParameterSet),
+        ];
+        for &(list, expected_type) in &arrays {
+            for nal_unit in list {
+                track!(options.check_nal_size(nal_unit.len()))?;
+                if options.strict {
+                    let header = track!(NalUnit::read_from_with_options(&nal_unit[..], options))?;
+                    track_assert_eq!(header.nal_unit_type, expected_type, ErrorKind::InvalidInput);
+                }
+            }
+        }
+        let has_trailer = matches!(profile_idc, 100 | 110 | 122 | 144);
+        track_assert!(
+            !options.strict || has_trailer || reader.is_empty(),
+            ErrorKind::InvalidInput,
+            "Trailing garbage: {} bytes",
+            reader.len()
+        );
+
+        let sequence_parameter_set = track_assert_some!(
+            sps_list.into_iter().next(),
+            ErrorKind::InvalidInput,
+            "No SPS"
+        );
+        // Some legacy records have no PPS (it is supplied in-band).
+        let picture_parameter_set = pps_list.into_iter().next().unwrap_or_default();
+
+        let extended_configuration_data = match profile_idc {
+            100 | 110 | 122 | 144 if !reader.is_empty() => Some(track!(
+                ExtendedConfigurationData::read_from_trailer(reader, profile_idc)
+            )?),
+            _ => None,
+        };
+        Ok(AvcDecoderConfigurationRecord {
+            profile_idc,
+            constraint_set_flag,
+            level_idc,
+            length_size,
+            sequence_parameter_set,
+            picture_parameter_set,
+            extended_configuration_data,
+        })
+    }
+
+    /// Returns a borrowed view of this record.
+    pub fn as_record_ref(&self) -> AvcDecoderConfigurationRecordRef<'_> {
+        AvcDecoderConfigurationRecordRef {
+            profile_idc: self.profile_idc,
+            constraint_set_flag: self.constraint_set_flag,
+            level_idc: self.level_idc,
+            length_size: self.length_size,
+            sequence_parameter_set: &self.sequence_parameter_set,
+            picture_parameter_set: &self.picture_parameter_set,
+            extended_configuration_data: self.extended_configuration_data.as_ref(),
+        }
+    }
+
+    /// Writes the record to the given writer.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+        track!(self.as_record_ref().write_to(writer))
+    }
+}
+
+/// AVC decoder configuration record that borrows its parameter sets.
+///
+/// This is the same as `AvcDecoderConfigurationRecord` except that it can be written
+/// without copying the SPS and PPS into owned buffers.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AvcDecoderConfigurationRecordRef<'a> {
+    pub profile_idc: u8,
+    pub constraint_set_flag: u8,
+    pub level_idc: u8,
+    pub length_size: LengthSize,
+    pub sequence_parameter_set: &'a [u8],
+    pub picture_parameter_set: &'a [u8],
+    pub extended_configuration_data: Option<&'a ExtendedConfigurationData>,
+}
+impl<'a> AvcDecoderConfigurationRecordRef<'a> {
+    /// Converts into an `AvcDecoderConfigurationRecord` by copying the borrowed data.
+    pub fn to_owned(&self) -> AvcDecoderConfigurationRecord {
+        AvcDecoderConfigurationRecord {
+            profile_idc: self.profile_idc,
+            constraint_set_flag: self.constraint_set_flag,
+            level_idc: self.level_idc,
+            length_size: self.length_size,
+            sequence_parameter_set: self.sequence_parameter_set.to_owned(),
+            picture_parameter_set: self.picture_parameter_set.to_owned(),
+            extended_configuration_data: self.extended_configuration_data.cloned(),
+        }
+    }
+
+    /// Writes the record to the given writer.
     pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
         write_u8!(writer, 1); // configuration_version
         write_u8!(writer, self.profile_idc);
         write_u8!(writer, self.constraint_set_flag);
         write_u8!(writer, self.level_idc);
-        write_u8!(writer, 0b1111_1100 | 0b0000_0011); // reserved and length_size_minus_one
+        // reserved and length_size_minus_one
+        write_u8!(writer, 0b1111_1100 | self.length_size.length_size_minus_one());
 
         write_u8!(writer, 0b1110_0000 | 0b0000_0001); // reserved and num_of_sequence_parameter_set_ext
         write_u16!(writer, self.sequence_parameter_set.len() as u16);
-        write_all!(writer, &self.sequence_parameter_set);
+        write_all!(writer, self.sequence_parameter_set);
 
-        write_u8!(writer, 0b0000_0001); // num_of_picture_parameter_set_ext
-        write_u16!(writer, self.picture_parameter_set.len() as u16);
-        write_all!(writer, &self.picture_parameter_set);
-
-        match self.profile_idc {
-            100 | 110 | 122 | 144 => {
-                if self.extended_configuration_data.is_none() {
-                    track_panic!(
-                        ErrorKind::Unsupported,
-                        "Profile IDC is {}, but missing extended configuration data",
-                        self.profile_idc
-                    );
-                }
-                let extended_configuration_data =
-                    self.extended_configuration_data.as_ref().unwrap();
-
-                let mut bit_writer = AvcBitWriter::new(writer);
-
-                bit_writer.write_ue(extended_configuration_data.chroma_format)?;
-                if extended_configuration_data.chroma_format == 3 {
-                    let separate_color_plane = extended_configuration_data
-                        .separate_color_plane
-                        .unwrap_or_else(|| {
-                            panic!("Must have optional flag set when chroma format is YUV444")
-                        });
-                        bit_writer.write_bool(separate_color_plane)?;
-                }
+        if self.picture_parameter_set.is_empty() {
+            write_u8!(writer, 0); // num_of_picture_parameter_set_ext
+        } else {
+            write_u8!(writer, 0b0000_0001); // num_of_picture_parameter_set_ext
+            write_u16!(writer, self.picture_parameter_set.len() as u16);
+            write_all!(writer, self.picture_parameter_set);
+        }
 
-                bit_writer.write_ue(extended_configuration_data.bit_depth_luma_minus_8)?;
-                bit_writer.write_ue(extended_configuration_data.bit_depth_chroma_minus_8)?;
-                bit_writer.write_bool(extended_configuration_data.qp_prime_y_zero_transform_bypass)?;
-                bit_writer.write_bool(false)?; //False for scaling matrix
-                bit_writer.flush()?;
+        // A High profile record without `extended_configuration_data` is written without
+        // the trailer, as it was read.
+        if let Some(extended_configuration_data) = self.extended_configuration_data {
+            if matches!(self.profile_idc, 100 | 110 | 122 | 144) {
+                track!(extended_configuration_data.write_trailer_to(writer))?;
             }
-            _ => {}
         }
 
         Ok(())
     }
 }
 
+/// Builder of `AvcDecoderConfigurationRecord`.
+#[derive(Debug, Default, Clone)]
+pub struct AvcDecoderConfigurationRecordBuilder {
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    profile_idc: Option<u8>,
+    level_idc: Option<u8>,
+    extended_configuration_data: Option<ExtendedConfigurationData>,
+}
+impl AvcDecoderConfigurationRecordBuilder {
+    /// Makes a new `AvcDecoderConfigurationRecordBuilder` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the SPS NAL unit (including the NAL unit header).
+    pub fn sps(mut self, sps: Vec<u8>) -> Self {
+        self.sps = Some(sps);
+        self
+    }
+
+    /// Sets the PPS NAL unit (including the NAL unit header).
+    pub fn pps(mut self, pps: Vec<u8>) -> Self {
+        self.pps = Some(pps);
+        self
+    }
+
+    /// Sets `profile_idc`.
+    ///
+    /// If this is not called, the value in the SPS is used.
+    pub fn profile(mut self, profile_idc: u8) -> Self {
+        self.profile_idc = Some(profile_idc);
+        self
+    }
+
+    /// Sets `level_idc`.
+    ///
+    /// If this is not called, the value in the SPS is used.
+    pub fn level(mut self, level_idc: u8) -> Self {
+        self.level_idc = Some(level_idc);
+        self
+    }
+
+    /// Sets the extended configuration data for the High profiles.
+    ///
+    /// If this is not called, the data parsed from the SPS is used.
+    pub fn extended_config(mut self, data: ExtendedConfigurationData) -> Self {
+        self.extended_configuration_data = Some(data);
+        self
+    }
+
+    /// Builds a record after checking the consistency between the given values.
+    ///
+    /// The following are checked:
+    /// - the SPS and the PPS are given, and the PPS refers to the SPS
+    /// - `profile_idc` and `level_idc` match those of the SPS
+    /// - the extended configuration data is present if and only if `profile_idc` is a High profile
+    pub fn build(self) -> Result<AvcDecoderConfigurationRecord> {
+        let sps = track_assert_some!(self.sps, ErrorKind::InvalidInput, "Missing SPS");
+        let pps = track_assert_some!(self.pps, ErrorKind::InvalidInput, "Missing PPS");
+        let mut record = track!(AvcDecoderConfigurationRecord::from_parameter_sets(&sps, &pps))?;
+        if let Some(profile_idc) = self.profile_idc {
+            track_assert_eq!(profile_idc, record.profile_idc, ErrorKind::InvalidInput);
+        }
+        if let Some(level_idc) = self.level_idc {
+            track_assert_eq!(level_idc, record.level_idc, ErrorKind::InvalidInput);
+        }
+        if let Some(data) = self.extended_configuration_data {
+            track!(data.validate())?;
+            record.extended_configuration_data = Some(data);
+        }
+
+        let is_high_profile = matches!(record.profile_idc, 100 | 110 | 122 | 144);
+        track_assert_eq!(
+            record.extended_configuration_data.is_some(),
+            is_high_profile,
+            ErrorKind::InvalidInput,
+            "profile_idc={}",
+            record.profile_idc
+        );
+        Ok(record)
+    }
+}
+
+/// Packed `constraint_set0_flag`..`constraint_set5_flag` and `reserved_zero_2bits`
+/// (7.3.2.1.1, ISO/IEC 14496-10).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConstraintFlags(u8);
+impl ConstraintFlags {
+    const RESERVED_ZERO_2BITS: u8 = 0b0000_0011;
+
+    /// Makes an instance with all flags unset.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the packed byte (e.g., `AvcDecoderConfigurationRecord::constraint_set_flag`).
+    pub fn from_u8(b: u8) -> Result<Self> {
+        track_assert_eq!(
+            b & Self::RESERVED_ZERO_2BITS,
+            0,
+            ErrorKind::InvalidInput,
+            "reserved_zero_2bits must be zero"
+        );
+        Ok(ConstraintFlags(b))
+    }
+
+    /// Returns the packed byte.
+    pub fn as_u8(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the value of `constraint_set<n>_flag`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is greater than `5`.
+    pub fn constraint_set_flag(self, n: usize) -> bool {
+        (self.0 & Self::mask(n)) != 0
+    }
+
+    /// Sets the value of `constraint_set<n>_flag`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is greater than `5`.
+    pub fn set_constraint_set_flag(&mut self, n: usize, value: bool) -> &mut Self {
+        if value {
+            self.0 |= Self::mask(n);
+        } else {
+            self.0 &= !Self::mask(n);
+        }
+        self
+    }
+
+    /// Returns `true` if `constraint_set1_flag` is set
+    /// (i.e., the stream is Constrained Baseline if `profile_idc` is `66`).
+    pub fn is_constrained_baseline(self) -> bool {
+        self.constraint_set_flag(1)
+    }
+
+    /// Sets `constraint_set1_flag` that indicates the Constrained Baseline profile
+    /// if `profile_idc` is `66`.
+    pub fn set_constrained_baseline(&mut self, value: bool) -> &mut Self {
+        self.set_constraint_set_flag(1, value)
+    }
+
+    /// Returns `true` if `constraint_set3_flag` is set
+    /// (i.e., the level is 1b if `profile_idc` is `66`, `77` or `88` and `level_idc` is `11`).
+    pub fn is_level_1b(self) -> bool {
+        self.constraint_set_flag(3)
+    }
+
+    /// Sets `constraint_set3_flag` that indicates the level 1b
+    /// if `profile_idc` is `66`, `77` or `88` and `level_idc` is `11`.
+    pub fn set_level_1b(&mut self, value: bool) -> &mut Self {
+        self.set_constraint_set_flag(3, value)
+    }
+
+    fn mask(n: usize) -> u8 {
+        assert!(n <= 5, "constraint_set{}_flag does not exist", n);
+        0b1000_0000 >> n
+    }
+}
+
 #[derive(Debug)]
 pub struct SpsSummary {
     pub profile_idc: u8,
     pub constraint_set_flag: u8,
     pub level_idc: u8,
+
+    /// The id referred to by `PpsSummary::seq_parameter_set_id`.
+    pub seq_parameter_set_id: u64,
+
+    /// `frame_num` of the slice headers is coded in `log2_max_frame_num_minus4 + 4` bits.
+    pub log2_max_frame_num_minus4: u64,
+    pic_order_cnt: PicOrderCnt,
+
+    /// The maximum number of reference frames (`num_ref_frames` in older editions of the spec).
+    pub max_num_ref_frames: u64,
+
+    /// Whether gaps in `frame_num` are allowed.
+    pub gaps_in_frame_num_value_allowed_flag: bool,
     pic_width_in_mbs_minus_1: u64,
     pic_height_in_map_units_minus_1: u64,
     frame_mbs_only_flag: u8,
+    mb_adaptive_frame_field_flag: bool,
+    direct_8x8_inference_flag: bool,
+    frame_cropping_flag: bool,
     frame_crop_left_offset: u64,
     frame_crop_right_offset: u64,
     frame_crop_top_offset: u64,
     frame_crop_bottom_offset: u64,
     pub extended_configuration_data: Option<ExtendedConfigurationData>,
+
+    /// VUI parameters (`None` if `vui_parameters_present_flag` is not set).
+    pub vui_parameters: Option<VuiParameters>,
+
+    /// The original RBSP retained by `SpsSummary::from_nal_unit`.
+    pub preserved_rbsp: Option<PreservedRbsp>,
 }
 impl SpsSummary {
     pub fn width(&self) -> usize {
@@ -98,80 +638,449 @@ impl SpsSummary {
             - (self.frame_crop_top_offset as usize * 2)
     }
 
-    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
-        let profile_idc = track_io!(reader.read_u8())?;
-        let constraint_set_flag = track_io!(reader.read_u8())?;
-        let level_idc = track_io!(reader.read_u8())?;
+    /// Returns the bit width of `frame_num` in the slice headers.
+    pub fn frame_num_bits(&self) -> usize {
+        self.log2_max_frame_num_minus4 as usize + 4
+    }
 
-        let mut reader = AvcBitReader::new(reader);
-        let _seq_parameter_set_id = track!(reader.read_ue())?;
+    /// Returns `MaxFrameNum`.
+    pub fn max_frame_num(&self) -> u64 {
+        1 << self.frame_num_bits()
+    }
+
+    /// Returns `pic_order_cnt_type`.
+    pub fn pic_order_cnt_type(&self) -> u64 {
+        match self.pic_order_cnt {
+            PicOrderCnt::Type0 { .. } => 0,
+            PicOrderCnt::Type1 { .. } => 1,
+            PicOrderCnt::Type2 => 2,
+        }
+    }
+
+    /// Returns `log2_max_pic_order_cnt_lsb_minus4` (`None` if `pic_order_cnt_type` is not `0`).
+    ///
+    /// `pic_order_cnt_lsb` in slice headers occupies `log2_max_pic_order_cnt_lsb_minus4 + 4` bits.
+    pub fn log2_max_pic_order_cnt_lsb_minus4(&self) -> Option<u64> {
+        if let PicOrderCnt::Type0 {
+            log2_max_pic_order_cnt_lsb_minus4,
+        } = self.pic_order_cnt
+        {
+            Some(log2_max_pic_order_cnt_lsb_minus4)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `vui_parameters_present_flag`.
+    ///
+    /// This is `true` if and only if `vui_parameters` is `Some`, since the VUI is parsed
+    /// whenever it is present.
+    pub fn vui_parameters_present_flag(&self) -> bool {
+        self.vui_parameters.is_some()
+    }
+
+    /// Returns `true` if the stream may use referencing that complicates random access,
+    /// i.e., `max_num_ref_frames > 1` or gaps in `frame_num` are allowed.
+    ///
+    /// This is a heuristic: such a stream may refer to pictures (e.g., long-term references)
+    /// preceding a non-IDR point, so seeking should decode from the previous IDR picture
+    /// rather than from a nearer one.
+    pub fn complex_referencing(&self) -> bool {
+        self.max_num_ref_frames > 1 || self.gaps_in_frame_num_value_allowed_flag
+    }
+
+    /// Returns the number of macroblocks in a frame (`FrameSizeInMbs`).
+    pub fn macroblocks_per_frame(&self) -> usize {
+        (self.pic_width_in_mbs_minus_1 as usize + 1)
+            * ((self.pic_height_in_map_units_minus_1 as usize + 1)
+                * (2 - self.frame_mbs_only_flag as usize))
+    }
+
+    /// Returns the number of macroblocks per second.
+    ///
+    /// The frame rate is derived from the VUI timing information as
+    /// `time_scale / (2 * num_units_in_tick)`, and `None` is returned if it is not signaled.
+    pub fn macroblocks_per_second(&self) -> Option<u64> {
+        let timing_info = self.vui_parameters.as_ref()?.timing_info.as_ref()?;
+        if timing_info.num_units_in_tick == 0 {
+            return None;
+        }
+        let mbs = self.macroblocks_per_frame() as u64 * u64::from(timing_info.time_scale);
+        Some(mbs / (2 * u64::from(timing_info.num_units_in_tick)))
+    }
+
+    /// Returns `max_num_reorder_frames` of the VUI bitstream restriction.
+    ///
+    /// `None` is returned if the VUI or its bitstream restriction is absent.
+    pub fn max_num_reorder_frames(&self) -> Option<u64> {
+        self.vui_parameters
+            .as_ref()
+            .and_then(|vui| vui.bitstream_restriction.as_ref())
+            .map(|x| x.max_num_reorder_frames)
+    }
+
+    /// Returns the display aspect ratio in the lowest terms (e.g., `(16, 9)`).
+    ///
+    /// The ratio is derived from the cropped picture size and the VUI sample aspect ratio,
+    /// and `None` is returned if the sample aspect ratio is not signaled or unspecified.
+    pub fn display_aspect_ratio(&self) -> Option<(u32, u32)> {
+        let aspect_ratio_info = self.vui_parameters.as_ref()?.aspect_ratio_info.as_ref()?;
+        let (sar_width, sar_height) = aspect_ratio_info.sample_aspect_ratio()?;
+        let width = self.width() as u64 * u64::from(sar_width);
+        let height = self.height() as u64 * u64::from(sar_height);
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let (mut a, mut b) = (width, height);
+        while b != 0 {
+            let r = a % b;
+            a = b;
+            b = r;
+        }
+        Some(((width / a) as u32, (height / a) as u32))
+    }
+
+    /// Returns the number of frames the decoded picture buffer has to hold.
+    ///
+    /// The `max_dec_frame_buffering` of the VUI is used if it is signaled.
+    /// Otherwise, `MaxDpbFrames` is derived from the level limits (A.3.1, ISO/IEC 14496-10).
+    pub fn max_dpb_frames(&self) -> usize {
+        if let Some(x) = self
+            .vui_parameters
+            .as_ref()
+            .and_then(|vui| vui.bitstream_restriction.as_ref())
+        {
+            return x.max_dec_frame_buffering as usize;
+        }
+
+        let frame_size_in_mbs = self.macroblocks_per_frame();
+        match self.max_dpb_mbs() {
+            Some(max_dpb_mbs) => cmp::min(max_dpb_mbs / frame_size_in_mbs, MAX_DPB_FRAMES),
+            None => MAX_DPB_FRAMES,
+        }
+    }
+
+    /// Returns a human readable multi-line listing of the parsed fields.
+    ///
+    /// Unlike the `Debug` representation, the values are decoded into their meanings
+    /// (e.g., `chroma: 4:2:0` and `level: 3.1`).
+    /// The format is intended for diagnostics and may change.
+    pub fn describe(&self) -> String {
+        let mut s = String::new();
+        let profile = match self.profile_idc {
+            66 if ConstraintFlags(self.constraint_set_flag).is_constrained_baseline() => {
+                "Constrained Baseline"
+            }
+            66 => "Baseline",
+            77 => "Main",
+            88 => "Extended",
+            100 => "High",
+            110 => "High 10",
+            122 => "High 4:2:2",
+            244 => "High 4:4:4 Predictive",
+            44 => "CAVLC 4:4:4 Intra",
+            _ => "Unknown",
+        };
+        let _ = writeln!(s, "profile: {} ({})", profile, self.profile_idc);
+        let _ = writeln!(s, "constraint_set_flag: {:#04x}", self.constraint_set_flag);
+        if self.is_level_1b() {
+            let _ = writeln!(s, "level: 1b ({})", self.level_idc);
+        } else {
+            let _ = writeln!(
+                s,
+                "level: {}.{} ({})",
+                self.level_idc / 10,
+                self.level_idc % 10,
+                self.level_idc
+            );
+        }
+        let _ = writeln!(s, "seq_parameter_set_id: {}", self.seq_parameter_set_id);
+        let coded_width = (self.pic_width_in_mbs_minus_1 + 1) * 16;
+        let coded_height = (2 - u64::from(self.frame_mbs_only_flag))
+            * (self.pic_height_in_map_units_minus_1 + 1)
+            * 16;
+        let _ = writeln!(
+            s,
+            "size: {}x{} (coded {}x{})",
+            self.width(),
+            self.height(),
+            coded_width,
+            coded_height
+        );
+        if self.frame_cropping_flag {
+            let _ = writeln!(
+                s,
+                "crop offsets: left={}, right={}, top={}, bottom={}",
+                self.frame_crop_left_offset,
+                self.frame_crop_right_offset,
+                self.frame_crop_top_offset,
+                self.frame_crop_bottom_offset
+            );
+        } else {
+            let _ = writeln!(s, "crop offsets: none");
+        }
+
+        let (chroma_format, bit_depth_luma, bit_depth_chroma) =
+            match self.extended_configuration_data {
+                Some(ref x) => (
+                    x.chroma_format,
+                    x.bit_depth_luma_minus_8 + 8,
+                    x.bit_depth_chroma_minus_8 + 8,
+                ),
+                None => (1, 8, 8),
+            };
+        let chroma = match chroma_format {
+            0 => "4:0:0",
+            1 => "4:2:0",
+            2 => "4:2:2",
+            _ => "4:4:4",
+        };
+        let _ = writeln!(s, "chroma: {}", chroma);
+        let _ = writeln!(
+            s,
+            "bit depth: luma={}, chroma={}",
+            bit_depth_luma, bit_depth_chroma
+        );
+        let interlaced = match (self.frame_mbs_only_flag, self.mb_adaptive_frame_field_flag) {
+            (1, _) => "no",
+            (_, true) => "yes (MBAFF)",
+            _ => "yes",
+        };
+        let _ = writeln!(s, "interlaced: {}", interlaced);
+        let _ = writeln!(s, "pic_order_cnt_type: {}", self.pic_order_cnt_type());
+        let _ = writeln!(s, "max_num_ref_frames: {}", self.max_num_ref_frames);
+        let scaling_matrix = match self.extended_configuration_data {
+            Some(ref x) => x.seq_scaling_matrix_present,
+            None => false,
+        };
+        let _ = writeln!(
+            s,
+            "scaling matrix: {}",
+            if scaling_matrix { "present" } else { "absent" }
+        );
+
+        match self.vui_parameters {
+            None => {
+                let _ = writeln!(s, "vui: absent");
+            }
+            Some(ref vui) => {
+                let _ = writeln!(s, "vui: present");
+                let sar = vui
+                    .aspect_ratio_info
+                    .as_ref()
+                    .and_then(|x| x.sample_aspect_ratio());
+                if let Some((w, h)) = sar {
+                    let _ = writeln!(s, "  sample aspect ratio: {}:{}", w, h);
+                }
+                if let Some(ref x) = vui.timing_info {
+                    let _ = writeln!(
+                        s,
+                        "  timing: num_units_in_tick={}, time_scale={}, fixed_frame_rate={}",
+                        x.num_units_in_tick,
+                        x.time_scale,
+                        if x.fixed_frame_rate_flag { "yes" } else { "no" }
+                    );
+                }
+                if let Some(ref x) = vui.video_signal_type {
+                    let _ = writeln!(
+                        s,
+                        "  full range: {}",
+                        if x.video_full_range_flag { "yes" } else { "no" }
+                    );
+                }
+            }
+        }
+        s
+    }
+
+    fn is_level_1b(&self) -> bool {
+        self.level_idc == 9
+            || (self.level_idc == 11
+                && ConstraintFlags(self.constraint_set_flag).is_level_1b()
+                && (self.profile_idc == 66 || self.profile_idc == 77 || self.profile_idc == 88))
+    }
+
+    /// `MaxFS` of Table A-1 (ISO/IEC 14496-10).
+    fn max_frame_size_mbs(&self) -> Option<usize> {
+        if self.is_level_1b() {
+            return Some(99);
+        }
+        Some(match self.level_idc {
+            10 => 99,
+            11..=13 | 20 => 396,
+            21 => 792,
+            22 | 30 => 1_620,
+            31 => 3_600,
+            32 => 5_120,
+            40 | 41 => 8_192,
+            42 => 8_704,
+            50 => 22_080,
+            51 | 52 => 36_864,
+            60..=62 => 139_264,
+            _ => return None,
+        })
+    }
+
+    /// `MaxDpbMbs` of Table A-1 (ISO/IEC 14496-10).
+    fn max_dpb_mbs(&self) -> Option<usize> {
+        if self.is_level_1b() {
+            return Some(396);
+        }
+        Some(match self.level_idc {
+            10 => 396,
+            11 => 900,
+            12 | 13 | 20 => 2_376,
+            21 => 4_752,
+            22 | 30 => 8_100,
+            31 => 18_000,
+            32 => 20_480,
+            40 | 41 => 32_768,
+            42 => 34_816,
+            50 => 110_400,
+            51 | 52 => 184_320,
+            60..=62 => 696_320,
+            _ => return None,
+        })
+    }
+
+    /// Reads an SPS from the bytes following the NAL unit header.
+    ///
+    /// Emulation prevention bytes are removed while reading.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let mut reader = AvcBitReader::new(RbspReader::new(reader));
+        track!(Self::read_from_bit_reader(&mut reader))
+    }
+
+    /// Reads an SPS from the bytes following the NAL unit header with the given options.
+    ///
+    /// The size of the NAL unit (including its header) is checked against `max_nal_size`.
+    /// In the strict mode, the parsed fields must be followed by exactly one
+    /// `rbsp_trailing_bits()`; any other trailing bits are rejected as garbage.
+    pub fn read_from_with_options<R: Read>(mut reader: R, options: &ParseOptions) -> Result<Self> {
+        let mut bytes = Vec::new();
+        track_io!(reader.read_to_end(&mut bytes))?;
+        track!(options.check_nal_size(bytes.len() + 1))?;
+        let rbsp = if options.remove_emulation_prevention {
+            let mut rbsp = Vec::new();
+            track_io!(RbspReader::new(&bytes[..]).read_to_end(&mut rbsp))?;
+            rbsp
+        } else {
+            bytes
+        };
+
+        let mut rest = &rbsp[..];
+        let (sps, unread_bits) = {
+            let mut reader = AvcBitReader::new(&mut rest);
+            let sps = track!(Self::read_from_bit_reader(&mut reader))?;
+            (sps, reader.unread_bits_in_byte())
+        };
+        if options.strict {
+            let bit_offset = (rbsp.len() - rest.len()) * 8 - unread_bits;
+            let is_trailing_bits = bit_offset / 8 + 1 == rbsp.len()
+                && rbsp[bit_offset / 8] << (bit_offset % 8) == 0b1000_0000;
+            track_assert!(
+                is_trailing_bits,
+                ErrorKind::InvalidInput,
+                "Trailing garbage following the SPS fields at bit {}",
+                bit_offset
+            );
+        }
+        Ok(sps)
+    }
+
+    /// Reads an SPS from the given NAL unit (including the NAL unit header).
+    ///
+    /// If `preserve_rbsp` is `true`, the original RBSP is retained in `preserved_rbsp`
+    /// so that `write_to` can re-emit the bits following the parsed fields verbatim.
+    pub fn from_nal_unit(nal_unit: &[u8], preserve_rbsp: bool) -> Result<Self> {
+        let header = track!(NalUnit::read_from(nal_unit))?;
+        track_assert_eq!(
+            header.nal_unit_type,
+            NalUnitType::SequenceParameterSet,
+            ErrorKind::InvalidInput
+        );
+
+        let mut rbsp = Vec::new();
+        track_io!(RbspReader::new(&nal_unit[1..]).read_to_end(&mut rbsp))?;
+
+        let mut rest = &rbsp[..];
+        let (mut sps, unread_bits) = {
+            let mut reader = AvcBitReader::new(&mut rest);
+            let sps = track!(Self::read_from_bit_reader(&mut reader))?;
+            (sps, reader.unread_bits_in_byte())
+        };
+        if preserve_rbsp {
+            let bit_offset = (rbsp.len() - rest.len()) * 8 - unread_bits;
+            sps.preserved_rbsp = Some(PreservedRbsp { rbsp, bit_offset });
+        }
+        Ok(sps)
+    }
+
+    fn read_from_bit_reader<R: Read>(reader: &mut AvcBitReader<R>) -> Result<Self> {
+        let profile_idc = track!(reader.read_bits(8))? as u8;
+        let constraint_set_flag = track!(reader.read_bits(8))? as u8;
+        let level_idc = track!(reader.read_bits(8))? as u8;
+        let seq_parameter_set_id = track!(reader.read_ue())?;
 
         let mut extended_data = None;
 
         match profile_idc {
             100 | 110 | 122 | 144 => {
-                //let chroma_format = track!(reader.read_byte())?;
-                let chroma_format = track!(reader.read_ue())?;
-                let separate_color_plane = if chroma_format == 3 {
-                    //YUV 444
-                    Some(true)
-                } else {
-                    None
-                };
-                let bit_depth_luma_minus_8 = track!(reader.read_ue())?;
-                let bit_depth_chroma_minus_8 = track!(reader.read_ue())?;
-                let qp_prime_y_zero_transform_bypass = track!(reader.read_bit())? == 1;
-                let scaling_matrix_present = track!(reader.read_bit())? == 1;
-
-                if scaling_matrix_present {
-                    panic!("Reading scaling matrix unsupported");
-                }
-
-                extended_data = Some(ExtendedConfigurationData {
-                    chroma_format: chroma_format,
-                    separate_color_plane: separate_color_plane,
-                    bit_depth_luma_minus_8: bit_depth_luma_minus_8,
-                    bit_depth_chroma_minus_8: bit_depth_chroma_minus_8,
-                    qp_prime_y_zero_transform_bypass: qp_prime_y_zero_transform_bypass,
-                })
+                extended_data = Some(track!(ExtendedConfigurationData::read_from_bit_reader(
+                    reader
+                ))?);
             }
             _ => {}
         }
 
-        let _log2_max_frame_num_minus4 = track!(reader.read_ue())?;
+        let log2_max_frame_num_minus4 = track!(reader.read_ue())?;
         let pic_order_cnt_type = track!(reader.read_ue())?;
-        match pic_order_cnt_type {
-            0 => {
-                let _log2_max_pic_order_cnt_lsb_minus4 = track!(reader.read_ue())?;
-            }
+        let pic_order_cnt = match pic_order_cnt_type {
+            0 => PicOrderCnt::Type0 {
+                log2_max_pic_order_cnt_lsb_minus4: track!(reader.read_ue())?,
+            },
             1 => {
-                let _delta_pic_order_always_zero_flag = track!(reader.read_bit())?;
-                let _offset_for_non_ref_pic = track!(reader.read_ue())?;
-                let _ffset_for_top_to_bottom_field = track!(reader.read_ue())?;
+                let delta_pic_order_always_zero_flag = track!(reader.read_bit())? == 1;
+                let offset_for_non_ref_pic = track!(reader.read_se())?;
+                let offset_for_top_to_bottom_field = track!(reader.read_se())?;
                 let num_ref_frames_in_pic_order_cnt_cycle = track!(reader.read_ue())?;
+                track_assert!(
+                    num_ref_frames_in_pic_order_cnt_cycle <= 255,
+                    ErrorKind::InvalidInput
+                );
+                let mut offset_for_ref_frame = Vec::new();
                 for _ in 0..num_ref_frames_in_pic_order_cnt_cycle {
-                    let _offset_for_ref_frame = track!(reader.read_ue())?;
+                    offset_for_ref_frame.push(track!(reader.read_se())?);
+                }
+                PicOrderCnt::Type1 {
+                    delta_pic_order_always_zero_flag,
+                    offset_for_non_ref_pic,
+                    offset_for_top_to_bottom_field,
+                    offset_for_ref_frame,
                 }
             }
-            2 => {}
+            2 => PicOrderCnt::Type2,
             _ => track_panic!(ErrorKind::InvalidInput),
-        }
-        let _num_ref_frames = track!(reader.read_ue())?;
-        let _gaps_in_frame_num_value_allowed_flag = track!(reader.read_bit())?;
+        };
+        let max_num_ref_frames = track!(reader.read_ue())?;
+        let gaps_in_frame_num_value_allowed_flag = track!(reader.read_bit())? == 1;
         let pic_width_in_mbs_minus_1 = track!(reader.read_ue())?;
         let pic_height_in_map_units_minus_1 = track!(reader.read_ue())?;
         let frame_mbs_only_flag = track!(reader.read_bit())?;
-        if frame_mbs_only_flag == 0 {
-            let _mb_adaptive_frame_field_flag = track!(reader.read_bit())?;
-        }
-        let _direct_8x8_inference_flag = track!(reader.read_bit())?;
-        let frame_cropping_flag = track!(reader.read_bit())?;
+        let mb_adaptive_frame_field_flag = if frame_mbs_only_flag == 0 {
+            track!(reader.read_bit())? == 1
+        } else {
+            false
+        };
+        let direct_8x8_inference_flag = track!(reader.read_bit())? == 1;
+        let frame_cropping_flag = track!(reader.read_bit())? == 1;
         let (
             frame_crop_left_offset,
             frame_crop_right_offset,
             frame_crop_top_offset,
             frame_crop_bottom_offset,
-        ) = if frame_cropping_flag == 1 {
+        ) = if frame_cropping_flag {
             (
                 track!(reader.read_ue())?,
                 track!(reader.read_ue())?,
@@ -181,126 +1090,3311 @@ impl SpsSummary {
         } else {
             (0, 0, 0, 0)
         };
+        let vui_parameters_present_flag = track!(reader.read_bit())?;
+        let vui_parameters = if vui_parameters_present_flag == 1 {
+            Some(track!(VuiParameters::read_from_bit_reader(reader))?)
+        } else {
+            None
+        };
 
         Ok(SpsSummary {
             profile_idc,
             constraint_set_flag,
             level_idc,
+            seq_parameter_set_id,
+            log2_max_frame_num_minus4,
+            pic_order_cnt,
+            max_num_ref_frames,
+            gaps_in_frame_num_value_allowed_flag,
             pic_width_in_mbs_minus_1,
             pic_height_in_map_units_minus_1,
             frame_mbs_only_flag,
+            mb_adaptive_frame_field_flag,
+            direct_8x8_inference_flag,
+            frame_cropping_flag,
             frame_crop_left_offset,
             frame_crop_right_offset,
             frame_crop_top_offset,
             frame_crop_bottom_offset,
             extended_configuration_data: extended_data,
+            vui_parameters,
+            preserved_rbsp: None,
         })
     }
-}
-
-#[derive(Debug)]
-pub struct NalUnit {
-    pub nal_ref_idc: u8,
-    pub nal_unit_type: NalUnitType,
-}
-impl NalUnit {
-    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
-        let b = track_io!(reader.read_u8())?;
 
-        let nal_ref_idc = (b >> 5) & 0b11;
-        let nal_unit_type = track!(NalUnitType::from_u8(b & 0b1_1111))?;
-        Ok(NalUnit {
-            nal_ref_idc,
-            nal_unit_type,
-        })
+    /// Sets `fixed_frame_rate_flag` of the VUI timing information.
+    ///
+    /// If the SPS has no timing information, it is added with `timing`
+    /// (`(num_units_in_tick, time_scale)`) and, if the SPS has no VUI at all,
+    /// a minimal VUI containing only the timing information is synthesized.
+    /// In that case, an `ErrorKind::InvalidInput` error is returned if `timing` is `None`.
+    /// Clearing the flag of an SPS without timing information does nothing.
+    ///
+    /// The other fields are kept as is, so `write_to()` re-emits the SPS with only this change.
+    pub fn set_fixed_frame_rate(&mut self, fixed: bool, timing: Option<(u32, u32)>) -> Result<()> {
+        let has_timing_info = self
+            .vui_parameters
+            .as_ref()
+            .and_then(|vui| vui.timing_info.as_ref())
+            .is_some();
+        if !fixed && !has_timing_info {
+            return Ok(());
+        }
+        if !has_timing_info {
+            let (num_units_in_tick, time_scale) = track_assert_some!(
+                timing,
+                ErrorKind::InvalidInput,
+                "No timing information to mark as fixed frame rate"
+            );
+            track_assert_ne!(num_units_in_tick, 0, ErrorKind::InvalidInput);
+            track_assert_ne!(time_scale, 0, ErrorKind::InvalidInput);
+            let vui = self.vui_parameters.get_or_insert(VuiParameters {
+                aspect_ratio_info: None,
+                overscan_appropriate_flag: None,
+                video_signal_type: None,
+                chroma_loc_info: None,
+                timing_info: None,
+                nal_hrd_parameters: None,
+                vcl_hrd_parameters: None,
+                low_delay_hrd_flag: false,
+                pic_struct_present_flag: false,
+                bitstream_restriction: None,
+            });
+            vui.timing_info = Some(TimingInfo {
+                num_units_in_tick,
+                time_scale,
+                fixed_frame_rate_flag: fixed,
+            });
+        } else if let Some(x) = self
+            .vui_parameters
+            .as_mut()
+            .and_then(|vui| vui.timing_info.as_mut())
+        {
+            x.fixed_frame_rate_flag = fixed;
+        }
+        Ok(())
     }
-}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum NalUnitType {
-    CodedSliceOfANonIdrPicture = 1,
-    CodedSliceDataPartitionA = 2,
-    CodedSliceDataPartitionB = 3,
-    CodedSliceDataPartitionC = 4,
-    CodedSliceOfAnIdrPicture = 5,
-    SupplementalEnhancementInformation = 6,
-    SequenceParameterSet = 7,
-    PictureParameterSet = 8,
-    AccessUnitDelimiter = 9,
-    EndOfSequence = 10,
-    EndOfStream = 11,
-    FilterData = 12,
-    SequenceParameterSetExtension = 13,
-    PrefixNalUnit = 14,
-    SubsetSequenceParameterSet = 15,
-    CodedSliceOfAnAuxiliaryCodedPictureWithoutPartitioning = 19,
-    CodedSliceExtension = 20,
-    CodedSliceExtensionForDepthViewComponents = 21,
+    /// Changes `time_scale` of the VUI timing information to `new_time_scale`,
+    /// adjusting `num_units_in_tick` so that the frame rate
+    /// (`time_scale / (2 * num_units_in_tick)`) is preserved.
+    ///
+    /// The new `num_units_in_tick` is `num_units_in_tick * new_time_scale / time_scale`.
+    /// If the division is not exact, it is rounded to the nearest integer (but at least `1`),
+    /// so the frame rate is preserved only approximately.
+    ///
+    /// An `ErrorKind::InvalidInput` error is returned if the SPS has no timing information,
+    /// if `new_time_scale` is `0`, or if the new `num_units_in_tick` does not fit in 32 bits.
+    /// The other fields are kept as is, so `write_to()` re-emits the SPS with only this change.
+    pub fn rescale_timing(&mut self, new_time_scale: u32) -> Result<()> {
+        track_assert_ne!(new_time_scale, 0, ErrorKind::InvalidInput);
+        let timing_info = track_assert_some!(
+            self.vui_parameters
+                .as_mut()
+                .and_then(|vui| vui.timing_info.as_mut()),
+            ErrorKind::InvalidInput,
+            "No timing information to rescale"
+        );
+        track_assert_ne!(timing_info.time_scale, 0, ErrorKind::InvalidInput);
+
+        let time_scale = u64::from(timing_info.time_scale);
+        let units = u64::from(timing_info.num_units_in_tick) * u64::from(new_time_scale);
+        let num_units_in_tick = cmp::max((units + time_scale / 2) / time_scale, 1);
+        track_assert!(
+            num_units_in_tick <= u64::from(u32::MAX),
+            ErrorKind::InvalidInput,
+            "num_units_in_tick={}",
+            num_units_in_tick
+        );
+        timing_info.num_units_in_tick = num_units_in_tick as u32;
+        timing_info.time_scale = new_time_scale;
+        Ok(())
+    }
+
+    /// Writes this SPS as the bytes following the NAL unit header.
+    ///
+    /// If `preserved_rbsp` is present, the bits following the parsed fields are copied from it.
+    /// Emulation prevention bytes are inserted while writing.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        let mut rbsp = Vec::new();
+        {
+            let mut writer = AvcBitWriter::new(&mut rbsp);
+            track!(self.write_fields(&mut writer))?;
+            if let Some(ref preserved) = self.preserved_rbsp {
+                track!(preserved.write_tail(&mut writer))?;
+            }
+            track!(writer.write_rbsp_trailing_bits())?;
+        }
+        write_all!(writer, &escape_rbsp(&rbsp));
+        Ok(())
+    }
+
+    fn write_fields<W: Write>(&self, writer: &mut AvcBitWriter<W>) -> Result<()> {
+        track!(writer.write_bits(8, u64::from(self.profile_idc)))?;
+        track!(writer.write_bits(8, u64::from(self.constraint_set_flag)))?;
+        track!(writer.write_bits(8, u64::from(self.level_idc)))?;
+        track!(writer.write_ue(self.seq_parameter_set_id))?;
+
+        match self.profile_idc {
+            100 | 110 | 122 | 144 => {
+                let x = track_assert_some!(
+                    self.extended_configuration_data.as_ref(),
+                    ErrorKind::InvalidInput
+                );
+                track!(x.write_to_bit_writer(writer))?;
+            }
+            _ => {}
+        }
+
+        track!(writer.write_ue(self.log2_max_frame_num_minus4))?;
+        match self.pic_order_cnt {
+            PicOrderCnt::Type0 {
+                log2_max_pic_order_cnt_lsb_minus4,
+            } => {
+                track!(writer.write_ue(0))?;
+                track!(writer.write_ue(log2_max_pic_order_cnt_lsb_minus4))?;
+            }
+            PicOrderCnt::Type1 {
+                delta_pic_order_always_zero_flag,
+                offset_for_non_ref_pic,
+                offset_for_top_to_bottom_field,
+                ref offset_for_ref_frame,
+            } => {
+                track!(writer.write_ue(1))?;
+                track!(writer.write_bool(delta_pic_order_always_zero_flag))?;
+                track!(writer.write_se(offset_for_non_ref_pic))?;
+                track!(writer.write_se(offset_for_top_to_bottom_field))?;
+                track!(writer.write_ue(offset_for_ref_frame.len() as u64))?;
+                for &offset in offset_for_ref_frame {
+                    track!(writer.write_se(offset))?;
+                }
+            }
+            PicOrderCnt::Type2 => {
+                track!(writer.write_ue(2))?;
+            }
+        }
+        track!(writer.write_ue(self.max_num_ref_frames))?;
+        track!(writer.write_bool(self.gaps_in_frame_num_value_allowed_flag))?;
+        track!(writer.write_ue(self.pic_width_in_mbs_minus_1))?;
+        track!(writer.write_ue(self.pic_height_in_map_units_minus_1))?;
+        track!(writer.write_bit(self.frame_mbs_only_flag))?;
+        if self.frame_mbs_only_flag == 0 {
+            track!(writer.write_bool(self.mb_adaptive_frame_field_flag))?;
+        }
+        track!(writer.write_bool(self.direct_8x8_inference_flag))?;
+        track!(writer.write_bool(self.frame_cropping_flag))?;
+        if self.frame_cropping_flag {
+            track!(writer.write_ue(self.frame_crop_left_offset))?;
+            track!(writer.write_ue(self.frame_crop_right_offset))?;
+            track!(writer.write_ue(self.frame_crop_top_offset))?;
+            track!(writer.write_ue(self.frame_crop_bottom_offset))?;
+        }
+        track!(writer.write_bool(self.vui_parameters.is_some()))?;
+        if let Some(ref vui) = self.vui_parameters {
+            track!(vui.write_to_bit_writer(writer))?;
+        }
+        Ok(())
+    }
 }
-impl NalUnitType {
-    fn from_u8(n: u8) -> Result<Self> {
+
+/// `pic_order_cnt_type` and its dependent fields of an SPS.
+#[derive(Debug, Clone)]
+enum PicOrderCnt {
+    Type0 {
+        log2_max_pic_order_cnt_lsb_minus4: u64,
+    },
+    Type1 {
+        delta_pic_order_always_zero_flag: bool,
+        offset_for_non_ref_pic: i64,
+        offset_for_top_to_bottom_field: i64,
+        offset_for_ref_frame: Vec<i64>,
+    },
+    Type2,
+}
+
+/// The original RBSP of a parsed SPS.
+#[derive(Debug, Clone)]
+pub struct PreservedRbsp {
+    /// The RBSP (without emulation prevention bytes).
+    pub rbsp: Vec<u8>,
+
+    /// The bit offset in `rbsp` at which parsing stopped.
+    pub bit_offset: usize,
+}
+impl PreservedRbsp {
+    /// Writes the bits between `bit_offset` and `rbsp_stop_one_bit`.
+    fn write_tail<W: Write>(&self, writer: &mut AvcBitWriter<W>) -> Result<()> {
+        let bit = |i: usize| (self.rbsp[i / 8] >> (7 - i % 8)) & 1;
+        let stop_bit_offset = (0..self.rbsp.len() * 8)
+            .rev()
+            .find(|&i| bit(i) == 1)
+            .unwrap_or(0);
+        for i in self.bit_offset..stop_bit_offset {
+            track!(writer.write_bit(bit(i)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads `count` parameter sets, each preceded by its 16-bit length, from an `avcC` record.
+pub(crate) fn read_parameter_sets(reader: &mut &[u8], count: u8) -> Result<Vec<Vec<u8>>> {
+    let mut parameter_sets = Vec::with_capacity(usize::from(count));
+    for i in 0..count {
+        track_assert!(
+            reader.len() >= 2,
+            ErrorKind::InvalidInput,
+            "{} parameter sets are declared, but the record contains only {}",
+            count,
+            i
+        );
+        let length = usize::from(track_io!(reader.read_u16::<BigEndian>())?);
+        track_assert!(
+            reader.len() >= length,
+            ErrorKind::InvalidInput,
+            "Parameter set #{} overruns the record: length={}, remaining={}",
+            i,
+            length,
+            reader.len()
+        );
+        let (parameter_set, rest) = reader.split_at(length);
+        parameter_sets.push(parameter_set.to_owned());
+        *reader = rest;
+    }
+    Ok(parameter_sets)
+}
+
+/// Picture parameter set (7.3.2.2, ISO/IEC 14496-10).
+///
+/// Only the leading fields of the PPS are parsed.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct PpsSummary {
+    pub pic_parameter_set_id: u64,
+    pub seq_parameter_set_id: u64,
+    pub entropy_coding_mode_flag: bool,
+    pub bottom_field_pic_order_in_frame_present_flag: bool,
+}
+impl PpsSummary {
+    /// Reads a PPS from the bytes following the NAL unit header.
+    ///
+    /// Emulation prevention bytes are removed while reading.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let mut reader = AvcBitReader::new(RbspReader::new(reader));
+        let pic_parameter_set_id = track!(reader.read_ue())?;
+        let seq_parameter_set_id = track!(reader.read_ue())?;
+        let entropy_coding_mode_flag = track!(reader.read_bit())? == 1;
+        let bottom_field_pic_order_in_frame_present_flag = track!(reader.read_bit())? == 1;
+        Ok(PpsSummary {
+            pic_parameter_set_id,
+            seq_parameter_set_id,
+            entropy_coding_mode_flag,
+            bottom_field_pic_order_in_frame_present_flag,
+        })
+    }
+}
+
+/// Checks that `pps` refers to `sps`.
+pub fn validate_pps_references_sps(pps: &PpsSummary, sps: &SpsSummary) -> Result<()> {
+    track_assert_eq!(
+        pps.seq_parameter_set_id,
+        sps.seq_parameter_set_id,
+        ErrorKind::InvalidInput
+    );
+    Ok(())
+}
+
+/// Checks the consistency of the given SPS and PPS NAL units (including their NAL unit headers)
+/// against each other and against the profile and level signaled in the SPS.
+///
+/// The following constraints are checked:
+/// - the PPS refers to the SPS (`seq_parameter_set_id`)
+/// - the Baseline profile does not use CABAC
+/// - the frame size does not exceed `MaxFS` of the level (Table A-1, ISO/IEC 14496-10),
+///   and neither does the width nor the height exceed `Sqrt(MaxFS * 8)` macroblocks
+/// - the chroma format and the bit depths are allowed by the profile (A.2, ISO/IEC 14496-10)
+///
+/// An `ErrorKind::InvalidInput` error describing the first violation is returned.
+pub fn validate_stream_consistency(sps: &[u8], pps: &[u8]) -> Result<()> {
+    track_assert!(!sps.is_empty(), ErrorKind::InvalidInput, "Empty SPS");
+    track_assert!(!pps.is_empty(), ErrorKind::InvalidInput, "Empty PPS");
+    track_assert_eq!(
+        track!(nal_unit_type_of(sps[0]))?,
+        NalUnitType::SequenceParameterSet,
+        ErrorKind::InvalidInput
+    );
+    track_assert_eq!(
+        track!(nal_unit_type_of(pps[0]))?,
+        NalUnitType::PictureParameterSet,
+        ErrorKind::InvalidInput
+    );
+    let sps = track!(SpsSummary::read_from(&sps[1..]))?;
+    let pps = track!(PpsSummary::read_from(&pps[1..]))?;
+    track!(
+        validate_pps_references_sps(&pps, &sps),
+        "The PPS refers to a missing SPS"
+    )?;
+    track_assert!(
+        !(sps.profile_idc == 66 && pps.entropy_coding_mode_flag),
+        ErrorKind::InvalidInput,
+        "The Baseline profile does not support CABAC"
+    );
+
+    let max_frame_size = track_assert_some!(
+        sps.max_frame_size_mbs(),
+        ErrorKind::InvalidInput,
+        "Unknown level: level_idc={}",
+        sps.level_idc
+    );
+    let frame_size = sps.macroblocks_per_frame();
+    track_assert!(
+        frame_size <= max_frame_size,
+        ErrorKind::InvalidInput,
+        "The frame size exceeds the level limit: level_idc={}, macroblocks={}, MaxFS={}",
+        sps.level_idc,
+        frame_size,
+        max_frame_size
+    );
+    let width_in_mbs = sps.pic_width_in_mbs_minus_1 as usize + 1;
+    let height_in_mbs = frame_size / width_in_mbs;
+    for &(name, mbs) in &[("width", width_in_mbs), ("height", height_in_mbs)] {
+        track_assert!(
+            mbs * mbs <= max_frame_size * 8,
+            ErrorKind::InvalidInput,
+            "The frame {} exceeds the level limit: level_idc={}, macroblocks={}",
+            name,
+            sps.level_idc,
+            mbs
+        );
+    }
+
+    let (chroma_format, bit_depth) = match sps.extended_configuration_data {
+        Some(ref x) => (x.chroma_format, x.max_bit_depth()),
+        None => (1, 8),
+    };
+    let (max_chroma_format, max_bit_depth) = match sps.profile_idc {
+        100 => (1, 8),
+        110 => (1, 10),
+        122 => (2, 10),
+        144 | 244 => (3, 14),
+        _ => (1, 8),
+    };
+    track_assert!(
+        chroma_format <= max_chroma_format,
+        ErrorKind::InvalidInput,
+        "The chroma format is not allowed by the profile: profile_idc={}, chroma_format_idc={}",
+        sps.profile_idc,
+        chroma_format
+    );
+    track_assert!(
+        bit_depth <= max_bit_depth,
+        ErrorKind::InvalidInput,
+        "The bit depth is not allowed by the profile: profile_idc={}, bit_depth={}",
+        sps.profile_idc,
+        bit_depth
+    );
+    Ok(())
+}
+
+/// Slice type (Table 7-6, ISO/IEC 14496-10).
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SliceType {
+    P,
+    B,
+    I,
+    Sp,
+    Si,
+}
+impl SliceType {
+    fn from_u64(n: u64) -> Result<Self> {
+        // Values 5..=9 additionally indicate that all slices of the picture have the same type.
         Ok(match n {
-            1 => NalUnitType::CodedSliceOfANonIdrPicture,
-            2 => NalUnitType::CodedSliceDataPartitionA,
-            3 => NalUnitType::CodedSliceDataPartitionB,
-            4 => NalUnitType::CodedSliceDataPartitionC,
-            5 => NalUnitType::CodedSliceOfAnIdrPicture,
-            6 => NalUnitType::SupplementalEnhancementInformation,
-            7 => NalUnitType::SequenceParameterSet,
-            8 => NalUnitType::PictureParameterSet,
-            9 => NalUnitType::AccessUnitDelimiter,
-            10 => NalUnitType::EndOfSequence,
-            11 => NalUnitType::EndOfStream,
-            12 => NalUnitType::FilterData,
-            13 => NalUnitType::SequenceParameterSetExtension,
-            14 => NalUnitType::PrefixNalUnit,
-            15 => NalUnitType::SubsetSequenceParameterSet,
-            19 => NalUnitType::CodedSliceOfAnAuxiliaryCodedPictureWithoutPartitioning,
-            20 => NalUnitType::CodedSliceExtension,
-            21 => NalUnitType::CodedSliceExtensionForDepthViewComponents,
-            _ => track_panic!(ErrorKind::InvalidInput),
+            0 | 5 => SliceType::P,
+            1 | 6 => SliceType::B,
+            2 | 7 => SliceType::I,
+            3 | 8 => SliceType::Sp,
+            4 | 9 => SliceType::Si,
+            _ => track_panic!(ErrorKind::InvalidInput, "Unknown slice type: {}", n),
         })
     }
 }
 
-#[derive(Debug)]
-pub struct ByteStreamFormatNalUnits<'a> {
-    bytes: &'a [u8],
+/// Slice header (7.3.3, ISO/IEC 14496-10).
+///
+/// Only the leading fields of the slice header are parsed.
+/// The fields following `pic_parameter_set_id` are available only if the header is read by
+/// `read_with_parameter_sets()`.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct SliceHeaderSummary {
+    pub first_mb_in_slice: u64,
+    pub slice_type: SliceType,
+    pub pic_parameter_set_id: u64,
+    pub frame_num: Option<u64>,
+    pub field_pic_flag: bool,
+    pub bottom_field_flag: bool,
+    pub idr_pic_id: Option<u64>,
+
+    /// `pic_order_cnt_lsb` (only present if `pic_order_cnt_type` is `0`).
+    pub pic_order_cnt_lsb: Option<u64>,
+
+    /// `delta_pic_order_cnt_bottom` (only present if `pic_order_cnt_type` is `0`,
+    /// `bottom_field_pic_order_in_frame_present_flag` is set and the picture is a frame).
+    pub delta_pic_order_cnt_bottom: Option<i64>,
+
+    /// `delta_pic_order_cnt[0]` and `delta_pic_order_cnt[1]` (only present if
+    /// `pic_order_cnt_type` is `1` and `delta_pic_order_always_zero_flag` is not set;
+    /// the latter also requires the conditions of `delta_pic_order_cnt_bottom`).
+    pub delta_pic_order_cnt: [Option<i64>; 2],
 }
-impl<'a> ByteStreamFormatNalUnits<'a> {
-    pub fn new(bytes: &'a [u8]) -> Result<Self> {
-        let bytes = if bytes.starts_with(&[0, 0, 1][..]) {
-            &bytes[3..]
-        } else if bytes.starts_with(&[0, 0, 0, 1][..]) {
-            &bytes[4..]
+impl SliceHeaderSummary {
+    /// Reads a slice header from the bytes following the NAL unit header.
+    ///
+    /// Emulation prevention bytes are removed while reading.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let mut reader = AvcBitReader::new(RbspReader::new(reader));
+        track!(Self::read_leading_fields(&mut reader))
+    }
+
+    /// Reads a slice header up to `delta_pic_order_cnt_bottom` from the bytes following
+    /// the header of a NAL unit of the type `nal_unit_type`.
+    ///
+    /// `sps` and `pps` are the parameter sets referred by the slice.
+    /// Emulation prevention bytes are removed while reading.
+    pub fn read_with_parameter_sets<R: Read>(
+        reader: R,
+        nal_unit_type: NalUnitType,
+        sps: &SpsSummary,
+        pps: &PpsSummary,
+    ) -> Result<Self> {
+        let mut reader = AvcBitReader::new(RbspReader::new(reader));
+        let mut header = track!(Self::read_leading_fields(&mut reader))?;
+        track_assert_eq!(
+            header.pic_parameter_set_id,
+            pps.pic_parameter_set_id,
+            ErrorKind::InvalidInput
+        );
+        track!(validate_pps_references_sps(pps, sps))?;
+
+        let separate_colour_plane = sps
+            .extended_configuration_data
+            .as_ref()
+            .and_then(|x| x.separate_color_plane)
+            .unwrap_or(false);
+        if separate_colour_plane {
+            let _colour_plane_id = track!(reader.read_bits(2))?;
+        }
+        header.frame_num = Some(track!(reader.read_bits(sps.frame_num_bits()))?);
+        if sps.frame_mbs_only_flag == 0 {
+            header.field_pic_flag = track!(reader.read_bit())? == 1;
+            if header.field_pic_flag {
+                header.bottom_field_flag = track!(reader.read_bit())? == 1;
+            }
+        }
+        if nal_unit_type == NalUnitType::CodedSliceOfAnIdrPicture {
+            header.idr_pic_id = Some(track!(reader.read_ue())?);
+        }
+        if let Some(log2_max_pic_order_cnt_lsb_minus4) = sps.log2_max_pic_order_cnt_lsb_minus4() {
+            let bits = log2_max_pic_order_cnt_lsb_minus4 as usize + 4;
+            header.pic_order_cnt_lsb = Some(track!(reader.read_bits(bits))?);
+            if pps.bottom_field_pic_order_in_frame_present_flag && !header.field_pic_flag {
+                header.delta_pic_order_cnt_bottom = Some(track!(reader.read_se())?);
+            }
+        }
+        if let PicOrderCnt::Type1 {
+            delta_pic_order_always_zero_flag: false,
+            ..
+        } = sps.pic_order_cnt
+        {
+            header.delta_pic_order_cnt[0] = Some(track!(reader.read_se())?);
+            if pps.bottom_field_pic_order_in_frame_present_flag && !header.field_pic_flag {
+                header.delta_pic_order_cnt[1] = Some(track!(reader.read_se())?);
+            }
+        }
+        Ok(header)
+    }
+
+    fn read_leading_fields<R: Read>(reader: &mut AvcBitReader<R>) -> Result<Self> {
+        let first_mb_in_slice = track!(reader.read_ue())?;
+        let slice_type = track!(SliceType::from_u64(track!(reader.read_ue())?))?;
+        let pic_parameter_set_id = track!(reader.read_ue())?;
+        Ok(SliceHeaderSummary {
+            first_mb_in_slice,
+            slice_type,
+            pic_parameter_set_id,
+            frame_num: None,
+            field_pic_flag: false,
+            bottom_field_flag: false,
+            idr_pic_id: None,
+            pic_order_cnt_lsb: None,
+            delta_pic_order_cnt_bottom: None,
+            delta_pic_order_cnt: [None; 2],
+        })
+    }
+}
+
+/// The upper bound of `MaxDpbFrames` (A.3.1, ISO/IEC 14496-10).
+const MAX_DPB_FRAMES: usize = 16;
+
+/// Video usability information (E.1.1, ISO/IEC 14496-10).
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct VuiParameters {
+    pub aspect_ratio_info: Option<AspectRatioInfo>,
+    pub overscan_appropriate_flag: Option<bool>,
+    pub video_signal_type: Option<VideoSignalType>,
+    pub chroma_loc_info: Option<ChromaLocInfo>,
+    pub timing_info: Option<TimingInfo>,
+    pub nal_hrd_parameters: Option<HrdParameters>,
+    pub vcl_hrd_parameters: Option<HrdParameters>,
+
+    /// Only meaningful if either `nal_hrd_parameters` or `vcl_hrd_parameters` is present.
+    pub low_delay_hrd_flag: bool,
+    pub pic_struct_present_flag: bool,
+    pub bitstream_restriction: Option<BitstreamRestriction>,
+}
+impl VuiParameters {
+    pub(crate) fn read_from_bit_reader<R: Read>(reader: &mut AvcBitReader<R>) -> Result<Self> {
+        let aspect_ratio_info = track!(AspectRatioInfo::read_if_present(reader))?;
+
+        let overscan_info_present_flag = track!(reader.read_bit())?;
+        let overscan_appropriate_flag = if overscan_info_present_flag == 1 {
+            Some(track!(reader.read_bit())? == 1)
         } else {
-            track_panic!(ErrorKind::InvalidInput);
+            None
+        };
+
+        let video_signal_type = track!(VideoSignalType::read_if_present(reader))?;
+        let chroma_loc_info = track!(ChromaLocInfo::read_if_present(reader))?;
+
+        let timing_info_present_flag = track!(reader.read_bit())?;
+        let timing_info = if timing_info_present_flag == 1 {
+            Some(TimingInfo {
+                num_units_in_tick: track!(reader.read_bits(32))? as u32,
+                time_scale: track!(reader.read_bits(32))? as u32,
+                fixed_frame_rate_flag: track!(reader.read_bit())? == 1,
+            })
+        } else {
+            None
+        };
+
+        let nal_hrd_parameters_present_flag = track!(reader.read_bit())?;
+        let nal_hrd_parameters = if nal_hrd_parameters_present_flag == 1 {
+            Some(track!(HrdParameters::read_from_bit_reader(reader))?)
+        } else {
+            None
+        };
+        let vcl_hrd_parameters_present_flag = track!(reader.read_bit())?;
+        let vcl_hrd_parameters = if vcl_hrd_parameters_present_flag == 1 {
+            Some(track!(HrdParameters::read_from_bit_reader(reader))?)
+        } else {
+            None
+        };
+        let low_delay_hrd_flag = if nal_hrd_parameters.is_some() || vcl_hrd_parameters.is_some()
+        {
+            track!(reader.read_bit())? == 1
+        } else {
+            false
+        };
+        let pic_struct_present_flag = track!(reader.read_bit())? == 1;
+
+        let bitstream_restriction_flag = track!(reader.read_bit())?;
+        let bitstream_restriction = if bitstream_restriction_flag == 1 {
+            Some(BitstreamRestriction {
+                motion_vectors_over_pic_boundaries_flag: track!(reader.read_bit())? == 1,
+                max_bytes_per_pic_denom: track!(reader.read_ue())?,
+                max_bits_per_mb_denom: track!(reader.read_ue())?,
+                log2_max_mv_length_horizontal: track!(reader.read_ue())?,
+                log2_max_mv_length_vertical: track!(reader.read_ue())?,
+                max_num_reorder_frames: track!(reader.read_ue())?,
+                max_dec_frame_buffering: track!(reader.read_ue())?,
+            })
+        } else {
+            None
         };
-        Ok(ByteStreamFormatNalUnits { bytes })
+
+        Ok(VuiParameters {
+            aspect_ratio_info,
+            overscan_appropriate_flag,
+            video_signal_type,
+            chroma_loc_info,
+            timing_info,
+            nal_hrd_parameters,
+            vcl_hrd_parameters,
+            low_delay_hrd_flag,
+            pic_struct_present_flag,
+            bitstream_restriction,
+        })
+    }
+
+    pub(crate) fn write_to_bit_writer<W: Write>(&self, writer: &mut AvcBitWriter<W>) -> Result<()> {
+        track!(writer.write_bool(self.aspect_ratio_info.is_some()))?;
+        if let Some(ref x) = self.aspect_ratio_info {
+            track!(writer.write_bits(8, u64::from(x.aspect_ratio_idc)))?;
+            if x.aspect_ratio_idc == AspectRatioInfo::EXTENDED_SAR {
+                track!(writer.write_bits(16, u64::from(x.sar_width)))?;
+                track!(writer.write_bits(16, u64::from(x.sar_height)))?;
+            }
+        }
+
+        track!(writer.write_bool(self.overscan_appropriate_flag.is_some()))?;
+        if let Some(x) = self.overscan_appropriate_flag {
+            track!(writer.write_bool(x))?;
+        }
+
+        track!(writer.write_bool(self.video_signal_type.is_some()))?;
+        if let Some(ref x) = self.video_signal_type {
+            track!(writer.write_bits(3, u64::from(x.video_format)))?;
+            track!(writer.write_bool(x.video_full_range_flag))?;
+            track!(writer.write_bool(x.colour_description.is_some()))?;
+            if let Some(ref x) = x.colour_description {
+                track!(writer.write_bits(8, u64::from(x.colour_primaries)))?;
+                track!(writer.write_bits(8, u64::from(x.transfer_characteristics)))?;
+                track!(writer.write_bits(8, u64::from(x.matrix_coefficients)))?;
+            }
+        }
+
+        track!(writer.write_bool(self.chroma_loc_info.is_some()))?;
+        if let Some(ref x) = self.chroma_loc_info {
+            track!(writer.write_ue(x.chroma_sample_loc_type_top_field))?;
+            track!(writer.write_ue(x.chroma_sample_loc_type_bottom_field))?;
+        }
+
+        track!(writer.write_bool(self.timing_info.is_some()))?;
+        if let Some(ref x) = self.timing_info {
+            track!(writer.write_bits(32, u64::from(x.num_units_in_tick)))?;
+            track!(writer.write_bits(32, u64::from(x.time_scale)))?;
+            track!(writer.write_bool(x.fixed_frame_rate_flag))?;
+        }
+
+        track!(writer.write_bool(self.nal_hrd_parameters.is_some()))?;
+        if let Some(ref x) = self.nal_hrd_parameters {
+            track!(x.write_to_bit_writer(writer))?;
+        }
+        track!(writer.write_bool(self.vcl_hrd_parameters.is_some()))?;
+        if let Some(ref x) = self.vcl_hrd_parameters {
+            track!(x.write_to_bit_writer(writer))?;
+        }
+        if self.nal_hrd_parameters.is_some() || self.vcl_hrd_parameters.is_some() {
+            track!(writer.write_bool(self.low_delay_hrd_flag))?;
+        }
+        track!(writer.write_bool(self.pic_struct_present_flag))?;
+
+        track!(writer.write_bool(self.bitstream_restriction.is_some()))?;
+        if let Some(ref x) = self.bitstream_restriction {
+            track!(writer.write_bool(x.motion_vectors_over_pic_boundaries_flag))?;
+            track!(writer.write_ue(x.max_bytes_per_pic_denom))?;
+            track!(writer.write_ue(x.max_bits_per_mb_denom))?;
+            track!(writer.write_ue(x.log2_max_mv_length_horizontal))?;
+            track!(writer.write_ue(x.log2_max_mv_length_vertical))?;
+            track!(writer.write_ue(x.max_num_reorder_frames))?;
+            track!(writer.write_ue(x.max_dec_frame_buffering))?;
+        }
+        Ok(())
     }
 }
-impl<'a> Iterator for ByteStreamFormatNalUnits<'a> {
-    type Item = &'a [u8];
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.bytes.is_empty() {
-            None
+
+/// HRD parameters (E.1.2, ISO/IEC 14496-10).
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct HrdParameters {
+    pub bit_rate_scale: u8,
+    pub cpb_size_scale: u8,
+
+    /// The specifications of each CPB (`cpb_cnt_minus1 + 1` entries).
+    pub cpb_specs: Vec<CpbSpec>,
+    pub initial_cpb_removal_delay_length_minus1: u8,
+    pub cpb_removal_delay_length_minus1: u8,
+    pub dpb_output_delay_length_minus1: u8,
+    pub time_offset_length: u8,
+}
+impl HrdParameters {
+    pub(crate) fn read_from_bit_reader<R: Read>(reader: &mut AvcBitReader<R>) -> Result<Self> {
+        let cpb_cnt_minus1 = track!(reader.read_ue())?;
+        track_assert!(cpb_cnt_minus1 <= 31, ErrorKind::InvalidInput);
+        let bit_rate_scale = track!(reader.read_bits(4))? as u8;
+        let cpb_size_scale = track!(reader.read_bits(4))? as u8;
+        let mut cpb_specs = Vec::with_capacity(cpb_cnt_minus1 as usize + 1);
+        for _ in 0..=cpb_cnt_minus1 {
+            cpb_specs.push(CpbSpec {
+                bit_rate_value_minus1: track!(reader.read_ue())?,
+                cpb_size_value_minus1: track!(reader.read_ue())?,
+                cbr_flag: track!(reader.read_bit())? == 1,
+            });
+        }
+        Ok(HrdParameters {
+            bit_rate_scale,
+            cpb_size_scale,
+            cpb_specs,
+            initial_cpb_removal_delay_length_minus1: track!(reader.read_bits(5))? as u8,
+            cpb_removal_delay_length_minus1: track!(reader.read_bits(5))? as u8,
+            dpb_output_delay_length_minus1: track!(reader.read_bits(5))? as u8,
+            time_offset_length: track!(reader.read_bits(5))? as u8,
+        })
+    }
+
+    pub(crate) fn write_to_bit_writer<W: Write>(&self, writer: &mut AvcBitWriter<W>) -> Result<()> {
+        track_assert!(
+            !self.cpb_specs.is_empty() && self.cpb_specs.len() <= 32,
+            ErrorKind::InvalidInput
+        );
+        track!(writer.write_ue(self.cpb_specs.len() as u64 - 1))?;
+        track!(writer.write_bits(4, u64::from(self.bit_rate_scale)))?;
+        track!(writer.write_bits(4, u64::from(self.cpb_size_scale)))?;
+        for x in &self.cpb_specs {
+            track!(writer.write_ue(x.bit_rate_value_minus1))?;
+            track!(writer.write_ue(x.cpb_size_value_minus1))?;
+            track!(writer.write_bool(x.cbr_flag))?;
+        }
+        track!(writer.write_bits(5, u64::from(self.initial_cpb_removal_delay_length_minus1)))?;
+        track!(writer.write_bits(5, u64::from(self.cpb_removal_delay_length_minus1)))?;
+        track!(writer.write_bits(5, u64::from(self.dpb_output_delay_length_minus1)))?;
+        track!(writer.write_bits(5, u64::from(self.time_offset_length)))?;
+        Ok(())
+    }
+}
+
+/// Specification of a coded picture buffer in `HrdParameters`.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct CpbSpec {
+    pub bit_rate_value_minus1: u64,
+    pub cpb_size_value_minus1: u64,
+    pub cbr_flag: bool,
+}
+
+/// Sample aspect ratio signaled in the VUI.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct AspectRatioInfo {
+    pub aspect_ratio_idc: u8,
+
+    /// Only meaningful if `aspect_ratio_idc` is `Extended_SAR` (255).
+    pub sar_width: u16,
+
+    /// Only meaningful if `aspect_ratio_idc` is `Extended_SAR` (255).
+    pub sar_height: u16,
+}
+impl AspectRatioInfo {
+    /// The `aspect_ratio_idc` value indicating that `sar_width` and `sar_height` are explicitly signaled.
+    pub const EXTENDED_SAR: u8 = 255;
+
+    /// Reads `aspect_ratio_info_present_flag` and the following fields.
+    pub(crate) fn read_if_present<R: Read>(reader: &mut AvcBitReader<R>) -> Result<Option<Self>> {
+        let aspect_ratio_info_present_flag = track!(reader.read_bit())?;
+        if aspect_ratio_info_present_flag == 0 {
+            return Ok(None);
+        }
+        let aspect_ratio_idc = track!(reader.read_bits(8))? as u8;
+        let (sar_width, sar_height) = if aspect_ratio_idc == AspectRatioInfo::EXTENDED_SAR {
+            (
+                track!(reader.read_bits(16))? as u16,
+                track!(reader.read_bits(16))? as u16,
+            )
         } else {
-            let mut nal_unit_end = self.bytes.len();
-            let mut next_start = self.bytes.len();
-            for i in 0..self.bytes.len() {
-                if (&self.bytes[i..]).starts_with(&[0, 0, 0, 1][..]) {
-                    nal_unit_end = i;
-                    next_start = i + 4;
-                    break;
-                } else if (&self.bytes[i..]).starts_with(&[0, 0, 1][..]) {
-                    nal_unit_end = i;
-                    next_start = i + 3;
-                    break;
-                }
+            (0, 0)
+        };
+        Ok(Some(AspectRatioInfo {
+            aspect_ratio_idc,
+            sar_width,
+            sar_height,
+        }))
+    }
+
+    /// Returns the sample aspect ratio as `(horizontal, vertical)` (Table E-1, ISO/IEC 14496-10).
+    ///
+    /// `None` is returned if the ratio is unspecified or reserved.
+    pub fn sample_aspect_ratio(&self) -> Option<(u16, u16)> {
+        Some(match self.aspect_ratio_idc {
+            1 => (1, 1),
+            2 => (12, 11),
+            3 => (10, 11),
+            4 => (16, 11),
+            5 => (40, 33),
+            6 => (24, 11),
+            7 => (20, 11),
+            8 => (32, 11),
+            9 => (80, 33),
+            10 => (18, 11),
+            11 => (15, 11),
+            12 => (64, 33),
+            13 => (160, 99),
+            14 => (4, 3),
+            15 => (3, 2),
+            16 => (2, 1),
+            Self::EXTENDED_SAR if self.sar_width != 0 && self.sar_height != 0 => {
+                (self.sar_width, self.sar_height)
             }
-            let nal_unit = &self.bytes[..nal_unit_end];
-            self.bytes = &self.bytes[next_start..];
-            Some(nal_unit)
+            _ => return None,
+        })
+    }
+
+    /// Makes a `pasp` box from the sample aspect ratio.
+    ///
+    /// `None` is returned if the ratio is unspecified,
+    /// or if it is 1:1 and `skip_square_pixels` is `true`.
+    pub fn pasp_box(&self, skip_square_pixels: bool) -> Option<PixelAspectRatioBox> {
+        let (h_spacing, v_spacing) = self.sample_aspect_ratio()?;
+        if skip_square_pixels && h_spacing == v_spacing {
+            return None;
+        }
+        Some(PixelAspectRatioBox {
+            h_spacing: u32::from(h_spacing),
+            v_spacing: u32::from(v_spacing),
+        })
+    }
+}
+
+/// Video signal type signaled in the VUI.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct VideoSignalType {
+    pub video_format: u8,
+    pub video_full_range_flag: bool,
+    pub colour_description: Option<ColourDescription>,
+}
+impl VideoSignalType {
+    /// Reads `video_signal_type_present_flag` and the following fields.
+    pub(crate) fn read_if_present<R: Read>(reader: &mut AvcBitReader<R>) -> Result<Option<Self>> {
+        let video_signal_type_present_flag = track!(reader.read_bit())?;
+        if video_signal_type_present_flag == 0 {
+            return Ok(None);
+        }
+        let video_format = track!(reader.read_bits(3))? as u8;
+        let video_full_range_flag = track!(reader.read_bit())? == 1;
+        let colour_description_present_flag = track!(reader.read_bit())?;
+        let colour_description = if colour_description_present_flag == 1 {
+            Some(ColourDescription {
+                colour_primaries: track!(reader.read_bits(8))? as u8,
+                transfer_characteristics: track!(reader.read_bits(8))? as u8,
+                matrix_coefficients: track!(reader.read_bits(8))? as u8,
+            })
+        } else {
+            None
+        };
+        Ok(Some(VideoSignalType {
+            video_format,
+            video_full_range_flag,
+            colour_description,
+        }))
+    }
+}
+
+/// Colour description signaled in the VUI.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct ColourDescription {
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+}
+
+/// Chroma sample locations signaled in the VUI (`chroma_loc_info_present_flag` is `1`).
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChromaLocInfo {
+    pub chroma_sample_loc_type_top_field: u64,
+    pub chroma_sample_loc_type_bottom_field: u64,
+}
+impl ChromaLocInfo {
+    /// Reads `chroma_loc_info_present_flag` and the following fields.
+    pub(crate) fn read_if_present<R: Read>(reader: &mut AvcBitReader<R>) -> Result<Option<Self>> {
+        let chroma_loc_info_present_flag = track!(reader.read_bit())?;
+        if chroma_loc_info_present_flag == 0 {
+            return Ok(None);
         }
+        let chroma_sample_loc_type_top_field = track!(reader.read_ue())?;
+        let chroma_sample_loc_type_bottom_field = track!(reader.read_ue())?;
+        Ok(Some(ChromaLocInfo {
+            chroma_sample_loc_type_top_field,
+            chroma_sample_loc_type_bottom_field,
+        }))
+    }
+}
+
+/// Timing information signaled in the VUI.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct TimingInfo {
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub fixed_frame_rate_flag: bool,
+}
+
+/// Bitstream restriction signaled in the VUI.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct BitstreamRestriction {
+    pub motion_vectors_over_pic_boundaries_flag: bool,
+    pub max_bytes_per_pic_denom: u64,
+    pub max_bits_per_mb_denom: u64,
+    pub log2_max_mv_length_horizontal: u64,
+    pub log2_max_mv_length_vertical: u64,
+    pub max_num_reorder_frames: u64,
+    pub max_dec_frame_buffering: u64,
+}
+
+/// Sequence parameter set extension (NAL unit type 13).
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct SpsExtension {
+    pub seq_parameter_set_id: u64,
+    pub aux_format_idc: u64,
+    pub aux_format: Option<AuxFormatInfo>,
+}
+impl SpsExtension {
+    /// Reads the extension from the RBSP following the NAL unit header.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let mut reader = AvcBitReader::new(reader);
+        let seq_parameter_set_id = track!(reader.read_ue())?;
+        let aux_format_idc = track!(reader.read_ue())?;
+        track_assert!(aux_format_idc <= 3, ErrorKind::InvalidInput);
+
+        let aux_format = if aux_format_idc != 0 {
+            let bit_depth_aux_minus8 = track!(reader.read_ue())?;
+            track_assert!(bit_depth_aux_minus8 <= 4, ErrorKind::InvalidInput);
+            let alpha_incr_flag = track!(reader.read_bit())? == 1;
+            let alpha_value_bits = bit_depth_aux_minus8 as usize + 9;
+            let alpha_opaque_value = track!(reader.read_bits(alpha_value_bits))?;
+            let alpha_transparent_value = track!(reader.read_bits(alpha_value_bits))?;
+            Some(AuxFormatInfo {
+                bit_depth_aux_minus8,
+                alpha_incr_flag,
+                alpha_opaque_value,
+                alpha_transparent_value,
+            })
+        } else {
+            None
+        };
+        let _additional_extension_flag = track!(reader.read_bit())?;
+
+        Ok(SpsExtension {
+            seq_parameter_set_id,
+            aux_format_idc,
+            aux_format,
+        })
+    }
+}
+
+/// Auxiliary coded picture format signaled by an `SpsExtension`.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct AuxFormatInfo {
+    pub bit_depth_aux_minus8: u64,
+    pub alpha_incr_flag: bool,
+    pub alpha_opaque_value: u64,
+    pub alpha_transparent_value: u64,
+}
+
+#[derive(Debug)]
+pub struct NalUnit {
+    pub nal_ref_idc: u8,
+    pub nal_unit_type: NalUnitType,
+}
+impl NalUnit {
+    /// Reads a NAL unit header.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        track!(Self::read_from_with_options(reader, &ParseOptions::default()))
+    }
+
+    /// Reads a NAL unit header with the given options.
+    ///
+    /// In the strict mode, a set `forbidden_zero_bit` is rejected.
+    /// Unknown NAL unit types are rejected regardless of the mode.
+    pub fn read_from_with_options<R: Read>(mut reader: R, options: &ParseOptions) -> Result<Self> {
+        let b = track_io!(reader.read_u8())?;
+        track_assert!(
+            !options.strict || b & 0b1000_0000 == 0,
+            ErrorKind::InvalidInput,
+            "forbidden_zero_bit must be zero"
+        );
+
+        let nal_ref_idc = (b >> 5) & 0b11;
+        let nal_unit_type = track!(nal_unit_type_of(b))?;
+        Ok(NalUnit {
+            nal_ref_idc,
+            nal_unit_type,
+        })
+    }
+
+    /// Returns `true` if `nal_ref_idc` is consistent with `nal_unit_type`.
+    ///
+    /// See `validate()` for the checked combinations.
+    pub fn is_consistent(&self) -> bool {
+        self.validate().is_ok()
+    }
+
+    /// Checks that `nal_ref_idc` is consistent with `nal_unit_type` (7.4.1, ISO/IEC 14496-10).
+    ///
+    /// The following combinations are rejected:
+    /// - IDR slices, SPSs, SPS extensions, subset SPSs and PPSs with `nal_ref_idc == 0`
+    /// - SEIs, access unit delimiters, end of sequence/stream and filler data
+    ///   with `nal_ref_idc != 0`
+    pub fn validate(&self) -> Result<()> {
+        match self.nal_unit_type {
+            NalUnitType::CodedSliceOfAnIdrPicture
+            | NalUnitType::SequenceParameterSet
+            | NalUnitType::SequenceParameterSetExtension
+            | NalUnitType::SubsetSequenceParameterSet
+            | NalUnitType::PictureParameterSet => {
+                track_assert_ne!(
+                    self.nal_ref_idc,
+                    0,
+                    ErrorKind::InvalidInput,
+                    "nal_ref_idc must not be 0 for {:?}",
+                    self.nal_unit_type
+                );
+            }
+            NalUnitType::SupplementalEnhancementInformation
+            | NalUnitType::AccessUnitDelimiter
+            | NalUnitType::EndOfSequence
+            | NalUnitType::EndOfStream
+            | NalUnitType::FilterData => {
+                track_assert_eq!(
+                    self.nal_ref_idc,
+                    0,
+                    ErrorKind::InvalidInput,
+                    "nal_ref_idc must be 0 for {:?}",
+                    self.nal_unit_type
+                );
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// The NAL unit header extension of prefix NAL units and coded slice extensions
+/// (G.7.3.1.1 and H.7.3.1.1, ISO/IEC 14496-10).
+///
+/// Only the fields common to the SVC and MVC extensions are kept.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefixNalUnit {
+    /// `svc_extension_flag` (`false` indicates the MVC extension).
+    pub svc_extension_flag: bool,
+
+    /// `idr_flag` of the SVC extension, or the negation of `non_idr_flag` of the MVC extension.
+    pub idr_flag: bool,
+    pub priority_id: u8,
+    pub temporal_id: u8,
+}
+impl PrefixNalUnit {
+    /// Reads the header extension of the given prefix NAL unit (`nal_unit_type` `14`)
+    /// or coded slice extension (`nal_unit_type` `20`), including its NAL unit header.
+    pub fn read_from(nal_unit: &[u8]) -> Result<Self> {
+        track_assert!(nal_unit.len() >= 4, ErrorKind::InvalidInput);
+        let nal_unit_type = track!(nal_unit_type_of(nal_unit[0]))?;
+        track_assert!(
+            nal_unit_type == NalUnitType::PrefixNalUnit
+                || nal_unit_type == NalUnitType::CodedSliceExtension,
+            ErrorKind::InvalidInput,
+            "Unexpected NAL unit type: {:?}",
+            nal_unit_type
+        );
+
+        let mut reader = AvcBitReader::new(&nal_unit[1..4]);
+        let svc_extension_flag = track!(reader.read_bit())? == 1;
+        if svc_extension_flag {
+            let idr_flag = track!(reader.read_bit())? == 1;
+            let priority_id = track!(reader.read_bits(6))? as u8;
+            let _no_inter_layer_pred_flag = track!(reader.read_bit())?;
+            let _dependency_id = track!(reader.read_bits(3))?;
+            let _quality_id = track!(reader.read_bits(4))?;
+            let temporal_id = track!(reader.read_bits(3))? as u8;
+            Ok(PrefixNalUnit {
+                svc_extension_flag,
+                idr_flag,
+                priority_id,
+                temporal_id,
+            })
+        } else {
+            let non_idr_flag = track!(reader.read_bit())? == 1;
+            let priority_id = track!(reader.read_bits(6))? as u8;
+            let _view_id = track!(reader.read_bits(10))?;
+            let temporal_id = track!(reader.read_bits(3))? as u8;
+            Ok(PrefixNalUnit {
+                svc_extension_flag,
+                idr_flag: !non_idr_flag,
+                priority_id,
+                temporal_id,
+            })
+        }
+    }
+}
+
+/// Returns the `nal_unit_type` of the NAL unit whose header byte is `b`.
+///
+/// Unlike `NalUnit::read_from`, this only looks at the low five bits of the byte.
+pub fn nal_unit_type_of(b: u8) -> Result<NalUnitType> {
+    track!(NalUnitType::from_u8(b & 0b1_1111))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NalUnitType {
+    CodedSliceOfANonIdrPicture = 1,
+    CodedSliceDataPartitionA = 2,
+    CodedSliceDataPartitionB = 3,
+    CodedSliceDataPartitionC = 4,
+    CodedSliceOfAnIdrPicture = 5,
+    SupplementalEnhancementInformation = 6,
+    SequenceParameterSet = 7,
+    PictureParameterSet = 8,
+    AccessUnitDelimiter = 9,
+    EndOfSequence = 10,
+    EndOfStream = 11,
+    FilterData = 12,
+    SequenceParameterSetExtension = 13,
+    PrefixNalUnit = 14,
+    SubsetSequenceParameterSet = 15,
+    CodedSliceOfAnAuxiliaryCodedPictureWithoutPartitioning = 19,
+    CodedSliceExtension = 20,
+    CodedSliceExtensionForDepthViewComponents = 21,
+}
+impl NalUnitType {
+    /// Filler data (the spec term for `FilterData`).
+    #[allow(non_upper_case_globals)]
+    pub const FillerData: NalUnitType = NalUnitType::FilterData;
+
+    fn from_u8(n: u8) -> Result<Self> {
+        Ok(track_assert_some!(
+            Self::try_from_u8(n),
+            ErrorKind::InvalidInput,
+            "Unknown NAL unit type: {}",
+            n
+        ))
+    }
+
+    /// Converts `n` into a `NalUnitType`, or returns `None` if `n` is unspecified or reserved.
+    pub fn try_from_u8(n: u8) -> Option<Self> {
+        Some(match n {
+            1 => NalUnitType::CodedSliceOfANonIdrPicture,
+            2 => NalUnitType::CodedSliceDataPartitionA,
+            3 => NalUnitType::CodedSliceDataPartitionB,
+            4 => NalUnitType::CodedSliceDataPartitionC,
+            5 => NalUnitType::CodedSliceOfAnIdrPicture,
+            6 => NalUnitType::SupplementalEnhancementInformation,
+            7 => NalUnitType::SequenceParameterSet,
+            8 => NalUnitType::PictureParameterSet,
+            9 => NalUnitType::AccessUnitDelimiter,
+            10 => NalUnitType::EndOfSequence,
+            11 => NalUnitType::EndOfStream,
+            12 => NalUnitType::FillerData,
+            13 => NalUnitType::SequenceParameterSetExtension,
+            14 => NalUnitType::PrefixNalUnit,
+            15 => NalUnitType::SubsetSequenceParameterSet,
+            19 => NalUnitType::CodedSliceOfAnAuxiliaryCodedPictureWithoutPartitioning,
+            20 => NalUnitType::CodedSliceExtension,
+            21 => NalUnitType::CodedSliceExtensionForDepthViewComponents,
+            _ => return None,
+        })
+    }
+}
+
+/// Returns the first SPS and PPS NAL units (without start codes) in the given Annex B stream.
+///
+/// NAL units are looked up by their types, so AUDs, SEIs and filler data preceding
+/// the parameter sets are skipped.
+/// This is the same as `find_first_parameter_sets()`.
+pub fn split_parameter_sets(bytes: &[u8]) -> Result<(&[u8], &[u8])> {
+    track!(find_first_parameter_sets(bytes))
+}
+
+/// Returns the first SPS and PPS NAL units (including their headers) in the given Annex B stream
+/// without allocating.
+///
+/// The scan stops as soon as both are found, so the rest of the stream is neither split
+/// nor parsed. The returned NAL units are borrowed from `bytes`.
+pub fn find_first_parameter_sets(bytes: &[u8]) -> Result<(&[u8], &[u8])> {
+    let mut sps = None;
+    let mut pps = None;
+    for nal_unit in track!(ByteStreamFormatNalUnits::new(bytes))? {
+        let nal_unit_type = match nal_unit.first() {
+            Some(&b) => nal_unit_type_of(b).ok(),
+            None => continue,
+        };
+        match nal_unit_type {
+            Some(NalUnitType::SequenceParameterSet) if sps.is_none() => sps = Some(nal_unit),
+            Some(NalUnitType::PictureParameterSet) if pps.is_none() => pps = Some(nal_unit),
+            _ => {}
+        }
+        if sps.is_some() && pps.is_some() {
+            break;
+        }
+    }
+    let sps = track_assert_some!(sps, ErrorKind::InvalidInput, "Missing SPS");
+    let pps = track_assert_some!(pps, ErrorKind::InvalidInput, "Missing PPS");
+    Ok((sps, pps))
+}
+
+/// Returns the byte range of the payload of the given SPS NAL unit (including its header).
+///
+/// The range is in the escaped (on-wire) byte space, i.e., it may contain emulation
+/// prevention bytes. It starts after the NAL unit header and ends after the last non-zero
+/// byte, so trailing zero bytes are excluded while the byte containing the
+/// `rbsp_stop_one_bit` is included (it may also hold the last bits of the payload).
+pub fn sps_payload_range(nal_unit: &[u8]) -> Result<Range<usize>> {
+    let header = track!(NalUnit::read_from(nal_unit))?;
+    track_assert_eq!(
+        header.nal_unit_type,
+        NalUnitType::SequenceParameterSet,
+        ErrorKind::InvalidInput
+    );
+    let end = track_assert_some!(
+        nal_unit.iter().rposition(|&b| b != 0),
+        ErrorKind::InvalidInput
+    ) + 1;
+    track_assert!(end > 1, ErrorKind::InvalidInput, "Empty SPS payload");
+    Ok(1..end)
+}
+
+/// Returns the payload of the given SPS NAL unit (including its header) as RBSP bytes.
+///
+/// This is the unescaped variant of `sps_payload_range()`;
+/// the bytes in the range are returned with the emulation prevention bytes removed.
+pub fn sps_rbsp_payload(nal_unit: &[u8]) -> Result<Vec<u8>> {
+    let range = track!(sps_payload_range(nal_unit))?;
+    let mut rbsp = Vec::with_capacity(range.len());
+    track_io!(RbspReader::new(&nal_unit[range]).read_to_end(&mut rbsp))?;
+    Ok(rbsp)
+}
+
+/// Returns a copy of the given Annex B stream without the NAL units of the given types.
+///
+/// Each of the remaining NAL units is prefixed with a four bytes start code.
+pub fn strip_nal_types(bytes: &[u8], types: &[NalUnitType]) -> Result<Vec<u8>> {
+    let mut stripped = Vec::with_capacity(bytes.len());
+    for nal_unit in track!(ByteStreamFormatNalUnits::new(bytes))? {
+        if let Some(&b) = nal_unit.first() {
+            if let Ok(nal_unit_type) = nal_unit_type_of(b) {
+                if types.contains(&nal_unit_type) {
+                    continue;
+                }
+            }
+        }
+        stripped.extend_from_slice(&[0, 0, 0, 1]);
+        stripped.extend_from_slice(nal_unit);
+    }
+    Ok(stripped)
+}
+
+/// Returns the NAL units of the given Annex B stream, each paired with its `temporal_id`.
+///
+/// The `temporal_id` of a prefix NAL unit or a coded slice extension is read from
+/// its own header extension.
+/// Since base layer NAL unit headers do not carry `temporal_id`, a base layer slice takes
+/// the `temporal_id` of the prefix NAL unit immediately preceding it, or `0` if there is none.
+/// The other NAL units (e.g., parameter sets and SEIs) are not associated with a temporal layer
+/// and are paired with `None`.
+pub fn nal_units_with_temporal_id(bytes: &[u8]) -> Result<Vec<(&[u8], Option<u8>)>> {
+    let mut nal_units = Vec::new();
+    let mut prefix_temporal_id = None;
+    for nal_unit in track!(ByteStreamFormatNalUnits::new(bytes))? {
+        let nal_unit_type = match nal_unit.first() {
+            Some(&b) => nal_unit_type_of(b).ok(),
+            None => None,
+        };
+        let temporal_id = match nal_unit_type {
+            Some(NalUnitType::PrefixNalUnit) | Some(NalUnitType::CodedSliceExtension) => {
+                let header = track!(PrefixNalUnit::read_from(nal_unit))?;
+                Some(header.temporal_id)
+            }
+            Some(NalUnitType::CodedSliceOfANonIdrPicture)
+            | Some(NalUnitType::CodedSliceDataPartitionA)
+            | Some(NalUnitType::CodedSliceDataPartitionB)
+            | Some(NalUnitType::CodedSliceDataPartitionC)
+            | Some(NalUnitType::CodedSliceOfAnIdrPicture) => Some(prefix_temporal_id.unwrap_or(0)),
+            _ => None,
+        };
+        prefix_temporal_id = if nal_unit_type == Some(NalUnitType::PrefixNalUnit) {
+            temporal_id
+        } else {
+            None
+        };
+        nal_units.push((nal_unit, temporal_id));
+    }
+    Ok(nal_units)
+}
+
+/// Returns a copy of the given Annex B stream without the NAL units
+/// whose `temporal_id` exceeds `max_temporal_id`.
+///
+/// The `temporal_id`s are determined as `nal_units_with_temporal_id()` does;
+/// hence prefix NAL units are assumed to precede their associated base layer slices.
+/// The NAL units not associated with a temporal layer are kept.
+///
+/// Each of the remaining NAL units is prefixed with a four bytes start code.
+pub fn filter_max_temporal_id(bytes: &[u8], max_temporal_id: u8) -> Result<Vec<u8>> {
+    let mut filtered = Vec::with_capacity(bytes.len());
+    for (nal_unit, temporal_id) in track!(nal_units_with_temporal_id(bytes))? {
+        if !matches!(temporal_id, Some(x) if x > max_temporal_id) {
+            filtered.extend_from_slice(&[0, 0, 0, 1]);
+            filtered.extend_from_slice(nal_unit);
+        }
+    }
+    Ok(filtered)
+}
+
+/// Returns a copy of the given Annex B stream without the SEI messages of the given payload types.
+///
+/// Unlike `strip_nal_types()`, this removes individual messages from SEI NAL units;
+/// the other messages in the same NAL units (e.g., captions) are kept.
+/// An SEI NAL unit is dropped only if all of its messages are removed.
+/// For example, `&[RecoveryPoint::PAYLOAD_TYPE]` strips the `recovery_point` SEIs
+/// of an open-GOP stream to be treated as closed-GOP.
+///
+/// Each of the resulting NAL units is prefixed with a four bytes start code.
+pub fn strip_sei_messages(bytes: &[u8], payload_types: &[u32]) -> Result<Vec<u8>> {
+    let mut stripped = Vec::with_capacity(bytes.len());
+    for nal_unit in track!(ByteStreamFormatNalUnits::new(bytes))? {
+        let is_sei = match nal_unit.first() {
+            Some(&b) => {
+                nal_unit_type_of(b).ok() == Some(NalUnitType::SupplementalEnhancementInformation)
+            }
+            None => false,
+        };
+        if !is_sei {
+            stripped.extend_from_slice(&[0, 0, 0, 1]);
+            stripped.extend_from_slice(nal_unit);
+            continue;
+        }
+
+        let mut rbsp = Vec::new();
+        track_io!(RbspReader::new(&nal_unit[1..]).read_to_end(&mut rbsp))?;
+        let mut messages = Vec::new();
+        let mut is_modified = false;
+        for message in SeiMessages::new(&rbsp) {
+            let message = track!(message)?;
+            if payload_types.contains(&message.payload_type) {
+                is_modified = true;
+            } else {
+                messages.push(message);
+            }
+        }
+        if !is_modified {
+            stripped.extend_from_slice(&[0, 0, 0, 1]);
+            stripped.extend_from_slice(nal_unit);
+            continue;
+        }
+        if messages.is_empty() {
+            continue;
+        }
+
+        let mut new_rbsp = Vec::with_capacity(rbsp.len());
+        for message in messages {
+            track!(message.write_to(&mut new_rbsp))?;
+        }
+        new_rbsp.push(0x80); // rbsp_trailing_bits
+        stripped.extend_from_slice(&[0, 0, 0, 1]);
+        stripped.push(nal_unit[0]);
+        stripped.extend_from_slice(&escape_rbsp(&new_rbsp));
+    }
+    Ok(stripped)
+}
+
+/// Rebuilds the given Annex B stream so that its parameter sets are placed as `mode` requires.
+///
+/// The in-band SPSs and PPSs of the stream are removed in both modes.
+/// If `mode` is `SampleEntryMode::InBand`, `sps` and `pps` (NAL units including their headers)
+/// are inserted at the beginning of every access unit containing an IDR picture
+/// (following the access unit delimiter if present).
+/// Empty `sps` or `pps` are skipped, as in `parameter_sets_annexb()`.
+pub fn normalize_parameter_sets(
+    bytes: &[u8],
+    mode: SampleEntryMode,
+    sps: &[u8],
+    pps: &[u8],
+) -> Result<Vec<u8>> {
+    let type_of = |nal_unit: &[u8]| nal_unit.first().and_then(|&b| nal_unit_type_of(b).ok());
+    let is_parameter_set = |nal_unit: &[u8]| {
+        matches!(
+            type_of(nal_unit),
+            Some(NalUnitType::SequenceParameterSet) | Some(NalUnitType::PictureParameterSet)
+        )
+    };
+
+    let mut writer = AnnexBWriter::new(Vec::with_capacity(bytes.len()));
+    for access_unit in track!(AccessUnits::new(bytes))? {
+        let mut nal_units = access_unit
+            .iter()
+            .filter(|nal_unit| !is_parameter_set(nal_unit))
+            .peekable();
+        let has_idr = access_unit
+            .iter()
+            .any(|nal_unit| type_of(nal_unit) == Some(NalUnitType::CodedSliceOfAnIdrPicture));
+        if mode == SampleEntryMode::InBand && has_idr {
+            if let Some(nal_unit) = nal_units.peek() {
+                if type_of(nal_unit) == Some(NalUnitType::AccessUnitDelimiter) {
+                    track!(writer.write_nal_unit(nal_unit))?;
+                    nal_units.next();
+                }
+            }
+            for nal_unit in [sps, pps].iter().filter(|x| !x.is_empty()) {
+                track!(writer.write_nal_unit(nal_unit))?;
+            }
+        }
+        for nal_unit in nal_units {
+            track!(writer.write_nal_unit(nal_unit))?;
+        }
+    }
+    Ok(writer.into_inner())
+}
+
+/// Returns the total size of the filler data NAL units (excluding start codes)
+/// in the given Annex B stream.
+pub fn filler_bytes(bytes: &[u8]) -> usize {
+    let nal_units = match ByteStreamFormatNalUnits::new(bytes) {
+        Ok(nal_units) => nal_units,
+        Err(_) => return 0,
+    };
+    nal_units
+        .filter(|nal_unit| match nal_unit.first() {
+            Some(&b) => nal_unit_type_of(b).ok() == Some(NalUnitType::FillerData),
+            None => false,
+        })
+        .map(|nal_unit| nal_unit.len())
+        .sum()
+}
+
+/// Returns `true` if the given Annex B stream contains a B slice.
+///
+/// The scan stops at the first B slice.
+pub fn has_b_frames(bytes: &[u8]) -> Result<bool> {
+    for nal_unit in track!(ByteStreamFormatNalUnits::new(bytes))? {
+        let nal_unit_type = match nal_unit.first() {
+            Some(&b) => nal_unit_type_of(b).ok(),
+            None => continue,
+        };
+        match nal_unit_type {
+            Some(NalUnitType::CodedSliceOfANonIdrPicture)
+            | Some(NalUnitType::CodedSliceOfAnIdrPicture) => {
+                let slice_header = track!(SliceHeaderSummary::read_from(&nal_unit[1..]))?;
+                if slice_header.slice_type == SliceType::B {
+                    return Ok(true);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(false)
+}
+
+/// Returns `true` if every PPS in the given Annex B stream uses CAVLC
+/// (i.e., `entropy_coding_mode_flag` is `0`).
+///
+/// All PPSs are examined, including the ones not referred by any slice.
+/// `false` is returned if the stream contains no PPS.
+pub fn is_cavlc_only(bytes: &[u8]) -> Result<bool> {
+    let mut has_pps = false;
+    for nal_unit in track!(ByteStreamFormatNalUnits::new(bytes))? {
+        let nal_unit_type = match nal_unit.first() {
+            Some(&b) => nal_unit_type_of(b).ok(),
+            None => continue,
+        };
+        if nal_unit_type == Some(NalUnitType::PictureParameterSet) {
+            let pps = track!(PpsSummary::read_from(&nal_unit[1..]))?;
+            if pps.entropy_coding_mode_flag {
+                return Ok(false);
+            }
+            has_pps = true;
+        }
+    }
+    Ok(has_pps)
+}
+
+/// Builds a minimal SPS and PPS (NAL units including their headers) for the given
+/// resolution and profile.
+///
+/// Only the Baseline (`66`) and Main (`77`) profiles are supported.
+/// Both parameter sets have the id `0`, the pictures are progressive 4:2:0 frames
+/// and the PPS uses CAVLC. If `width` or `height` is not a multiple of 16,
+/// the right and bottom edges are cropped so that the resulting SPS reports exactly
+/// the given dimensions, which must therefore be even.
+pub fn synthesize_parameter_sets(
+    width: usize,
+    height: usize,
+    profile_idc: u8,
+    level_idc: u8,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let constraint_set_flag = match profile_idc {
+        66 => 0b1100_0000, // Constrained Baseline
+        77 => 0b0100_0000,
+        _ => track_panic!(
+            ErrorKind::Unsupported,
+            "Unsupported profile: profile_idc={}",
+            profile_idc
+        ),
+    };
+    track_assert!(
+        width > 0 && height > 0,
+        ErrorKind::InvalidInput,
+        "width={}, height={}",
+        width,
+        height
+    );
+    track_assert!(
+        (width | height) & 1 == 0,
+        ErrorKind::InvalidInput,
+        "Odd dimensions cannot be cropped to: width={}, height={}",
+        width,
+        height
+    );
+
+    // The cropping unit is two luma samples in both directions (4:2:0 and `frame_mbs_only_flag`).
+    let width_in_mbs = (width - 1) / 16 + 1;
+    let height_in_mbs = (height - 1) / 16 + 1;
+    let crop_right = (width_in_mbs * 16 - width) / 2;
+    let crop_bottom = (height_in_mbs * 16 - height) / 2;
+    let sps = SpsSummary {
+        profile_idc,
+        constraint_set_flag,
+        level_idc,
+        seq_parameter_set_id: 0,
+        log2_max_frame_num_minus4: 0,
+        pic_order_cnt: PicOrderCnt::Type0 {
+            log2_max_pic_order_cnt_lsb_minus4: 2,
+        },
+        max_num_ref_frames: 1,
+        gaps_in_frame_num_value_allowed_flag: false,
+        pic_width_in_mbs_minus_1: width_in_mbs as u64 - 1,
+        pic_height_in_map_units_minus_1: height_in_mbs as u64 - 1,
+        frame_mbs_only_flag: 1,
+        mb_adaptive_frame_field_flag: false,
+        direct_8x8_inference_flag: true,
+        frame_cropping_flag: crop_right != 0 || crop_bottom != 0,
+        frame_crop_left_offset: 0,
+        frame_crop_right_offset: crop_right as u64,
+        frame_crop_top_offset: 0,
+        frame_crop_bottom_offset: crop_bottom as u64,
+        extended_configuration_data: None,
+        vui_parameters: None,
+        preserved_rbsp: None,
+    };
+    let mut sps_nal_unit = vec![0x67]; // nal_ref_idc=3, nal_unit_type=7
+    track!(sps.write_to(&mut sps_nal_unit))?;
+
+    let mut rbsp = Vec::new();
+    {
+        let mut writer = AvcBitWriter::new(&mut rbsp);
+        track!(writer.write_ue(0))?; // pic_parameter_set_id
+        track!(writer.write_ue(0))?; // seq_parameter_set_id
+        track!(writer.write_bool(false))?; // entropy_coding_mode_flag
+        track!(writer.write_bool(false))?; // bottom_field_pic_order_in_frame_present_flag
+        track!(writer.write_ue(0))?; // num_slice_groups_minus1
+        track!(writer.write_ue(0))?; // num_ref_idx_l0_default_active_minus1
+        track!(writer.write_ue(0))?; // num_ref_idx_l1_default_active_minus1
+        track!(writer.write_bool(false))?; // weighted_pred_flag
+        track!(writer.write_bits(2, 0))?; // weighted_bipred_idc
+        track!(writer.write_se(0))?; // pic_init_qp_minus26
+        track!(writer.write_se(0))?; // pic_init_qs_minus26
+        track!(writer.write_se(0))?; // chroma_qp_index_offset
+        track!(writer.write_bool(true))?; // deblocking_filter_control_present_flag
+        track!(writer.write_bool(false))?; // constrained_intra_pred_flag
+        track!(writer.write_bool(false))?; // redundant_pic_cnt_present_flag
+        track!(writer.write_rbsp_trailing_bits())?;
+    }
+    let mut pps_nal_unit = vec![0x68]; // nal_ref_idc=3, nal_unit_type=8
+    pps_nal_unit.extend_from_slice(&escape_rbsp(&rbsp));
+    Ok((sps_nal_unit, pps_nal_unit))
+}
+
+/// A table of the SPSs and PPSs seen in a stream, keyed by their ids.
+///
+/// A parameter set replaces the previously ingested one that has the same id.
+#[derive(Debug, Default)]
+pub struct ParameterSetStore {
+    sps: HashMap<u64, (Vec<u8>, SpsSummary)>,
+    pps: HashMap<u64, (Vec<u8>, PpsSummary)>,
+    changed: bool,
+}
+impl ParameterSetStore {
+    /// Makes a new empty `ParameterSetStore` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses and stores the given NAL unit (including its NAL unit header)
+    /// if it is an SPS or a PPS.
+    ///
+    /// The other NAL units are ignored.
+    pub fn ingest(&mut self, nal_unit: &[u8]) -> Result<()> {
+        let nal_unit_type = match nal_unit.first() {
+            Some(&b) => nal_unit_type_of(b).ok(),
+            None => return Ok(()),
+        };
+        match nal_unit_type {
+            Some(NalUnitType::SequenceParameterSet) => {
+                let sps = track!(SpsSummary::read_from(&nal_unit[1..]))?;
+                let id = sps.seq_parameter_set_id;
+                if self.sps.get(&id).map(|x| &x.0[..]) != Some(nal_unit) {
+                    self.sps.insert(id, (nal_unit.to_owned(), sps));
+                    self.changed = true;
+                }
+            }
+            Some(NalUnitType::PictureParameterSet) => {
+                let pps = track!(PpsSummary::read_from(&nal_unit[1..]))?;
+                let id = pps.pic_parameter_set_id;
+                if self.pps.get(&id).map(|x| &x.0[..]) != Some(nal_unit) {
+                    self.pps.insert(id, (nal_unit.to_owned(), pps));
+                    self.changed = true;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Returns the SPS whose `seq_parameter_set_id` is `id`.
+    pub fn active_sps(&self, id: u64) -> Option<&SpsSummary> {
+        self.sps.get(&id).map(|x| &x.1)
+    }
+
+    /// Returns the PPS whose `pic_parameter_set_id` is `id`.
+    pub fn active_pps(&self, id: u64) -> Option<&PpsSummary> {
+        self.pps.get(&id).map(|x| &x.1)
+    }
+
+    /// Returns the SPS NAL unit whose `seq_parameter_set_id` is `id`.
+    pub fn sps_nal_unit(&self, id: u64) -> Option<&[u8]> {
+        self.sps.get(&id).map(|x| &x.0[..])
+    }
+
+    /// Returns the PPS NAL unit whose `pic_parameter_set_id` is `id`.
+    pub fn pps_nal_unit(&self, id: u64) -> Option<&[u8]> {
+        self.pps.get(&id).map(|x| &x.0[..])
+    }
+
+    /// Returns `true` if a new or different parameter set has been ingested
+    /// since the last call of `start_fragment()`.
+    pub fn changed_since_last_fragment(&self) -> bool {
+        self.changed
+    }
+
+    /// Marks the beginning of a new fragment, which resets `changed_since_last_fragment()`.
+    pub fn start_fragment(&mut self) {
+        self.changed = false;
+    }
+}
+
+/// Returns `true` if the given Annex B stream contains an access unit delimiter.
+pub fn uses_access_unit_delimiters(bytes: &[u8]) -> bool {
+    let mut nal_units = match ByteStreamFormatNalUnits::new(bytes) {
+        Ok(nal_units) => nal_units,
+        Err(_) => return false,
+    };
+    nal_units.any(|nal_unit| match nal_unit.first() {
+        Some(&b) => nal_unit_type_of(b).ok() == Some(NalUnitType::AccessUnitDelimiter),
+        None => false,
+    })
+}
+
+/// An iterator over the access units of an Annex B stream.
+///
+/// Each item is the list of the NAL units (without start codes) of an access unit.
+///
+/// If the stream uses access unit delimiters, the access units are split at them.
+/// Otherwise, or if some access units lack a delimiter (i.e., a mixed stream),
+/// the boundaries are detected from the NAL unit types and the slice headers (7.4.1.2.3).
+///
+/// Once the SPS and PPS referred by a slice have been seen, the slice is compared with
+/// the preceding one (`frame_num`, `pic_parameter_set_id`, `idr_pic_id` and so on)
+/// to detect the first slice of a picture (7.4.1.2.4).
+/// Otherwise, only `first_mb_in_slice` is examined.
+#[derive(Debug)]
+pub struct AccessUnits<'a> {
+    access_units: std::vec::IntoIter<Vec<&'a [u8]>>,
+}
+impl<'a> AccessUnits<'a> {
+    /// Makes a new `AccessUnits` instance.
+    pub fn new(bytes: &'a [u8]) -> Result<Self> {
+        let nal_units = track!(ByteStreamFormatNalUnits::new(bytes))?.collect::<Vec<_>>();
+        let mut access_units = None;
+        if uses_access_unit_delimiters(bytes) {
+            let units = split_at_access_unit_delimiters(&nal_units);
+            let mut detector = PictureBoundaryDetector::default();
+            let mut is_consistent = true;
+            for access_unit in &units {
+                let mut pictures = 0;
+                for nal_unit in access_unit {
+                    if track!(detector.is_first_slice_of_picture(nal_unit))? {
+                        pictures += 1;
+                    }
+                }
+                is_consistent &= pictures <= 1;
+            }
+            if is_consistent {
+                access_units = Some(units);
+            }
+        }
+        let access_units = match access_units {
+            Some(units) => units,
+            None => track!(split_at_slice_headers(&nal_units))?,
+        };
+        Ok(AccessUnits {
+            access_units: access_units.into_iter(),
+        })
+    }
+}
+impl<'a> Iterator for AccessUnits<'a> {
+    type Item = Vec<&'a [u8]>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.access_units.next()
+    }
+}
+
+fn split_at_access_unit_delimiters<'a>(nal_units: &[&'a [u8]]) -> Vec<Vec<&'a [u8]>> {
+    let mut access_units = Vec::new();
+    let mut current = Vec::new();
+    let mut has_slice = false;
+    for &nal_unit in nal_units {
+        let nal_unit_type = nal_unit.first().and_then(|&b| nal_unit_type_of(b).ok());
+        if nal_unit_type == Some(NalUnitType::AccessUnitDelimiter) && has_slice {
+            access_units.push(current);
+            current = Vec::new();
+            has_slice = false;
+        }
+        has_slice |= is_primary_slice(nal_unit_type);
+        current.push(nal_unit);
+    }
+    if !current.is_empty() {
+        access_units.push(current);
+    }
+    access_units
+}
+
+fn split_at_slice_headers<'a>(nal_units: &[&'a [u8]]) -> Result<Vec<Vec<&'a [u8]>>> {
+    let mut detector = PictureBoundaryDetector::default();
+    let mut access_units = Vec::new();
+    let mut current = Vec::new();
+    let mut has_slice = false;
+    for &nal_unit in nal_units {
+        let nal_unit_type = nal_unit.first().and_then(|&b| nal_unit_type_of(b).ok());
+        let is_first_slice = track!(detector.is_first_slice_of_picture(nal_unit))?;
+        let starts_access_unit = match nal_unit_type {
+            Some(NalUnitType::AccessUnitDelimiter)
+            | Some(NalUnitType::SequenceParameterSet)
+            | Some(NalUnitType::PictureParameterSet)
+            | Some(NalUnitType::SupplementalEnhancementInformation)
+            | Some(NalUnitType::PrefixNalUnit)
+            | Some(NalUnitType::SubsetSequenceParameterSet) => true,
+            _ => is_first_slice,
+        };
+        if starts_access_unit && has_slice {
+            access_units.push(current);
+            current = Vec::new();
+            has_slice = false;
+        }
+        has_slice |= is_primary_slice(nal_unit_type);
+        current.push(nal_unit);
+    }
+    if !current.is_empty() {
+        access_units.push(current);
+    }
+    Ok(access_units)
+}
+
+fn is_primary_slice(nal_unit_type: Option<NalUnitType>) -> bool {
+    matches!(
+        nal_unit_type,
+        Some(NalUnitType::CodedSliceOfANonIdrPicture) | Some(NalUnitType::CodedSliceOfAnIdrPicture)
+    )
+}
+
+/// Detects the first slices of primary coded pictures (7.4.1.2.4, ISO/IEC 14496-10).
+#[derive(Debug, Default)]
+struct PictureBoundaryDetector {
+    parameter_sets: ParameterSetStore,
+    prev_slice: Option<(NalUnit, SliceHeaderSummary)>,
+}
+impl PictureBoundaryDetector {
+    fn is_first_slice_of_picture(&mut self, nal_unit: &[u8]) -> Result<bool> {
+        // A malformed parameter set leaves the slices referring to it to `first_mb_in_slice`.
+        let _ = self.parameter_sets.ingest(nal_unit);
+        let header = match NalUnit::read_from(nal_unit) {
+            Ok(header) if is_primary_slice(Some(header.nal_unit_type)) => header,
+            _ => return Ok(false),
+        };
+        let slice_header = track!(SliceHeaderSummary::read_from(&nal_unit[1..]))?;
+        let pps = self.parameter_sets.active_pps(slice_header.pic_parameter_set_id);
+        let sps = pps.and_then(|pps| self.parameter_sets.active_sps(pps.seq_parameter_set_id));
+        let full_slice_header = match (sps, pps) {
+            // Falls back to `first_mb_in_slice` if the header is malformed.
+            (Some(sps), Some(pps)) => SliceHeaderSummary::read_with_parameter_sets(
+                &nal_unit[1..],
+                header.nal_unit_type,
+                sps,
+                pps,
+            )
+            .ok(),
+            _ => None,
+        };
+        let full_slice_header = match full_slice_header {
+            Some(x) => x,
+            None => {
+                self.prev_slice = None;
+                return Ok(slice_header.first_mb_in_slice == 0);
+            }
+        };
+
+        let is_first = slice_header.first_mb_in_slice == 0
+            || match self.prev_slice {
+                Some((ref prev_header, ref prev)) => {
+                    is_different_picture(prev_header, prev, &header, &full_slice_header)
+                }
+                None => true,
+            };
+        self.prev_slice = Some((header, full_slice_header));
+        Ok(is_first)
+    }
+}
+
+fn is_different_picture(
+    prev_nal_unit: &NalUnit,
+    prev: &SliceHeaderSummary,
+    nal_unit: &NalUnit,
+    current: &SliceHeaderSummary,
+) -> bool {
+    let is_idr = |x: &NalUnit| x.nal_unit_type == NalUnitType::CodedSliceOfAnIdrPicture;
+    prev.frame_num != current.frame_num
+        || prev.pic_parameter_set_id != current.pic_parameter_set_id
+        || prev.field_pic_flag != current.field_pic_flag
+        || prev.bottom_field_flag != current.bottom_field_flag
+        || (prev_nal_unit.nal_ref_idc == 0) != (nal_unit.nal_ref_idc == 0)
+        || prev.pic_order_cnt_lsb != current.pic_order_cnt_lsb
+        || prev.delta_pic_order_cnt_bottom != current.delta_pic_order_cnt_bottom
+        || prev.delta_pic_order_cnt != current.delta_pic_order_cnt
+        || is_idr(prev_nal_unit) != is_idr(nal_unit)
+        || prev.idr_pic_id != current.idr_pic_id
+}
+
+/// Makes an `avcC` box (including the box header) from the SPS and PPS in the given Annex B stream.
+pub fn avcc_box_from_annexb(bytes: &[u8]) -> Result<Vec<u8>> {
+    let (sps, pps) = track!(split_parameter_sets(bytes))?;
+    let avcc_box = AvcConfigurationBox {
+        configuration: track!(AvcDecoderConfigurationRecord::from_parameter_sets(sps, pps))?,
+    };
+    let mut buf = Vec::new();
+    write_box!(buf, avcc_box);
+    Ok(buf)
+}
+
+/// A sample entry update returned by `AvcDecoderConfigurationRecord::sample_entry_update()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleEntryUpdate {
+    /// The `avcC` box (including the box header) of the new record.
+    pub avcc_box: Vec<u8>,
+
+    /// Whether `SourceBuffer.changeType()` is required before appending
+    /// the new initialization segment.
+    pub requires_change_type: bool,
+}
+
+/// A writer of NAL units in the Annex B byte stream format.
+///
+/// By default, the first NAL unit is prefixed with a four bytes start code
+/// and the following ones are prefixed with three bytes start codes.
+#[derive(Debug)]
+pub struct AnnexBWriter<W> {
+    writer: W,
+    four_byte_start_codes: bool,
+    is_first: bool,
+}
+impl<W: Write> AnnexBWriter<W> {
+    /// Makes a new `AnnexBWriter` instance.
+    pub fn new(writer: W) -> Self {
+        AnnexBWriter {
+            writer,
+            four_byte_start_codes: false,
+            is_first: true,
+        }
+    }
+
+    /// Makes every NAL unit prefixed with a four bytes start code if `enabled` is `true`.
+    pub fn set_four_byte_start_codes(&mut self, enabled: bool) -> &mut Self {
+        self.four_byte_start_codes = enabled;
+        self
+    }
+
+    /// Writes the given NAL unit (including its NAL unit header) preceded by a start code.
+    pub fn write_nal_unit(&mut self, nal_unit: &[u8]) -> Result<()> {
+        if self.is_first || self.four_byte_start_codes {
+            write_all!(self.writer, &[0, 0, 0, 1]);
+        } else {
+            write_all!(self.writer, &[0, 0, 1]);
+        }
+        write_all!(self.writer, nal_unit);
+        self.is_first = false;
+        Ok(())
+    }
+
+    /// Returns the inner writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[derive(Debug)]
+pub struct ByteStreamFormatNalUnits<'a> {
+    bytes: &'a [u8],
+    is_two_byte_start_code_header: Option<fn(u8) -> bool>,
+}
+impl<'a> ByteStreamFormatNalUnits<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self> {
+        let bytes = if bytes.starts_with(&[0, 0, 1][..]) {
+            &bytes[3..]
+        } else if bytes.starts_with(&[0, 0, 0, 1][..]) {
+            &bytes[4..]
+        } else {
+            track_panic!(ErrorKind::InvalidInput);
+        };
+        Ok(ByteStreamFormatNalUnits {
+            bytes,
+            is_two_byte_start_code_header: None,
+        })
+    }
+
+    /// Makes a new `ByteStreamFormatNalUnits` instance that also splits the stream on
+    /// the two bytes pseudo start codes (`00 01`) emitted by some broken encoders.
+    ///
+    /// `00 01` is taken as a start code only if `is_nal_unit_header` returns `true`
+    /// for the byte following it, and the stream may also begin with it.
+    /// The regular three and four bytes start codes are recognized as usual.
+    ///
+    /// Note that this is a heuristic: unlike `00 00 01`, the sequence `00 01` may legitimately
+    /// appear inside NAL unit payloads, where it causes the NAL unit to be split wrongly
+    /// if the following byte happens to look like a NAL unit header.
+    /// The stricter `is_nal_unit_header` is (e.g., accepting only the NAL unit types
+    /// the encoder is known to emit), the fewer such false positives there are.
+    pub fn new_tolerant(bytes: &'a [u8], is_nal_unit_header: fn(u8) -> bool) -> Result<Self> {
+        let bytes = if bytes.starts_with(&[0, 1][..]) {
+            &bytes[2..]
+        } else {
+            track!(Self::new(bytes))?.bytes
+        };
+        Ok(ByteStreamFormatNalUnits {
+            bytes,
+            is_two_byte_start_code_header: Some(is_nal_unit_header),
+        })
+    }
+}
+impl<'a> Iterator for ByteStreamFormatNalUnits<'a> {
+    type Item = &'a [u8];
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            None
+        } else {
+            let mut nal_unit_end = self.bytes.len();
+            let mut next_start = self.bytes.len();
+            for i in 0..self.bytes.len() {
+                if (&self.bytes[i..]).starts_with(&[0, 0, 0, 1][..]) {
+                    nal_unit_end = i;
+                    next_start = i + 4;
+                    break;
+                } else if (&self.bytes[i..]).starts_with(&[0, 0, 1][..]) {
+                    nal_unit_end = i;
+                    next_start = i + 3;
+                    break;
+                } else if let Some(is_nal_unit_header) = self.is_two_byte_start_code_header {
+                    if self.bytes[i..].starts_with(&[0, 1][..])
+                        && matches!(self.bytes.get(i + 2), Some(&b) if is_nal_unit_header(b))
+                    {
+                        nal_unit_end = i;
+                        next_start = i + 2;
+                        break;
+                    }
+                }
+            }
+            let nal_unit = &self.bytes[..nal_unit_end];
+            self.bytes = &self.bytes[next_start..];
+            Some(nal_unit)
+        }
+    }
+}
+
+/// Iterator over the NAL units of a length-prefixed (i.e., MP4 sample format) byte sequence.
+///
+/// Each NAL unit is preceded by its size encoded as a `length_size` bytes big-endian integer.
+/// The yielded NAL units include their headers (as `ByteStreamFormatNalUnits` does).
+#[derive(Debug)]
+pub struct LengthPrefixedNalUnits<'a> {
+    bytes: &'a [u8],
+    length_size: LengthSize,
+}
+impl<'a> LengthPrefixedNalUnits<'a> {
+    /// Makes a new `LengthPrefixedNalUnits` instance.
+    pub fn new(bytes: &'a [u8], length_size: LengthSize) -> Self {
+        LengthPrefixedNalUnits { bytes, length_size }
+    }
+
+    fn read_nal_unit(&mut self) -> Result<&'a [u8]> {
+        let bytes = self.bytes;
+        self.bytes = &[];
+        track_assert!(
+            bytes.len() >= self.length_size.get(),
+            ErrorKind::InvalidInput,
+            "Truncated NAL unit length: {} bytes remaining",
+            bytes.len()
+        );
+        let (length, rest) = bytes.split_at(self.length_size.get());
+        let length = length.iter().fold(0, |acc, &b| (acc << 8) | usize::from(b));
+        track_assert!(
+            length <= rest.len(),
+            ErrorKind::InvalidInput,
+            "NAL unit length overruns the buffer: length={}, remaining={}",
+            length,
+            rest.len()
+        );
+        let (nal_unit, rest) = rest.split_at(length);
+        self.bytes = rest;
+        Ok(nal_unit)
+    }
+}
+impl<'a> Iterator for LengthPrefixedNalUnits<'a> {
+    type Item = Result<&'a [u8]>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            None
+        } else {
+            Some(track!(self.read_nal_unit()))
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sei::RecoveryPoint;
+
+    // Taken from `movie-init.mp4` (Baseline profile, 720x480, level 3.0).
+    pub(crate) const BASELINE_SPS: [u8; 23] = [
+        0x67, 0x42, 0xe0, 0x1e, 0xda, 0x02, 0xd0, 0xf6, 0xff, 0xc0, 0x02, 0x00, 0x02, 0x44, 0x00,
+        0x00, 0x2e, 0xec, 0x00, 0x0a, 0xfc, 0x82, 0x10,
+    ];
+    pub(crate) const BASELINE_PPS: [u8; 4] = [0x68, 0xce, 0x33, 0xc8];
+
+    // Monochrome High profile, 176x144, level 3.0, with a scaling matrix.
+    const MONOCHROME_SPS: [u8; 15] = [
+        0x67, 0x64, 0x00, 0x1e, 0xf6, 0x10, 0x10, 0x0a, 0x50, 0x88, 0xc2, 0x2b, 0x41, 0x62, 0x72,
+    ];
+
+    // High profile, 1920x1080, level 4.0 (includes emulation prevention bytes).
+    const HIGH_PROFILE_SPS: [u8; 27] = [
+        0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0xc0, 0x44, 0x00, 0x00,
+        0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xf0, 0x3c, 0x60, 0xc6, 0x58,
+    ];
+
+    #[test]
+    fn test_read_sps_vui() {
+        let sps = SpsSummary::read_from(&BASELINE_SPS[1..]).unwrap();
+        assert_eq!(sps.width(), 720);
+        assert_eq!(sps.height(), 480);
+        assert!(!sps.gaps_in_frame_num_value_allowed_flag);
+        assert_eq!(sps.frame_num_bits(), 4);
+
+        let description = sps.describe();
+        assert!(description.contains("profile: Constrained Baseline (66)\n"));
+        assert!(description.contains("level: 3.0 (30)\n"));
+        assert!(description.contains("size: 720x480 (coded 720x480)\n"));
+        assert!(description.contains("chroma: 4:2:0\n"));
+        assert!(description.contains("interlaced: no\n"));
+        assert!(description.contains("  sample aspect ratio: 8:9\n"));
+        assert_eq!(sps.max_frame_num(), 16);
+
+        let vui = sps.vui_parameters.as_ref().unwrap();
+        let aspect_ratio_info = vui.aspect_ratio_info.as_ref().unwrap();
+        assert_eq!(aspect_ratio_info.aspect_ratio_idc, AspectRatioInfo::EXTENDED_SAR);
+        assert_eq!((aspect_ratio_info.sar_width, aspect_ratio_info.sar_height), (8, 9));
+        assert_eq!(sps.display_aspect_ratio(), Some((4, 3)));
+
+        let timing_info = vui.timing_info.as_ref().unwrap();
+        assert_eq!(timing_info.num_units_in_tick, 3003);
+        assert_eq!(timing_info.time_scale, 180_000);
+        assert!(timing_info.fixed_frame_rate_flag);
+        assert!(vui.bitstream_restriction.is_none());
+        assert!(vui.chroma_loc_info.is_none());
+
+        let mut sps = SpsSummary::from_nal_unit(&BASELINE_SPS, false).unwrap();
+        let chroma_loc_info = ChromaLocInfo {
+            chroma_sample_loc_type_top_field: 1,
+            chroma_sample_loc_type_bottom_field: 3,
+        };
+        sps.vui_parameters.as_mut().unwrap().chroma_loc_info = Some(chroma_loc_info.clone());
+        let mut bytes = Vec::new();
+        sps.write_to(&mut bytes).unwrap();
+        let sps = SpsSummary::read_from(&bytes[..]).unwrap();
+        let vui = sps.vui_parameters.unwrap();
+        assert_eq!(vui.chroma_loc_info, Some(chroma_loc_info));
+        assert_eq!(vui.timing_info.unwrap().num_units_in_tick, 3003);
+    }
+
+    #[test]
+    fn test_read_high_profile_sps() {
+        let sps = SpsSummary::read_from(&HIGH_PROFILE_SPS[1..]).unwrap();
+        assert_eq!(sps.width(), 1920);
+        assert_eq!(sps.height(), 1080);
+        assert_eq!(sps.pic_order_cnt_type(), 0);
+        assert_eq!(sps.log2_max_pic_order_cnt_lsb_minus4(), Some(2));
+
+        let extended_configuration_data = sps.extended_configuration_data.as_ref().unwrap();
+        assert_eq!(extended_configuration_data.chroma_format, 1);
+
+        let vui = sps.vui_parameters.as_ref().unwrap();
+        let timing_info = vui.timing_info.as_ref().unwrap();
+        assert_eq!((timing_info.num_units_in_tick, timing_info.time_scale), (1, 60));
+        let bitstream_restriction = vui.bitstream_restriction.as_ref().unwrap();
+        assert_eq!(bitstream_restriction.max_num_reorder_frames, 2);
+        assert_eq!(sps.max_num_reorder_frames(), Some(2));
+        assert_eq!(sps.display_aspect_ratio(), Some((16, 9)));
+        assert_eq!(sps.max_dpb_frames(), 4);
+    }
+
+    #[test]
+    fn test_max_dpb_frames_from_level() {
+        // MaxDpbMbs(level 3.0) / (45 * 30) = 8100 / 1350
+        let sps = SpsSummary::read_from(&BASELINE_SPS[1..]).unwrap();
+        assert_eq!(sps.max_dpb_frames(), 6);
+        assert_eq!(sps.max_num_reorder_frames(), None);
+    }
+
+    #[test]
+    fn test_validate_pps_references_sps() {
+        let sps = SpsSummary::read_from(&BASELINE_SPS[1..]).unwrap();
+        let mut pps = PpsSummary::read_from(&BASELINE_PPS[1..]).unwrap();
+        assert_eq!(pps.seq_parameter_set_id, 0);
+        assert!(validate_pps_references_sps(&pps, &sps).is_ok());
+
+        pps.seq_parameter_set_id = 1;
+        assert!(validate_pps_references_sps(&pps, &sps).is_err());
+    }
+
+    #[test]
+    fn test_read_avc_decoder_configuration_record() {
+        let mut record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        record.length_size = LengthSize::new(2).unwrap();
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+
+        let actual = AvcDecoderConfigurationRecord::read_from(&bytes[..]).unwrap();
+        assert_eq!(actual.length_size.get(), 2);
+        assert_eq!(actual, record);
+
+        let record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&HIGH_PROFILE_SPS, &BASELINE_PPS)
+                .unwrap();
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+
+        let actual = AvcDecoderConfigurationRecord::read_from(&bytes[..]).unwrap();
+        assert_eq!(actual.length_size, LengthSize::FOUR);
+        assert_eq!(actual, record);
+
+        // Declares three SPSs but contains only one
+        let mut corrupted = bytes.clone();
+        corrupted[5] = 0b1110_0011;
+        let e = AvcDecoderConfigurationRecord::read_from(&corrupted[..]).err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+        // Truncated right after the PPS array
+        let pps_array_end = 6 + 2 + HIGH_PROFILE_SPS.len() + 1 + 2 + BASELINE_PPS.len();
+        let actual = AvcDecoderConfigurationRecord::read_from(&bytes[..pps_array_end]).unwrap();
+        assert_eq!(actual.profile_idc, 100);
+        assert_eq!(actual.picture_parameter_set, BASELINE_PPS);
+        assert!(actual.extended_configuration_data.is_none());
+
+        let mut truncated = Vec::new();
+        actual.write_to(&mut truncated).unwrap();
+        assert_eq!(truncated, &bytes[..pps_array_end]);
+        assert_eq!(AvcDecoderConfigurationRecord::read_from(&truncated[..]).unwrap(), actual);
+    }
+
+    #[test]
+    fn test_avc_decoder_configuration_record_without_pps() {
+        let record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &[]).unwrap();
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 6 + 2 + BASELINE_SPS.len() + 1);
+        assert_eq!(bytes[bytes.len() - 1], 0); // num_of_picture_parameter_sets
+
+        let actual = AvcDecoderConfigurationRecord::read_from(&bytes[..]).unwrap();
+        assert!(actual.picture_parameter_set.is_empty());
+        assert_eq!(actual, record);
+        assert_eq!(
+            actual.parameter_sets_annexb(),
+            [&[0, 0, 0, 1][..], &BASELINE_SPS].concat()
+        );
+    }
+
+    #[test]
+    fn test_avc_decoder_configuration_record_round_trip() {
+        // Taken from `movie-init.mp4`
+        let baseline = vec![
+            0x01, 0x42, 0xe0, 0x1e, 0xff, 0xe1, 0x00, 0x17, 0x67, 0x42, 0xe0, 0x1e, 0xda, 0x02,
+            0xd0, 0xf6, 0xff, 0xc0, 0x02, 0x00, 0x02, 0x44, 0x00, 0x00, 0x2e, 0xec, 0x00, 0x0a,
+            0xfc, 0x82, 0x10, 0x01, 0x00, 0x04, 0x68, 0xce, 0x33, 0xc8,
+        ];
+
+        // Main profile, 1280x720, level 3.1 (as written by x264)
+        let main = vec![
+            0x01, 0x4d, 0x40, 0x1f, 0xff, 0xe1, 0x00, 0x1c, 0x67, 0x4d, 0x40, 0x1f, 0xe8, 0x80,
+            0x28, 0x02, 0xdd, 0x80, 0xb5, 0x01, 0x01, 0x01, 0x40, 0x00, 0x00, 0x03, 0x00, 0x40,
+            0x00, 0x00, 0x0c, 0x03, 0xc6, 0x0c, 0x44, 0x80, 0x01, 0x00, 0x04, 0x68, 0xee, 0x3c,
+            0x80,
+        ];
+
+        // High profile, 1280x720, level 3.1 (as written by x264)
+        let high = vec![
+            0x01, 0x64, 0x00, 0x1f, 0xff, 0xe1, 0x00, 0x1a, 0x67, 0x64, 0x00, 0x1f, 0xac, 0xd9,
+            0x40, 0x50, 0x05, 0xbb, 0x01, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+            0x03, 0xc0, 0xf1, 0x83, 0x19, 0x60, 0x01, 0x00, 0x06, 0x68, 0xeb, 0xe3, 0xcb, 0x22,
+            0xc0, 0xfd, 0xf8, 0xf8, 0x00,
+        ];
+
+        for bytes in &[baseline, main, high] {
+            let record = AvcDecoderConfigurationRecord::read_from(&bytes[..]).unwrap();
+            let mut actual = Vec::new();
+            record.write_to(&mut actual).unwrap();
+            assert_eq!(&actual, bytes);
+        }
+    }
+
+    #[test]
+    fn test_with_parameter_sets() {
+        let mut record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        record.length_size = LengthSize::new(2).unwrap();
+
+        let high = record
+            .clone()
+            .with_parameter_sets(HIGH_PROFILE_SPS.to_vec(), BASELINE_PPS.to_vec())
+            .unwrap();
+        assert_eq!(high.profile_idc, 100);
+        assert_eq!(high.length_size(), 2);
+        assert_eq!(high.sequence_parameter_set, HIGH_PROFILE_SPS);
+        assert!(high.extended_configuration_data.is_some());
+
+        let swapped = record
+            .clone()
+            .with_parameter_sets(BASELINE_PPS.to_vec(), HIGH_PROFILE_SPS.to_vec());
+        assert!(swapped.is_err());
+        assert!(record.with_parameter_sets(Vec::new(), BASELINE_PPS.to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_avc_decoder_configuration_record_ref() {
+        let record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&HIGH_PROFILE_SPS, &BASELINE_PPS)
+                .unwrap();
+        let record_ref = AvcDecoderConfigurationRecordRef {
+            profile_idc: 100,
+            constraint_set_flag: 0,
+            level_idc: 40,
+            length_size: LengthSize::FOUR,
+            sequence_parameter_set: &HIGH_PROFILE_SPS,
+            picture_parameter_set: &BASELINE_PPS,
+            extended_configuration_data: record.extended_configuration_data.as_ref(),
+        };
+        assert_eq!(record.as_record_ref(), record_ref);
+        assert_eq!(record_ref.to_owned(), record);
+
+        let mut expected = Vec::new();
+        record.write_to(&mut expected).unwrap();
+        let mut actual = Vec::new();
+        record_ref.write_to(&mut actual).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_set_level() {
+        let mut record = AvcDecoderConfigurationRecord {
+            profile_idc: 66,
+            constraint_set_flag: 0xe0,
+            level_idc: 30,
+            length_size: LengthSize::FOUR,
+            sequence_parameter_set: BASELINE_SPS.to_vec(),
+            picture_parameter_set: BASELINE_PPS.to_vec(),
+            extended_configuration_data: None,
+        };
+        record.set_level(21).unwrap();
+        assert_eq!(record.level_idc, 21);
+
+        let sps = SpsSummary::read_from(&record.sequence_parameter_set[1..]).unwrap();
+        assert_eq!(sps.level_idc, 21);
+    }
+
+    #[test]
+    fn test_avcc_box_from_annexb() {
+        let mut bytes = vec![0, 0, 0, 1];
+        bytes.extend_from_slice(&BASELINE_SPS);
+        bytes.extend_from_slice(&[0, 0, 0, 1]);
+        bytes.extend_from_slice(&BASELINE_PPS);
+        bytes.extend_from_slice(&[0, 0, 1, 0x65, 0x88]);
+
+        let record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        assert_eq!(record.parameter_sets_annexb(), &bytes[..bytes.len() - 5]);
+
+        let avcc = avcc_box_from_annexb(&bytes).unwrap();
+        assert_eq!(avcc.len(), 8 + 8 + BASELINE_SPS.len() + 3 + BASELINE_PPS.len());
+        assert_eq!(&avcc[4..8], b"avcC");
+        assert_eq!(&avcc[9..12], &BASELINE_SPS[1..4]);
+        assert_eq!(&avcc[16..16 + BASELINE_SPS.len()], &BASELINE_SPS[..]);
+        assert_eq!(&avcc[avcc.len() - BASELINE_PPS.len()..], &BASELINE_PPS[..]);
+    }
+
+    #[test]
+    fn test_read_monochrome_sps_with_scaling_matrix() {
+        let sps = SpsSummary::read_from(&MONOCHROME_SPS[1..]).unwrap();
+        assert_eq!(sps.width(), 176);
+        assert_eq!(sps.height(), 144);
+
+        let extended_configuration_data = sps.extended_configuration_data.as_ref().unwrap();
+        assert_eq!(extended_configuration_data.chroma_format, 0);
+        assert!(extended_configuration_data.seq_scaling_matrix_present);
+        assert_eq!(
+            extended_configuration_data.seq_scaling_list_4x4,
+            [Some(vec![16, 20]), None, Some(vec![]), None, None, None]
+        );
+        assert_eq!(
+            extended_configuration_data.use_default_scaling_matrix_4x4,
+            [false, false, true, false, false, false]
+        );
+        assert_eq!(extended_configuration_data.seq_scaling_list_8x8, [Some(vec![8]), None]);
+        assert_eq!(extended_configuration_data.use_default_scaling_matrix_8x8, [false, false]);
+
+        // The scaling matrix is written back to the SPS
+        let mut bytes = vec![MONOCHROME_SPS[0]];
+        sps.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, MONOCHROME_SPS);
+
+        // ... but not to the `avcC` trailer
+        let record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&MONOCHROME_SPS, &BASELINE_PPS)
+                .unwrap();
+        let mut avcc = Vec::new();
+        record.write_to(&mut avcc).unwrap();
+        assert_eq!(avcc[avcc.len() - 4..], [0xfc, 0xf8, 0xf8, 0x00]);
+    }
+
+    #[test]
+    fn test_constraint_flags() {
+        let mut flags = ConstraintFlags::new();
+        flags.set_constrained_baseline(true).set_level_1b(true);
+        assert_eq!(flags.as_u8(), 0b0101_0000);
+        assert!(flags.constraint_set_flag(3));
+
+        flags.set_constraint_set_flag(1, false);
+        assert_eq!(flags.as_u8(), 0b0001_0000);
+
+        let flags = ConstraintFlags::from_u8(0xe0).unwrap();
+        assert!(flags.constraint_set_flag(0));
+        assert!(flags.is_constrained_baseline());
+        assert!(!flags.is_level_1b());
+
+        assert!(ConstraintFlags::from_u8(0b0000_0001).is_err());
+    }
+
+    #[test]
+    fn test_sps_round_trip() {
+        for nal_unit in &[&BASELINE_SPS[..], &HIGH_PROFILE_SPS[..], &MONOCHROME_SPS[..]] {
+            let sps = SpsSummary::from_nal_unit(nal_unit, false).unwrap();
+            let mut bytes = vec![nal_unit[0]];
+            sps.write_to(&mut bytes).unwrap();
+            assert_eq!(&bytes[..], *nal_unit);
+        }
+    }
+
+    #[test]
+    fn test_set_fixed_frame_rate() {
+        // `BASELINE_SPS` has `fixed_frame_rate_flag=1`
+        let mut sps = SpsSummary::from_nal_unit(&BASELINE_SPS, true).unwrap();
+        sps.set_fixed_frame_rate(false, None).unwrap();
+        let mut bytes = vec![BASELINE_SPS[0]];
+        sps.write_to(&mut bytes).unwrap();
+        let actual = SpsSummary::read_from(&bytes[1..]).unwrap();
+        let timing_info = actual.vui_parameters.unwrap().timing_info.unwrap();
+        assert!(!timing_info.fixed_frame_rate_flag);
+        assert_eq!(timing_info.num_units_in_tick, 3003);
+
+        // An SPS without VUI
+        let mut sps = SpsSummary::read_from(&MONOCHROME_SPS[1..]).unwrap();
+        assert!(sps.vui_parameters.is_none());
+        sps.set_fixed_frame_rate(false, None).unwrap();
+        assert!(sps.vui_parameters.is_none());
+        assert!(sps.set_fixed_frame_rate(true, None).is_err());
+
+        sps.set_fixed_frame_rate(true, Some((1001, 60_000))).unwrap();
+        let mut bytes = vec![MONOCHROME_SPS[0]];
+        sps.write_to(&mut bytes).unwrap();
+        let actual = SpsSummary::read_from(&bytes[1..]).unwrap();
+        assert_eq!(actual.width(), sps.width());
+        let vui = actual.vui_parameters.unwrap();
+        assert!(vui.aspect_ratio_info.is_none());
+        let timing_info = vui.timing_info.unwrap();
+        assert_eq!((timing_info.num_units_in_tick, timing_info.time_scale), (1001, 60_000));
+        assert!(timing_info.fixed_frame_rate_flag);
+    }
+
+    #[test]
+    fn test_sps_preserved_rbsp() {
+        // `BASELINE_SPS` followed by unparsed bits (`0b10110`).
+        let nal_unit = [
+            0x67, 0x42, 0xe0, 0x1e, 0xda, 0x02, 0xd0, 0xf6, 0xff, 0xc0, 0x02, 0x00, 0x02, 0x44,
+            0x00, 0x00, 0x2e, 0xec, 0x00, 0x0a, 0xfc, 0x82, 0x16, 0x80,
+        ];
+
+        let mut sps = SpsSummary::from_nal_unit(&nal_unit, true).unwrap();
+        assert_eq!(sps.preserved_rbsp.as_ref().unwrap().bit_offset, 171);
+
+        let mut bytes = vec![nal_unit[0]];
+        sps.write_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[..], &nal_unit[..]);
+
+        sps.level_idc = 21;
+        let mut bytes = vec![nal_unit[0]];
+        sps.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes[3], 21);
+        assert_eq!(&bytes[4..], &nal_unit[4..]);
+
+        sps.preserved_rbsp = None;
+        let mut bytes = vec![nal_unit[0]];
+        sps.write_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[4..], &BASELINE_SPS[4..]);
+    }
+
+    #[test]
+    fn test_split_parameter_sets_skips_other_nal_units() {
+        let mut bytes = vec![0, 0, 0, 1, 0x09, 0xf0]; // AUD
+        bytes.extend_from_slice(&[0, 0, 1, 0x06, 0x05, 0x01, 0xff, 0x80]); // SEI
+        bytes.extend_from_slice(&[0, 0, 1, 0x0c, 0xff, 0xff, 0x80]); // Filler data
+        bytes.extend_from_slice(&[0, 0, 0, 1]);
+        bytes.extend_from_slice(&BASELINE_PPS);
+        bytes.extend_from_slice(&[0, 0, 0, 1]);
+        bytes.extend_from_slice(&BASELINE_SPS);
+
+        let (sps, pps) = split_parameter_sets(&bytes).unwrap();
+        assert_eq!(sps, &BASELINE_SPS[..]);
+        assert_eq!(pps, &BASELINE_PPS[..]);
+
+        assert!(split_parameter_sets(&[0, 0, 0, 1, 0x09, 0xf0]).is_err());
+    }
+
+    #[test]
+    fn test_find_first_parameter_sets() {
+        let mut bytes = vec![0, 0, 0, 1];
+        bytes.extend_from_slice(&BASELINE_SPS);
+        bytes.extend_from_slice(&[0, 0, 1]);
+        bytes.extend_from_slice(&BASELINE_PPS);
+
+        // A truncated SPS and a NAL unit with `forbidden_zero_bit`, both of which fail to parse
+        let tail = [0, 0, 1, 0x67, 0x42, 0, 0, 1, 0xe5, 0xaa];
+        assert!(SpsSummary::read_from(&tail[4..5]).is_err());
+        assert!(NalUnit::read_from_with_options(&tail[8..], &ParseOptions::strict()).is_err());
+        bytes.extend_from_slice(&tail);
+
+        let (sps, pps) = find_first_parameter_sets(&bytes).unwrap();
+        assert_eq!(sps, &BASELINE_SPS[..]);
+        assert_eq!(pps, &BASELINE_PPS[..]);
+        assert_eq!(sps.as_ptr(), bytes[4..].as_ptr());
+
+        assert!(find_first_parameter_sets(&bytes[..4 + BASELINE_SPS.len()]).is_err());
+    }
+
+    #[test]
+    fn test_max_bit_depth() {
+        let baseline =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        assert_eq!(baseline.max_bit_depth(), 8);
+
+        let mut high =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&HIGH_PROFILE_SPS, &BASELINE_PPS)
+                .unwrap();
+        assert_eq!(high.max_bit_depth(), 8);
+        high.extended_configuration_data.as_mut().unwrap().bit_depth_chroma_minus_8 = 2;
+        assert_eq!(high.max_bit_depth(), 10);
+    }
+
+    #[test]
+    fn test_differs_only_in_level() {
+        let record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        let mut other = record.clone();
+        assert!(record.differs_only_in_level(&other));
+
+        other.set_level(31).unwrap();
+        assert!(record.differs_only_in_level(&other));
+        assert_ne!(record, other);
+
+        other.picture_parameter_set[1] ^= 1;
+        assert!(!record.differs_only_in_level(&other));
+    }
+
+    #[test]
+    fn test_requires_change_type() {
+        let record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        let mut other = record.clone();
+        other.set_level(31).unwrap();
+        assert!(!record.requires_change_type(&other));
+
+        other.picture_parameter_set[1] ^= 1;
+        assert!(!record.requires_change_type(&other));
+
+        // A level change along with a length size change
+        let mut other = record.clone();
+        other.set_level(31).unwrap();
+        other.length_size = LengthSize::new(2).unwrap();
+        assert!(!record.differs_only_in_level(&other));
+        assert!(record.requires_change_type(&other));
+
+        let high = AvcDecoderConfigurationRecord::from_parameter_sets(&HIGH_PROFILE_SPS, &[])
+            .unwrap();
+        assert!(record.requires_change_type(&high));
+        assert!(high.requires_change_type(&record));
+
+        // Only the chroma format and the bit depths of the trailer matter
+        let mut other = high.clone();
+        let data = other.extended_configuration_data.as_mut().unwrap();
+        data.sequence_parameter_set_ext.push(vec![0x6d, 0x00]);
+        data.qp_prime_y_zero_transform_bypass = true;
+        assert!(!high.requires_change_type(&other));
+
+        other.extended_configuration_data.as_mut().unwrap().bit_depth_luma_minus_8 = 2;
+        assert!(high.requires_change_type(&other));
+    }
+
+    #[test]
+    fn test_sample_entry_update() {
+        let baseline =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        assert_eq!(baseline.sample_entry_update(&baseline).unwrap(), None);
+
+        let mut level_up = baseline.clone();
+        level_up.set_level(31).unwrap();
+        let update = baseline.sample_entry_update(&level_up).unwrap().unwrap();
+        assert!(!update.requires_change_type);
+        assert_eq!(&update.avcc_box[4..8], b"avcC");
+        let record = AvcDecoderConfigurationRecord::read_from(&update.avcc_box[8..]).unwrap();
+        assert_eq!(record, level_up);
+
+        let high =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&HIGH_PROFILE_SPS, &BASELINE_PPS)
+                .unwrap();
+        let update = baseline.sample_entry_update(&high).unwrap().unwrap();
+        assert!(update.requires_change_type);
+    }
+
+    #[test]
+    fn test_record_builder() {
+        let record = AvcDecoderConfigurationRecordBuilder::new()
+            .sps(HIGH_PROFILE_SPS.to_vec())
+            .pps(BASELINE_PPS.to_vec())
+            .profile(100)
+            .level(40)
+            .build()
+            .unwrap();
+        assert_eq!(record.constraint_set_flag, 0);
+        assert!(record.extended_configuration_data.is_some());
+
+        let result = AvcDecoderConfigurationRecordBuilder::new()
+            .sps(BASELINE_SPS.to_vec())
+            .pps(BASELINE_PPS.to_vec())
+            .profile(100)
+            .build();
+        assert!(result.is_err());
+
+        let extended_configuration_data = record.extended_configuration_data.unwrap();
+        let result = AvcDecoderConfigurationRecordBuilder::new()
+            .sps(BASELINE_SPS.to_vec())
+            .pps(BASELINE_PPS.to_vec())
+            .extended_config(extended_configuration_data)
+            .build();
+        assert!(result.is_err());
+
+        let result = AvcDecoderConfigurationRecordBuilder::new()
+            .sps(BASELINE_SPS.to_vec())
+            .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_macroblocks() {
+        let sps = SpsSummary::read_from(&BASELINE_SPS[1..]).unwrap();
+        assert_eq!(sps.macroblocks_per_frame(), 45 * 30);
+        assert_eq!(sps.macroblocks_per_second(), Some(45 * 30 * 180_000 / 6_006));
+
+        let sps = SpsSummary::read_from(&HIGH_PROFILE_SPS[1..]).unwrap();
+        assert_eq!(sps.macroblocks_per_frame(), 120 * 68);
+        assert_eq!(sps.macroblocks_per_second(), Some(120 * 68 * 30));
+
+        let sps = SpsSummary::read_from(&MONOCHROME_SPS[1..]).unwrap();
+        assert_eq!(sps.macroblocks_per_frame(), 11 * 9);
+        assert_eq!(sps.macroblocks_per_second(), None);
+    }
+
+    #[test]
+    fn test_filler_data() {
+        let mut bytes = vec![0, 0, 0, 1, 0x0c, 0xff, 0xff, 0xff, 0x80];
+        bytes.extend_from_slice(&[0, 0, 0, 1, 0x65, 0x88]);
+        bytes.extend_from_slice(&[0, 0, 1, 0x0c, 0xff, 0x80]);
+        assert_eq!(filler_bytes(&bytes), 5 + 3);
+
+        let stripped = strip_nal_types(&bytes, &[NalUnitType::FillerData]).unwrap();
+        assert_eq!(stripped, [0, 0, 0, 1, 0x65, 0x88]);
+        assert_eq!(filler_bytes(&stripped), 0);
+        assert_eq!(NalUnitType::FillerData, NalUnitType::FilterData);
+    }
+
+    #[test]
+    fn test_filter_max_temporal_id() {
+        // svc_extension_flag=1, no_inter_layer_pred_flag=1, temporal_id=1, output_flag=1
+        let prefix = [0x6e, 0x80, 0x80, 0x27];
+        let bytes = [
+            &[0, 0, 0, 1][..],
+            &[0x67, 0x42], // SPS
+            &[0, 0, 0, 1],
+            &[0x65, 0x88], // IDR (temporal_id=0)
+            &[0, 0, 0, 1],
+            &prefix,
+            &[0, 0, 0, 1],
+            &[0x01, 0x9a], // non-IDR (temporal_id=1)
+            &[0, 0, 0, 1],
+            &[0x41, 0x9a], // non-IDR (temporal_id=0)
+        ]
+        .concat();
+
+        let header = PrefixNalUnit::read_from(&prefix).unwrap();
+        assert!(header.svc_extension_flag);
+        assert_eq!(header.temporal_id, 1);
+        assert!(PrefixNalUnit::read_from(&[0x65, 0x88, 0x80, 0x00]).is_err());
+
+        let temporal_ids = nal_units_with_temporal_id(&bytes)
+            .unwrap()
+            .into_iter()
+            .map(|x| x.1)
+            .collect::<Vec<_>>();
+        assert_eq!(temporal_ids, [None, Some(0), Some(1), Some(1), Some(0)]);
+
+        let filtered = filter_max_temporal_id(&bytes, 0).unwrap();
+        assert_eq!(
+            filtered,
+            [0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x65, 0x88, 0, 0, 0, 1, 0x41, 0x9a]
+        );
+        assert_eq!(filter_max_temporal_id(&bytes, 1).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_strip_sei_messages() {
+        let bytes = [
+            0, 0, 0, 1, 0x06, // SEI
+            0x06, 0x01, 0b0111_0000, // recovery_point
+            0x04, 0x04, 0xb5, 0x00, 0x00, 0x03, 0x03, // user_data_registered_itu_t_t35
+            0x80, // rbsp_trailing_bits
+            0, 0, 0, 1, 0x06, 0x06, 0x01, 0b1000_0000, 0x80, // SEI (recovery_point only)
+            0, 0, 0, 1, 0x65, 0x88, // IDR
+        ];
+        let stripped = strip_sei_messages(&bytes, &[RecoveryPoint::PAYLOAD_TYPE]).unwrap();
+        assert_eq!(
+            stripped,
+            [
+                0, 0, 0, 1, 0x06, 0x04, 0x04, 0xb5, 0x00, 0x00, 0x03, 0x03, 0x80, // SEI
+                0, 0, 0, 1, 0x65, 0x88, // IDR
+            ]
+        );
+        let restripped = strip_sei_messages(&stripped, &[RecoveryPoint::PAYLOAD_TYPE]).unwrap();
+        assert_eq!(restripped, stripped);
+    }
+
+    #[test]
+    fn test_inconsistent_separate_color_plane() {
+        let mut record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&HIGH_PROFILE_SPS, &BASELINE_PPS)
+                .unwrap();
+        assert!(record.write_to(Vec::new()).is_ok());
+
+        let data = record.extended_configuration_data.as_mut().unwrap();
+        data.separate_color_plane = Some(false);
+        assert!(data.validate().is_err());
+
+        data.chroma_format = 3;
+        data.separate_color_plane = None;
+        assert!(data.validate().is_err());
+
+        // The `avcC` trailer carries no `separate_color_plane`
+        assert!(record.write_to(Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_access_units() {
+        let aud = [0x09, 0xf0];
+        let idr = [0x65, 0b1000_1000, 0b1000_0000];
+        let non_idr = [0x41, 0b1001_1010];
+        let annexb = |nal_units: &[&[u8]]| {
+            let mut bytes = Vec::new();
+            for nal_unit in nal_units {
+                bytes.extend_from_slice(&[0, 0, 0, 1]);
+                bytes.extend_from_slice(nal_unit);
+            }
+            bytes
+        };
+        let sizes = |bytes: &[u8]| {
+            AccessUnits::new(bytes)
+                .unwrap()
+                .map(|access_unit| access_unit.len())
+                .collect::<Vec<_>>()
+        };
+
+        let bytes = annexb(&[&aud, &BASELINE_SPS, &BASELINE_PPS, &idr, &aud, &non_idr]);
+        assert!(uses_access_unit_delimiters(&bytes));
+        assert_eq!(sizes(&bytes), [4, 2]);
+
+        let bytes = annexb(&[&BASELINE_SPS, &BASELINE_PPS, &idr, &non_idr]);
+        assert!(!uses_access_unit_delimiters(&bytes));
+        assert_eq!(sizes(&bytes), [3, 1]);
+
+        // The second access unit lacks its delimiter.
+        let bytes = annexb(&[&aud, &idr, &non_idr, &aud, &non_idr]);
+        assert_eq!(sizes(&bytes), [2, 1, 2]);
+
+        // Slices with `first_mb_in_slice=1` and `frame_num` of 1, 1 and 2
+        let frame_num1 = [0x41, 0x46, 0x8c];
+        let frame_num2 = [0x41, 0x46, 0x94];
+        let bytes = annexb(&[&BASELINE_SPS, &BASELINE_PPS, &frame_num1, &frame_num1, &frame_num2]);
+        assert_eq!(sizes(&bytes), [4, 1]);
+
+        // A malformed SPS falls back to `first_mb_in_slice`
+        let bytes = annexb(&[&[0x67, 0x42], &idr, &non_idr]);
+        assert_eq!(sizes(&bytes), [2, 1]);
+    }
+
+    #[test]
+    fn test_annexb_writer() {
+        let mut writer = AnnexBWriter::new(Vec::new());
+        writer.write_nal_unit(&BASELINE_SPS).unwrap();
+        writer.write_nal_unit(&BASELINE_PPS).unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(&bytes[..5], &[0, 0, 0, 1, 0x67]);
+        assert_eq!(&bytes[4 + BASELINE_SPS.len()..][..4], &[0, 0, 1, 0x68]);
+
+        let mut writer = AnnexBWriter::new(Vec::new());
+        writer.set_four_byte_start_codes(true);
+        writer.write_nal_unit(&BASELINE_SPS).unwrap();
+        writer.write_nal_unit(&BASELINE_PPS).unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(&bytes[4 + BASELINE_SPS.len()..][..5], &[0, 0, 0, 1, 0x68]);
+    }
+
+    #[test]
+    fn test_read_delta_pic_order_cnt_bottom() {
+        let sps = SpsSummary::read_from(&HIGH_PROFILE_SPS[1..]).unwrap();
+        let mut pps = PpsSummary::read_from(&BASELINE_PPS[1..]).unwrap();
+        pps.bottom_field_pic_order_in_frame_present_flag = true;
+
+        // first_mb_in_slice=0, slice_type=7, frame_num=0, idr_pic_id=0, pic_order_cnt_lsb=4,
+        // delta_pic_order_cnt_bottom=-1
+        let slice = [0b1000_1000, 0b1000_0100, 0b0100_0111];
+        let header = SliceHeaderSummary::read_with_parameter_sets(
+            &slice[..],
+            NalUnitType::CodedSliceOfAnIdrPicture,
+            &sps,
+            &pps,
+        )
+        .unwrap();
+        assert_eq!(header.frame_num, Some(0));
+        assert!(!header.field_pic_flag);
+        assert_eq!(header.idr_pic_id, Some(0));
+        assert_eq!(header.pic_order_cnt_lsb, Some(4));
+        assert_eq!(header.delta_pic_order_cnt_bottom, Some(-1));
+
+        let header = SliceHeaderSummary::read_from(&slice[..]).unwrap();
+        assert_eq!(header.delta_pic_order_cnt_bottom, None);
+    }
+
+    #[test]
+    fn test_read_delta_pic_order_cnt() {
+        let mut sps = SpsSummary::read_from(&HIGH_PROFILE_SPS[1..]).unwrap();
+        sps.pic_order_cnt = PicOrderCnt::Type1 {
+            delta_pic_order_always_zero_flag: false,
+            offset_for_non_ref_pic: 0,
+            offset_for_top_to_bottom_field: 0,
+            offset_for_ref_frame: Vec::new(),
+        };
+        let mut pps = PpsSummary::read_from(&BASELINE_PPS[1..]).unwrap();
+        pps.bottom_field_pic_order_in_frame_present_flag = true;
+
+        let slice = |delta_pic_order_cnt: [i64; 2]| {
+            let mut bytes = Vec::new();
+            let mut writer = AvcBitWriter::new(&mut bytes);
+            writer.write_ue(0).unwrap(); // first_mb_in_slice
+            writer.write_ue(7).unwrap(); // slice_type
+            writer.write_ue(0).unwrap(); // pic_parameter_set_id
+            writer.write_bits(sps.frame_num_bits(), 0).unwrap(); // frame_num
+            writer.write_ue(0).unwrap(); // idr_pic_id
+            writer.write_se(delta_pic_order_cnt[0]).unwrap();
+            writer.write_se(delta_pic_order_cnt[1]).unwrap();
+            writer.write_rbsp_trailing_bits().unwrap();
+            SliceHeaderSummary::read_with_parameter_sets(
+                &bytes[..],
+                NalUnitType::CodedSliceOfAnIdrPicture,
+                &sps,
+                &pps,
+            )
+            .unwrap()
+        };
+        let header = slice([-1, 1]);
+        assert_eq!(header.pic_order_cnt_lsb, None);
+        assert_eq!(header.delta_pic_order_cnt, [Some(-1), Some(1)]);
+
+        let nal_unit = NalUnit::read_from(&[0x65][..]).unwrap();
+        assert!(!is_different_picture(&nal_unit, &header, &nal_unit, &slice([-1, 1])));
+        assert!(is_different_picture(&nal_unit, &header, &nal_unit, &slice([-1, 2])));
+        assert!(is_different_picture(&nal_unit, &header, &nal_unit, &slice([0, 1])));
+    }
+
+    #[test]
+    fn test_sps_payload_range() {
+        assert_eq!(sps_payload_range(&BASELINE_SPS).unwrap(), 1..BASELINE_SPS.len());
+
+        let mut nal_unit = HIGH_PROFILE_SPS.to_vec();
+        nal_unit.extend_from_slice(&[0, 0]);
+        let range = sps_payload_range(&nal_unit).unwrap();
+        assert_eq!(range, 1..HIGH_PROFILE_SPS.len());
+
+        // Two emulation prevention bytes are removed.
+        let rbsp = sps_rbsp_payload(&nal_unit).unwrap();
+        assert_eq!(rbsp.len(), range.len() - 2);
+        assert_eq!(&rbsp[..3], &[100, 0, 40]);
+
+        assert!(sps_payload_range(&BASELINE_PPS).is_err());
+    }
+
+    #[test]
+    fn test_pasp_box() {
+        let aspect_ratio_info = |aspect_ratio_idc, sar_width, sar_height| AspectRatioInfo {
+            aspect_ratio_idc,
+            sar_width,
+            sar_height,
+        };
+
+        let pasp = aspect_ratio_info(AspectRatioInfo::EXTENDED_SAR, 8, 9).pasp_box(true).unwrap();
+        let mut bytes = Vec::new();
+        pasp.write_box(&mut bytes).unwrap();
+        assert_eq!(bytes, [0, 0, 0, 16, b'p', b'a', b's', b'p', 0, 0, 0, 8, 0, 0, 0, 9]);
+
+        let pasp = aspect_ratio_info(4, 0, 0).pasp_box(true).unwrap();
+        assert_eq!((pasp.h_spacing, pasp.v_spacing), (16, 11));
+        let pasp = aspect_ratio_info(14, 0, 0).pasp_box(true).unwrap();
+        assert_eq!((pasp.h_spacing, pasp.v_spacing), (4, 3));
+
+        assert!(aspect_ratio_info(1, 0, 0).pasp_box(true).is_none());
+        assert!(aspect_ratio_info(1, 0, 0).pasp_box(false).is_some());
+        assert!(aspect_ratio_info(0, 0, 0).pasp_box(false).is_none());
+        assert!(aspect_ratio_info(17, 0, 0).pasp_box(false).is_none());
+    }
+
+    #[test]
+    fn test_parameter_set_store() {
+        let mut store = ParameterSetStore::new();
+        assert!(!store.changed_since_last_fragment());
+
+        store.ingest(&BASELINE_SPS).unwrap();
+        store.ingest(&BASELINE_PPS).unwrap();
+        store.ingest(&[0x09, 0xf0]).unwrap();
+        assert!(store.changed_since_last_fragment());
+        assert_eq!(store.active_sps(0).map(|sps| sps.width()), Some(720));
+        assert_eq!(store.active_pps(0).map(|pps| pps.seq_parameter_set_id), Some(0));
+        assert_eq!(store.pps_nal_unit(0), Some(&BASELINE_PPS[..]));
+        assert!(store.active_sps(1).is_none());
+
+        store.start_fragment();
+        store.ingest(&BASELINE_SPS).unwrap();
+        assert!(!store.changed_since_last_fragment());
+
+        store.ingest(&HIGH_PROFILE_SPS).unwrap();
+        assert!(store.changed_since_last_fragment());
+        assert_eq!(store.active_sps(0).map(|sps| sps.width()), Some(1920));
+    }
+
+    #[test]
+    fn test_nal_unit_consistency() {
+        let nal_unit = |b: u8| NalUnit::read_from(&[b][..]).unwrap();
+        assert!(nal_unit(BASELINE_SPS[0]).is_consistent());
+        assert!(nal_unit(0x65).is_consistent());
+        assert!(nal_unit(0x09).is_consistent());
+        assert!(nal_unit(0x01).is_consistent());
+
+        assert!(!nal_unit(0x05).is_consistent()); // IDR with nal_ref_idc=0
+        assert!(!nal_unit(0x07).is_consistent()); // SPS with nal_ref_idc=0
+        assert!(nal_unit(0x26).validate().is_err()); // SEI with nal_ref_idc=1
+    }
+
+    #[test]
+    fn test_normalize_parameter_sets() {
+        let aud = [0x09, 0xf0];
+        let idr = [0x65, 0b1000_1000, 0b1000_0000];
+        let non_idr = [0x41, 0b1001_1010];
+        let input = [&aud[..], &BASELINE_SPS, &BASELINE_PPS, &idr, &aud, &non_idr, &aud, &idr];
+        let mut writer = AnnexBWriter::new(Vec::new());
+        for nal_unit in &input {
+            writer.write_nal_unit(nal_unit).unwrap();
+        }
+        let bytes = writer.into_inner();
+        let nal_units = |bytes: &[u8]| {
+            ByteStreamFormatNalUnits::new(bytes)
+                .unwrap()
+                .map(|nal_unit| nal_unit.to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        let out_of_band =
+            normalize_parameter_sets(&bytes, SampleEntryMode::OutOfBand, &BASELINE_SPS, &[])
+                .unwrap();
+        assert_eq!(
+            nal_units(&out_of_band),
+            [&aud[..], &idr, &aud, &non_idr, &aud, &idr]
+        );
+
+        let in_band =
+            normalize_parameter_sets(&bytes, SampleEntryMode::InBand, &BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        assert_eq!(
+            nal_units(&in_band),
+            [
+                &aud[..],
+                &BASELINE_SPS,
+                &BASELINE_PPS,
+                &idr,
+                &aud,
+                &non_idr,
+                &aud,
+                &BASELINE_SPS,
+                &BASELINE_PPS,
+                &idr
+            ]
+        );
+
+        // An empty PPS is not inserted
+        let without_pps =
+            normalize_parameter_sets(&bytes, SampleEntryMode::InBand, &BASELINE_SPS, &[]).unwrap();
+        assert_eq!(
+            nal_units(&without_pps),
+            [&aud[..], &BASELINE_SPS, &idr, &aud, &non_idr, &aud, &BASELINE_SPS, &idr]
+        );
+    }
+
+    #[test]
+    fn test_length_prefixed_nal_units() {
+        let bytes = [0, 0, 0, 2, 0x09, 0xf0, 0, 0, 0, 3, 0x65, 0x88, 0x80];
+        let nal_units = LengthPrefixedNalUnits::new(&bytes, LengthSize::FOUR)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(nal_units, [&[0x09, 0xf0][..], &[0x65, 0x88, 0x80]]);
+
+        let bytes = [2, 0x09, 0xf0, 3, 0x65];
+        let mut nal_units = LengthPrefixedNalUnits::new(&bytes, LengthSize::new(1).unwrap());
+        assert_eq!(nal_units.next().unwrap().ok(), Some(&[0x09, 0xf0][..]));
+        assert_eq!(
+            nal_units.next().unwrap().err().map(|e| *e.kind()),
+            Some(ErrorKind::InvalidInput)
+        );
+        assert!(nal_units.next().is_none());
+    }
+
+    #[test]
+    fn test_has_b_frames() {
+        let mut bytes = vec![0, 0, 0, 1, 0x65, 0b1000_1000, 0b1000_0000]; // I (slice_type=7)
+        bytes.extend_from_slice(&[0, 0, 1, 0x41, 0b1001_1010]); // P (slice_type=5)
+        assert!(!has_b_frames(&bytes).unwrap());
+
+        bytes.extend_from_slice(&[0, 0, 1, 0x01, 0b1001_1110]); // B (slice_type=6)
+        assert!(has_b_frames(&bytes).unwrap());
+    }
+
+    #[test]
+    fn test_is_cavlc_only() {
+        let mut bytes = [&[0, 0, 0, 1][..], &BASELINE_SPS, &[0, 0, 0, 1], &BASELINE_PPS].concat();
+        assert!(!is_cavlc_only(&bytes[..4 + BASELINE_SPS.len()]).unwrap());
+        assert!(is_cavlc_only(&bytes).unwrap());
+
+        // A CABAC PPS (entropy_coding_mode_flag=1)
+        bytes.extend_from_slice(&[0, 0, 0, 1, 0x68, 0xee, 0x3c, 0x80]);
+        assert!(!is_cavlc_only(&bytes).unwrap());
+    }
+
+    #[test]
+    fn test_vui_parameters_present_flag() {
+        let mut sps = SpsSummary::read_from(&BASELINE_SPS[1..]).unwrap();
+        assert!(sps.vui_parameters_present_flag());
+        sps.vui_parameters = None;
+        assert!(!sps.vui_parameters_present_flag());
+
+        let sps = SpsSummary::read_from(&MONOCHROME_SPS[1..]).unwrap();
+        assert!(!sps.vui_parameters_present_flag());
+
+        let (sps, _) = synthesize_parameter_sets(1920, 1080, 77, 40).unwrap();
+        let summary = SpsSummary::read_from(&sps[1..]).unwrap();
+        assert!(!summary.vui_parameters_present_flag());
+    }
+
+    #[test]
+    fn test_synthesize_parameter_sets() {
+        let (sps, pps) = synthesize_parameter_sets(1920, 1080, 77, 40).unwrap();
+        assert_eq!(pps, [0x68, 0xce, 0x3c, 0x80]);
+        let summary = SpsSummary::read_from(&sps[1..]).unwrap();
+        assert_eq!(summary.profile_idc, 77);
+        assert_eq!(summary.level_idc, 40);
+        assert_eq!((summary.width(), summary.height()), (1920, 1080));
+        assert!(summary.frame_cropping_flag);
+
+        let (sps, pps) = synthesize_parameter_sets(426, 240, 66, 30).unwrap();
+        let summary = SpsSummary::read_from(&sps[1..]).unwrap();
+        assert_eq!((summary.width(), summary.height()), (426, 240));
+        let record = AvcDecoderConfigurationRecord::from_parameter_sets(&sps, &pps).unwrap();
+        assert_eq!(record.profile_idc, 66);
+        assert!(record.constraint_flags().unwrap().is_constrained_baseline());
+
+        let (sps, _) = synthesize_parameter_sets(640, 480, 66, 30).unwrap();
+        assert!(!SpsSummary::read_from(&sps[1..]).unwrap().frame_cropping_flag);
+
+        assert!(synthesize_parameter_sets(1920, 1080, 100, 40).is_err());
+        assert!(synthesize_parameter_sets(1921, 1080, 77, 40).is_err());
+        assert!(synthesize_parameter_sets(0, 1080, 77, 40).is_err());
+    }
+
+    #[test]
+    fn test_validate_stream_consistency() {
+        let (sps, pps) = synthesize_parameter_sets(1920, 1080, 77, 40).unwrap();
+        validate_stream_consistency(&sps, &pps).unwrap();
+
+        // 1920x1080 does not fit in level 3.1
+        let (sps, pps) = synthesize_parameter_sets(1920, 1080, 77, 31).unwrap();
+        assert!(validate_stream_consistency(&sps, &pps).is_err());
+
+        // Too wide for level 3.0 despite the small frame size
+        let (sps, pps) = synthesize_parameter_sets(2048, 64, 77, 30).unwrap();
+        assert!(validate_stream_consistency(&sps, &pps).is_err());
+
+        // Baseline with CABAC
+        let (sps, _) = synthesize_parameter_sets(640, 480, 66, 30).unwrap();
+        assert!(validate_stream_consistency(&sps, &[0x68, 0xee, 0x3c, 0x80]).is_err());
+
+        // The PPS refers to `seq_parameter_set_id=1`
+        assert!(validate_stream_consistency(&sps, &[0x68, 0xa3, 0x8f, 0x20]).is_err());
+
+        // High profile (4:2:0, 8 bits)
+        validate_stream_consistency(&HIGH_PROFILE_SPS, &BASELINE_PPS).unwrap();
+    }
+
+    #[test]
+    fn test_complex_referencing() {
+        let (sps, _) = synthesize_parameter_sets(640, 480, 66, 30).unwrap();
+        let mut sps = SpsSummary::read_from(&sps[1..]).unwrap();
+        assert_eq!(sps.max_num_ref_frames, 1);
+        assert!(!sps.complex_referencing());
+
+        sps.gaps_in_frame_num_value_allowed_flag = true;
+        assert!(sps.complex_referencing());
+
+        sps.gaps_in_frame_num_value_allowed_flag = false;
+        sps.max_num_ref_frames = 4;
+        assert!(sps.complex_referencing());
+    }
+
+    #[test]
+    fn test_rescale_timing() {
+        let (sps, _) = synthesize_parameter_sets(640, 480, 66, 30).unwrap();
+        let mut summary = SpsSummary::from_nal_unit(&sps, true).unwrap();
+        assert!(summary.rescale_timing(90_000).is_err());
+
+        summary.set_fixed_frame_rate(true, Some((1, 50))).unwrap();
+        summary.rescale_timing(90_000).unwrap();
+        let mut bytes = vec![0x67];
+        summary.write_to(&mut bytes).unwrap();
+        let summary = SpsSummary::from_nal_unit(&bytes, true).unwrap();
+        assert_eq!(summary.width(), 640);
+        let timing_info = summary.vui_parameters.unwrap().timing_info.unwrap();
+        assert_eq!(timing_info.num_units_in_tick, 1_800);
+        assert_eq!(timing_info.time_scale, 90_000);
+        assert!(timing_info.fixed_frame_rate_flag);
+
+        // 29.97 fps: 1001 * 30000 / 60000 = 500.5 is rounded
+        let mut summary = SpsSummary::read_from(&sps[1..]).unwrap();
+        summary.set_fixed_frame_rate(true, Some((1001, 60_000))).unwrap();
+        summary.rescale_timing(30_000).unwrap();
+        let timing_info = summary.vui_parameters.unwrap().timing_info.unwrap();
+        assert_eq!(timing_info.num_units_in_tick, 501);
+        assert_eq!(timing_info.time_scale, 30_000);
+    }
+
+    #[test]
+    fn test_write_avcc_box_into() {
+        let avcc_box = AvcConfigurationBox {
+            configuration: AvcDecoderConfigurationRecord::from_parameter_sets(
+                &BASELINE_SPS,
+                &BASELINE_PPS,
+            )
+            .unwrap(),
+        };
+        let mut expected = Vec::new();
+        avcc_box.write_box(&mut expected).unwrap();
+
+        let mut buf = vec![0xff; expected.len() + 4];
+        assert_eq!(avcc_box.write_box_into(&mut buf[2..]).unwrap(), expected.len());
+        assert_eq!(&buf[2..2 + expected.len()], &expected[..]);
+        assert_eq!(&buf[..2], [0xff, 0xff]);
+        assert_eq!(&buf[2 + expected.len()..], [0xff, 0xff]);
+
+        let mut buf = vec![0; expected.len() - 1];
+        assert!(avcc_box.write_box_into(&mut buf).is_err());
+        assert!(buf.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let strict = ParseOptions::strict();
+        let sps = SpsSummary::read_from_with_options(&BASELINE_SPS[1..], &strict).unwrap();
+        assert_eq!((sps.width(), sps.height()), (720, 480));
+
+        let mut garbage = BASELINE_SPS.to_vec();
+        garbage.push(0xff);
+        SpsSummary::read_from_with_options(&garbage[1..], &ParseOptions::default()).unwrap();
+        assert!(SpsSummary::read_from_with_options(&garbage[1..], &strict).is_err());
+
+        let small = ParseOptions {
+            max_nal_size: Some(BASELINE_SPS.len() - 1),
+            ..ParseOptions::default()
+        };
+        assert!(SpsSummary::read_from_with_options(&BASELINE_SPS[1..], &small).is_err());
+
+        assert_eq!(
+            NalUnit::read_from_with_options(&[0xe7][..], &ParseOptions::default())
+                .unwrap()
+                .nal_unit_type,
+            NalUnitType::SequenceParameterSet
+        );
+        assert!(NalUnit::read_from_with_options(&[0xe7][..], &strict).is_err());
+
+        let record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+        let read_strictly =
+            |bytes: &[u8]| AvcDecoderConfigurationRecord::read_from_with_options(bytes, &strict);
+        read_strictly(&bytes).unwrap();
+        assert!(AvcDecoderConfigurationRecord::read_from_with_options(&bytes[..], &small).is_err());
+
+        // Cleared reserved bits preceding `lengthSizeMinusOne`
+        let mut cleared = bytes.clone();
+        cleared[4] &= 0b0000_0011;
+        AvcDecoderConfigurationRecord::read_from(&cleared[..]).unwrap();
+        assert!(read_strictly(&cleared).is_err());
+
+        // Trailing garbage
+        let mut garbage = bytes.clone();
+        garbage.push(0);
+        AvcDecoderConfigurationRecord::read_from(&garbage[..]).unwrap();
+        assert!(read_strictly(&garbage).is_err());
+
+        // The PPS array holds an SPS
+        let mut swapped = bytes.clone();
+        let pps_offset = swapped.len() - BASELINE_PPS.len();
+        swapped[pps_offset] = 0x67;
+        AvcDecoderConfigurationRecord::read_from(&swapped[..]).unwrap();
+        assert!(read_strictly(&swapped).is_err());
+    }
+
+    #[test]
+    fn test_codec_string_keeps_constraint_set_flag() {
+        let (sps, pps) = synthesize_parameter_sets(1280, 720, 77, 31).unwrap();
+        assert_eq!(sps[2], 0x40);
+        let record = AvcDecoderConfigurationRecord::from_parameter_sets(&sps, &pps).unwrap();
+        assert_eq!(record.constraint_set_flag, 0x40);
+        assert_eq!(record.codec_string(), "avc1.4d401f");
+        assert_eq!(record.codec_string_for_mode(SampleEntryMode::InBand), "avc3.4d401f");
+
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+        let record = AvcDecoderConfigurationRecord::read_from(&bytes[..]).unwrap();
+        assert_eq!(record.codec_string(), "avc1.4d401f");
+
+        let record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        assert_eq!(record.codec_string(), "avc1.42e01e");
+    }
+
+    #[test]
+    fn test_codec_string_zero_padding() {
+        let mut record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&HIGH_PROFILE_SPS, &BASELINE_PPS)
+                .unwrap();
+        assert_eq!((record.profile_idc, record.constraint_set_flag), (100, 0));
+        record.level_idc = 31;
+        assert_eq!(record.codec_string(), "avc1.64001f");
+        record.level_idc = 10;
+        assert_eq!(record.codec_string(), "avc1.64000a");
+    }
+
+    #[test]
+    fn test_two_byte_start_codes() {
+        fn is_nal_unit_header(b: u8) -> bool {
+            b & 0b1000_0000 == 0 && matches!(b & 0b1_1111, 1 | 5 | 7 | 8 | 9)
+        }
+
+        let bytes = [
+            0x00, 0x01, 0x09, 0xf0, // AUD
+            0x00, 0x01, 0x67, 0x42, // SPS
+            0x00, 0x00, 0x01, 0x68, 0xce, // PPS (three bytes start code)
+            0x00, 0x01, 0x65, 0x88, 0x00, 0x01, 0xff, // IDR slice (`00 01 ff` is payload)
+        ];
+        assert!(ByteStreamFormatNalUnits::new(&bytes).is_err());
+
+        let nal_units = ByteStreamFormatNalUnits::new_tolerant(&bytes, is_nal_unit_header)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nal_units,
+            [
+                &[0x09, 0xf0][..],
+                &[0x67, 0x42][..],
+                &[0x68, 0xce][..],
+                &[0x65, 0x88, 0x00, 0x01, 0xff][..],
+            ]
+        );
+
+        // The default mode does not split on `00 01`
+        let nal_units = ByteStreamFormatNalUnits::new(&bytes[8..]).unwrap().collect::<Vec<_>>();
+        assert_eq!(nal_units, [&[0x68, 0xce, 0x00, 0x01, 0x65, 0x88, 0x00, 0x01, 0xff][..]]);
+    }
+
+    #[test]
+    fn test_is_append_compatible() {
+        let (sps, pps) = synthesize_parameter_sets(1280, 720, 77, 31).unwrap();
+        let record = AvcDecoderConfigurationRecord::from_parameter_sets(&sps, &pps).unwrap();
+        assert!(record.is_append_compatible(&record.clone()));
+
+        let mut other = record.clone();
+        other.set_level(40).unwrap();
+        assert!(!record.requires_change_type(&other));
+        assert!(!record.is_append_compatible(&other));
+
+        let (sps, pps) = synthesize_parameter_sets(640, 360, 77, 31).unwrap();
+        let other = AvcDecoderConfigurationRecord::from_parameter_sets(&sps, &pps).unwrap();
+        assert!(!record.is_append_compatible(&other));
+
+        // High profile without the extended configuration data is written without the trailer
+        let mut truncated = record.clone();
+        truncated.profile_idc = 100;
+        assert!(truncated.is_append_compatible(&truncated.clone()));
+        assert!(!truncated.is_append_compatible(&record));
     }
 }
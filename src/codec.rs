@@ -0,0 +1,199 @@
+//! Codec related constituent elements.
+use crate::{ErrorKind, Result};
+use std::io::Write;
+
+/// Video codec that a decoder configuration record targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Codec {
+    /// AVC (H.264).
+    H264,
+
+    /// HEVC (H.265).
+    H265,
+}
+impl Codec {
+    /// Returns the four-character code of the sample entry for the codec.
+    pub fn sample_entry_fourcc(self, mode: SampleEntryMode) -> [u8; 4] {
+        match (self, mode) {
+            (Codec::H264, SampleEntryMode::OutOfBand) => *b"avc1",
+            (Codec::H264, SampleEntryMode::InBand) => *b"avc3",
+            (Codec::H265, SampleEntryMode::OutOfBand) => *b"hvc1",
+            (Codec::H265, SampleEntryMode::InBand) => *b"hev1",
+        }
+    }
+}
+
+/// Size of the NAL unit length field preceding each NAL unit in MP4 samples.
+///
+/// This is shared by the decoder configuration records (`length_size_minus_one`)
+/// and the functions that frame or split length-prefixed NAL units,
+/// so that the record and the samples always agree on the size.
+/// Only `1`, `2` and `4` bytes are allowed (ISO/IEC 14496-15).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LengthSize(u8);
+impl LengthSize {
+    /// Four bytes, the size used by this crate by default.
+    pub const FOUR: LengthSize = LengthSize(4);
+
+    /// Makes a new `LengthSize` instance from the size in bytes.
+    pub fn new(size: u8) -> Result<Self> {
+        track_assert!(
+            [1, 2, 4].contains(&size),
+            ErrorKind::InvalidInput,
+            "Unsupported length size: {}",
+            size
+        );
+        Ok(LengthSize(size))
+    }
+
+    /// Makes a new `LengthSize` instance from the `length_size_minus_one` field of a record.
+    pub fn from_length_size_minus_one(length_size_minus_one: u8) -> Result<Self> {
+        track!(Self::new(length_size_minus_one.saturating_add(1)))
+    }
+
+    /// Returns the size in bytes.
+    pub fn get(self) -> usize {
+        usize::from(self.0)
+    }
+
+    /// Returns the value of the `length_size_minus_one` field of a record.
+    pub fn length_size_minus_one(self) -> u8 {
+        self.0 - 1
+    }
+
+    /// Writes the given NAL unit preceded by its length.
+    pub fn write_nal_unit<W: Write>(self, mut writer: W, nal_unit: &[u8]) -> Result<()> {
+        let len = nal_unit.len() as u64;
+        track_assert!(
+            len >> (8 * self.get()) == 0,
+            ErrorKind::InvalidInput,
+            "Too large NAL unit for {} bytes length: {} bytes",
+            self.0,
+            len
+        );
+        let prefix = len.to_be_bytes();
+        write_all!(writer, &prefix[prefix.len() - self.get()..]);
+        write_all!(writer, nal_unit);
+        Ok(())
+    }
+}
+
+/// Placement of parameter sets in a track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SampleEntryMode {
+    /// Parameter sets are stored only in the sample entry (`avc1` / `hvc1`).
+    OutOfBand,
+
+    /// Parameter sets may also be stored in the samples (`avc3` / `hev1`).
+    InBand,
+}
+
+/// Tolerance of the parsers for non-conformant input.
+///
+/// This is taken by the `read_from_with_options` variants of `AvcDecoderConfigurationRecord`,
+/// `SpsSummary` and `NalUnit` (see each of them for the checked deviations).
+/// The other parsers, including those of PPSs, SEI messages and HEVC, have no such variants
+/// and always behave as in the lenient mode.
+/// `ParseOptions::default()` is the lenient mode in which the plain `read_from` methods operate.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParseOptions {
+    /// If `true`, reserved-bit violations, parameter sets of unexpected NAL unit types
+    /// and trailing garbage are reported as `ErrorKind::InvalidInput` errors
+    /// instead of being tolerated.
+    ///
+    /// Unknown NAL unit types are rejected regardless of this flag.
+    pub strict: bool,
+
+    /// The maximum size of a NAL unit (including its header) in bytes (`None` if unlimited).
+    pub max_nal_size: Option<usize>,
+
+    /// If `true`, emulation prevention bytes are removed before parsing an SPS.
+    ///
+    /// Set this to `false` if the input has already been unescaped.
+    /// Only `SpsSummary::read_from_with_options` honours this.
+    pub remove_emulation_prevention: bool,
+}
+impl ParseOptions {
+    /// Returns the options of the strict mode.
+    pub fn strict() -> Self {
+        ParseOptions {
+            strict: true,
+            ..Self::default()
+        }
+    }
+
+    /// Checks that a NAL unit of `size` bytes does not exceed `max_nal_size`.
+    pub(crate) fn check_nal_size(&self, size: usize) -> Result<()> {
+        if let Some(max) = self.max_nal_size {
+            track_assert!(
+                size <= max,
+                ErrorKind::InvalidInput,
+                "Too large NAL unit: size={}, max_nal_size={}",
+                size,
+                max
+            );
+        }
+        Ok(())
+    }
+}
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            strict: false,
+            max_nal_size: None,
+            remove_emulation_prevention: true,
+        }
+    }
+}
+
+/// Returns `true` if `write_to` succeeds for both `a` and `b` and writes the same bytes.
+pub(crate) fn serializes_identically<T, F>(a: &T, b: &T, write_to: F) -> bool
+where
+    F: Fn(&T, &mut Vec<u8>) -> Result<()>,
+{
+    let serialize = |x: &T| {
+        let mut bytes = Vec::new();
+        write_to(x, &mut bytes).ok().map(|()| bytes)
+    };
+    match (serialize(a), serialize(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_size() {
+        assert!(LengthSize::new(3).is_err());
+        assert_eq!(LengthSize::from_length_size_minus_one(3).unwrap(), LengthSize::FOUR);
+
+        let length_size = LengthSize::new(2).unwrap();
+        assert_eq!(length_size.length_size_minus_one(), 1);
+
+        let mut bytes = Vec::new();
+        length_size.write_nal_unit(&mut bytes, &[0x09, 0xf0]).unwrap();
+        assert_eq!(bytes, [0, 2, 0x09, 0xf0]);
+
+        let length_size = LengthSize::new(1).unwrap();
+        assert!(length_size.write_nal_unit(Vec::new(), &[0; 256]).is_err());
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = ParseOptions::default();
+        assert!(!options.strict);
+        assert!(options.remove_emulation_prevention);
+        assert!(options.check_nal_size(usize::MAX).is_ok());
+
+        let options = ParseOptions {
+            max_nal_size: Some(4),
+            ..ParseOptions::strict()
+        };
+        assert!(options.strict);
+        assert!(options.check_nal_size(4).is_ok());
+        assert!(options.check_nal_size(5).is_err());
+    }
+}
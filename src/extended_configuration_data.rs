@@ -1,8 +1,383 @@
-#[derive(Clone,Debug)]
+use crate::avc;
+use crate::io::{AvcBitReader, AvcBitWriter};
+use crate::{ErrorKind, Result};
+use byteorder::ReadBytesExt;
+use std::io::{Read, Write};
+
+#[derive(Clone,Debug,PartialEq,Eq)]
 pub struct ExtendedConfigurationData{
     pub chroma_format: u64,
     pub separate_color_plane: Option<bool>,
     pub bit_depth_luma_minus_8: u64,
     pub bit_depth_chroma_minus_8: u64,
     pub qp_prime_y_zero_transform_bypass: bool,
-}
\ No newline at end of file
+
+    /// `seq_scaling_matrix_present_flag`.
+    pub seq_scaling_matrix_present: bool,
+
+    /// The 4x4 scaling lists (`None` if `seq_scaling_list_present_flag` is not set).
+    ///
+    /// Each list holds the explicitly coded scale values;
+    /// the remaining entries of the list repeat the last value.
+    pub seq_scaling_list_4x4: Vec<Option<Vec<u8>>>,
+
+    /// `UseDefaultScalingMatrix4x4Flag` for each of `seq_scaling_list_4x4`.
+    pub use_default_scaling_matrix_4x4: Vec<bool>,
+
+    /// The 8x8 scaling lists (`None` if `seq_scaling_list_present_flag` is not set).
+    ///
+    /// Each list holds the explicitly coded scale values;
+    /// the remaining entries of the list repeat the last value.
+    pub seq_scaling_list_8x8: Vec<Option<Vec<u8>>>,
+
+    /// `UseDefaultScalingMatrix8x8Flag` for each of `seq_scaling_list_8x8`.
+    pub use_default_scaling_matrix_8x8: Vec<bool>,
+
+    /// The SPS extension NAL units of the `avcC` trailer (always empty if read from an SPS).
+    pub sequence_parameter_set_ext: Vec<Vec<u8>>,
+}
+impl ExtendedConfigurationData {
+    /// Reads the trailer of an `avcC` record (the bytes following the PPS array).
+    ///
+    /// `profile_idc` must be one of the High profiles that carry the trailer
+    /// (`100`, `110`, `122` or `144`).
+    ///
+    /// The trailer (5.3.3.1 of ISO/IEC 14496-15) only has the chroma format, the bit depths
+    /// and the SPS extension NAL units; the fields that are only found in the SPS
+    /// are left unset (no `separate_color_plane` and no scaling matrix).
+    pub fn read_from_trailer(mut bytes: &[u8], profile_idc: u8) -> Result<Self> {
+        track_assert!(
+            matches!(profile_idc, 100 | 110 | 122 | 144),
+            ErrorKind::InvalidInput,
+            "profile_idc {} has no extended configuration data",
+            profile_idc
+        );
+        let reader = &mut bytes;
+        let chroma_format = u64::from(track_io!(reader.read_u8())? & 0b0000_0011);
+        let bit_depth_luma_minus_8 = u64::from(track_io!(reader.read_u8())? & 0b0000_0111);
+        let bit_depth_chroma_minus_8 = u64::from(track_io!(reader.read_u8())? & 0b0000_0111);
+        let num_of_sequence_parameter_set_ext = track_io!(reader.read_u8())?;
+        let sequence_parameter_set_ext =
+            track!(avc::read_parameter_sets(reader, num_of_sequence_parameter_set_ext))?;
+        Ok(ExtendedConfigurationData {
+            chroma_format,
+            separate_color_plane: None,
+            bit_depth_luma_minus_8,
+            bit_depth_chroma_minus_8,
+            qp_prime_y_zero_transform_bypass: false,
+            seq_scaling_matrix_present: false,
+            seq_scaling_list_4x4: Vec::new(),
+            use_default_scaling_matrix_4x4: Vec::new(),
+            seq_scaling_list_8x8: Vec::new(),
+            use_default_scaling_matrix_8x8: Vec::new(),
+            sequence_parameter_set_ext,
+        })
+    }
+
+    /// Writes the trailer of an `avcC` record (the bytes following the PPS array).
+    ///
+    /// This is the counterpart of `read_from_trailer`; the fields that are only found in
+    /// the SPS are not written.
+    pub fn write_trailer_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        track_assert!(self.chroma_format <= 3, ErrorKind::InvalidInput);
+        track_assert!(self.bit_depth_luma_minus_8 <= 7, ErrorKind::InvalidInput);
+        track_assert!(self.bit_depth_chroma_minus_8 <= 7, ErrorKind::InvalidInput);
+        track_assert!(self.sequence_parameter_set_ext.len() <= 0xFF, ErrorKind::InvalidInput);
+        write_u8!(writer, 0b1111_1100 | self.chroma_format as u8);
+        write_u8!(writer, 0b1111_1000 | self.bit_depth_luma_minus_8 as u8);
+        write_u8!(writer, 0b1111_1000 | self.bit_depth_chroma_minus_8 as u8);
+        write_u8!(writer, self.sequence_parameter_set_ext.len() as u8);
+        for sps_ext in &self.sequence_parameter_set_ext {
+            track_assert!(sps_ext.len() <= 0xFFFF, ErrorKind::InvalidInput);
+            write_u16!(writer, sps_ext.len() as u16);
+            write_all!(writer, sps_ext);
+        }
+        Ok(())
+    }
+
+    /// Reads the chroma format and the following fields of a High profile SPS.
+    ///
+    /// This is only for `SpsSummary`; the `avcC` trailer is read by `read_from_trailer`.
+    pub(crate) fn read_from_bit_reader<R: Read>(reader: &mut AvcBitReader<R>) -> Result<Self> {
+        let chroma_format = track!(reader.read_ue())?;
+        let separate_color_plane = if chroma_format == 3 {
+            //YUV 444
+            Some(track!(reader.read_bit())? == 1)
+        } else {
+            None
+        };
+        let bit_depth_luma_minus_8 = track!(reader.read_ue())?;
+        let bit_depth_chroma_minus_8 = track!(reader.read_ue())?;
+        let qp_prime_y_zero_transform_bypass = track!(reader.read_bit())? == 1;
+        let scaling_matrix_present = track!(reader.read_bit())? == 1;
+
+        let mut seq_scaling_list_4x4 = Vec::new();
+        let mut use_default_scaling_matrix_4x4 = Vec::new();
+        let mut seq_scaling_list_8x8 = Vec::new();
+        let mut use_default_scaling_matrix_8x8 = Vec::new();
+        if scaling_matrix_present {
+            // NOTE: Monochrome streams (`chroma_format == 0`) also signal eight lists;
+            // the chroma lists are parsed but not used by the decoder (7.4.2.1.1).
+            for i in 0..scaling_list_count(chroma_format) {
+                let (lists, use_defaults, size) = if i < 6 {
+                    (&mut seq_scaling_list_4x4, &mut use_default_scaling_matrix_4x4, 16)
+                } else {
+                    (&mut seq_scaling_list_8x8, &mut use_default_scaling_matrix_8x8, 64)
+                };
+                let seq_scaling_list_present_flag = track!(reader.read_bit())? == 1;
+                if seq_scaling_list_present_flag {
+                    let (list, use_default) = track!(read_scaling_list(reader, size))?;
+                    lists.push(Some(list));
+                    use_defaults.push(use_default);
+                } else {
+                    lists.push(None);
+                    use_defaults.push(false);
+                }
+            }
+        }
+
+        Ok(ExtendedConfigurationData {
+            chroma_format,
+            separate_color_plane,
+            bit_depth_luma_minus_8,
+            bit_depth_chroma_minus_8,
+            qp_prime_y_zero_transform_bypass,
+            seq_scaling_matrix_present: scaling_matrix_present,
+            seq_scaling_list_4x4,
+            use_default_scaling_matrix_4x4,
+            seq_scaling_list_8x8,
+            use_default_scaling_matrix_8x8,
+            sequence_parameter_set_ext: Vec::new(),
+        })
+    }
+
+    /// Writes the chroma format and the following fields of a High profile SPS.
+    ///
+    /// This is only for `SpsSummary`; the `avcC` trailer is written by `write_trailer_to`.
+    ///
+    /// If `seq_scaling_matrix_present` is `true`, `scaling_list_count(chroma_format)` lists
+    /// (six 4x4 ones followed by the 8x8 ones) are required.
+    /// A list whose `UseDefaultScalingMatrix*Flag` is set is written as such,
+    /// regardless of its scale values.
+    pub(crate) fn write_to_bit_writer<W: Write>(&self, writer: &mut AvcBitWriter<W>) -> Result<()> {
+        track!(self.validate())?;
+        track!(writer.write_ue(self.chroma_format))?;
+        if let Some(separate_color_plane) = self.separate_color_plane {
+            track!(writer.write_bool(separate_color_plane))?;
+        }
+        track!(writer.write_ue(self.bit_depth_luma_minus_8))?;
+        track!(writer.write_ue(self.bit_depth_chroma_minus_8))?;
+        track!(writer.write_bool(self.qp_prime_y_zero_transform_bypass))?;
+        track!(writer.write_bool(self.seq_scaling_matrix_present))?;
+        if !self.seq_scaling_matrix_present {
+            return Ok(());
+        }
+
+        track_assert_eq!(self.seq_scaling_list_4x4.len(), 6, ErrorKind::InvalidInput);
+        track_assert_eq!(
+            self.seq_scaling_list_4x4.len() + self.seq_scaling_list_8x8.len(),
+            scaling_list_count(self.chroma_format),
+            ErrorKind::InvalidInput
+        );
+        track_assert!(
+            self.use_default_scaling_matrix_4x4.len() == 6
+                && self.use_default_scaling_matrix_8x8.len() == self.seq_scaling_list_8x8.len(),
+            ErrorKind::InvalidInput,
+            "Missing UseDefaultScalingMatrix flags"
+        );
+        let lists_4x4 = self.seq_scaling_list_4x4.iter().zip(&self.use_default_scaling_matrix_4x4);
+        let lists_8x8 = self.seq_scaling_list_8x8.iter().zip(&self.use_default_scaling_matrix_8x8);
+        let lists = lists_4x4.map(|x| (x, 16)).chain(lists_8x8.map(|x| (x, 64)));
+        for ((list, &use_default), size) in lists {
+            track!(writer.write_bool(list.is_some()))?;
+            if let Some(list) = list {
+                let list = if use_default { &[][..] } else { &list[..] };
+                track!(write_scaling_list(writer, list, size))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the consistency between the fields.
+    ///
+    /// `separate_color_plane` must be present if and only if `chroma_format` is `3` (4:4:4).
+    pub fn validate(&self) -> Result<()> {
+        track_assert_eq!(
+            self.separate_color_plane.is_some(),
+            self.chroma_format == 3,
+            ErrorKind::InvalidInput,
+            "chroma_format={}, separate_color_plane={:?}",
+            self.chroma_format,
+            self.separate_color_plane
+        );
+        Ok(())
+    }
+
+    /// Returns the larger of the luma and chroma bit depths.
+    pub fn max_bit_depth(&self) -> u8 {
+        8 + self.bit_depth_luma_minus_8.max(self.bit_depth_chroma_minus_8) as u8
+    }
+}
+
+/// Returns the number of `seq_scaling_list_present_flag`s (and thus of scaling lists)
+/// signaled by an SPS with the given `chroma_format_idc` (7.3.2.1.1, ISO/IEC 14496-10).
+///
+/// There are six 4x4 lists, followed by two 8x8 lists (luma intra and inter) or,
+/// for 4:4:4 (`chroma_format_idc == 3`), six 8x8 lists (adding Cb and Cr).
+/// The read and write paths of the scaling matrix must both process this many lists.
+pub fn scaling_list_count(chroma_format_idc: u64) -> usize {
+    if chroma_format_idc != 3 {
+        8
+    } else {
+        12
+    }
+}
+
+/// Reads a `scaling_list()` (7.3.2.1.1.1, ISO/IEC 14496-10).
+///
+/// Returns the explicitly coded scale values and `useDefaultScalingMatrixFlag`.
+fn read_scaling_list<R: Read>(
+    reader: &mut AvcBitReader<R>,
+    size: usize,
+) -> Result<(Vec<u8>, bool)> {
+    let mut list = Vec::with_capacity(size);
+    let mut last_scale = 8;
+    for j in 0..size {
+        let delta_scale = track!(reader.read_se())?;
+        track_assert!((-128..=127).contains(&delta_scale), ErrorKind::InvalidInput);
+        let next_scale = (last_scale + delta_scale + 256) % 256;
+        if next_scale == 0 {
+            return Ok((list, j == 0));
+        }
+        list.push(next_scale as u8);
+        last_scale = next_scale;
+    }
+    Ok((list, false))
+}
+
+/// Writes a `scaling_list()` holding the explicitly coded scale values
+/// (7.3.2.1.1.1, ISO/IEC 14496-10).
+///
+/// An empty `list` signals `useDefaultScalingMatrixFlag`.
+fn write_scaling_list<W: Write>(
+    writer: &mut AvcBitWriter<W>,
+    list: &[u8],
+    size: usize,
+) -> Result<()> {
+    track_assert!(list.len() <= size, ErrorKind::InvalidInput);
+    let mut last_scale = 8;
+    for &scale in list {
+        track_assert_ne!(scale, 0, ErrorKind::InvalidInput);
+        track!(writer.write_se(delta_scale(last_scale, i64::from(scale))))?;
+        last_scale = i64::from(scale);
+    }
+    if list.len() < size {
+        track!(writer.write_se(delta_scale(last_scale, 0)))?;
+    }
+    Ok(())
+}
+
+fn delta_scale(last_scale: i64, next_scale: i64) -> i64 {
+    (next_scale - last_scale + 128 + 256) % 256 - 128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_from_trailer() {
+        // chroma_format=1, bit_depth_luma_minus_8=0, bit_depth_chroma_minus_8=0
+        let data = ExtendedConfigurationData::read_from_trailer(&[0xfd, 0xf8, 0xf8, 0x00], 100)
+            .unwrap();
+        assert_eq!(data.chroma_format, 1);
+        assert_eq!(data.separate_color_plane, None);
+        assert_eq!(data.max_bit_depth(), 8);
+        assert!(!data.seq_scaling_matrix_present);
+
+        // chroma_format=3, bit_depth_luma_minus_8=2, bit_depth_chroma_minus_8=0
+        let data = ExtendedConfigurationData::read_from_trailer(&[0xff, 0xfa, 0xf8, 0x00], 122)
+            .unwrap();
+        assert_eq!(data.chroma_format, 3);
+        assert_eq!(data.separate_color_plane, None);
+        assert_eq!(data.bit_depth_luma_minus_8, 2);
+        assert_eq!(data.max_bit_depth(), 10);
+
+        assert!(ExtendedConfigurationData::read_from_trailer(&[0xfd, 0xf8, 0xf8, 0x00], 66)
+            .is_err());
+    }
+
+    #[test]
+    fn test_trailer_round_trip() {
+        // As written by x264 and FFmpeg
+        let bytes = [0xfd, 0xf8, 0xf8, 0x00];
+        let data = ExtendedConfigurationData::read_from_trailer(&bytes, 100).unwrap();
+        assert!(data.sequence_parameter_set_ext.is_empty());
+        let mut actual = Vec::new();
+        data.write_trailer_to(&mut actual).unwrap();
+        assert_eq!(actual, bytes);
+
+        // 4:2:2, 10 bits, with an SPS extension NAL unit
+        let bytes = [0xfe, 0xfa, 0xfa, 0x01, 0x00, 0x03, 0x6d, 0x00, 0x80];
+        let data = ExtendedConfigurationData::read_from_trailer(&bytes, 122).unwrap();
+        assert_eq!(data.chroma_format, 2);
+        assert_eq!(data.bit_depth_chroma_minus_8, 2);
+        assert_eq!(data.sequence_parameter_set_ext, [vec![0x6d, 0x00, 0x80]]);
+        let mut actual = Vec::new();
+        data.write_trailer_to(&mut actual).unwrap();
+        assert_eq!(actual, bytes);
+
+        assert!(ExtendedConfigurationData::read_from_trailer(&bytes[..3], 122).is_err());
+        assert!(ExtendedConfigurationData::read_from_trailer(&bytes[..8], 122).is_err());
+    }
+
+    #[test]
+    fn test_write_scaling_matrix() {
+        let data = ExtendedConfigurationData {
+            chroma_format: 3,
+            separate_color_plane: Some(false),
+            bit_depth_luma_minus_8: 0,
+            bit_depth_chroma_minus_8: 0,
+            qp_prime_y_zero_transform_bypass: false,
+            seq_scaling_matrix_present: true,
+            seq_scaling_list_4x4: vec![Some(vec![6, 13, 20]), None, Some(Vec::new())]
+                .into_iter()
+                .chain(vec![None; 3])
+                .collect(),
+            use_default_scaling_matrix_4x4: vec![false, false, true, false, false, false],
+            seq_scaling_list_8x8: vec![None, Some(vec![16; 64]), None, None, Some(vec![9]), None],
+            use_default_scaling_matrix_8x8: vec![false; 6],
+            sequence_parameter_set_ext: Vec::new(),
+        };
+        let mut bytes = Vec::new();
+        let mut writer = AvcBitWriter::new(&mut bytes);
+        data.write_to_bit_writer(&mut writer).unwrap();
+        writer.write_rbsp_trailing_bits().unwrap();
+        let mut reader = AvcBitReader::new(&bytes[..]);
+        assert_eq!(ExtendedConfigurationData::read_from_bit_reader(&mut reader).unwrap(), data);
+
+        // The default matrix is signaled even if the scale values are given
+        let mut with_values = data.clone();
+        with_values.seq_scaling_list_4x4[2] = Some(vec![1, 2, 3]);
+        let mut actual = Vec::new();
+        let mut writer = AvcBitWriter::new(&mut actual);
+        with_values.write_to_bit_writer(&mut writer).unwrap();
+        writer.write_rbsp_trailing_bits().unwrap();
+        assert_eq!(actual, bytes);
+
+        // 4:4:4 requires twelve lists
+        let mut short = data;
+        short.seq_scaling_list_8x8.truncate(2);
+        short.use_default_scaling_matrix_8x8.truncate(2);
+        let mut writer = AvcBitWriter::new(Vec::new());
+        assert!(short.write_to_bit_writer(&mut writer).is_err());
+    }
+
+    #[test]
+    fn test_scaling_list_count() {
+        assert_eq!(scaling_list_count(0), 8);
+        assert_eq!(scaling_list_count(1), 8);
+        assert_eq!(scaling_list_count(2), 8);
+        assert_eq!(scaling_list_count(3), 12);
+    }
+}
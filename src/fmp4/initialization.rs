@@ -1,5 +1,8 @@
 use crate::aac::{AacProfile, ChannelConfiguration, SamplingFrequency};
-use crate::avc::AvcDecoderConfigurationRecord;
+use crate::avc::{AvcDecoderConfigurationRecord, SpsSummary};
+use crate::codec::LengthSize;
+use crate::hevc::HvcDecoderConfigurationRecord;
+use crate::vvc::VvcDecoderConfigurationRecord;
 use crate::fmp4::{Mp4Box, AUDIO_TRACK_ID, VIDEO_TRACK_ID};
 use crate::io::{ByteCounter, WriteTo};
 use crate::{ErrorKind, Result};
@@ -34,6 +37,77 @@ impl WriteTo for InitializationSegment {
     }
 }
 
+/// Writes a minimal initialization segment (`ftyp` and `moov`) of a video-only AVC stream.
+///
+/// The `avc1` sample entry takes its dimensions from the SPS of `record`,
+/// while `width` and `height` are the presentation size of the track (`tkhd`).
+/// `timescale` is used for both the movie and the media; all durations are written as `0`
+/// (i.e., unknown), since the media segments are expected to be appended by MSE.
+///
+/// `record.length_size` must be `LengthSize::FOUR`, the size used by `write_media_segment()`.
+pub fn write_init_segment<W: Write>(
+    record: &AvcDecoderConfigurationRecord,
+    width: u32,
+    height: u32,
+    timescale: u32,
+    writer: W,
+) -> Result<()> {
+    track_assert!(
+        width <= 0xFFFF && height <= 0xFFFF,
+        ErrorKind::InvalidInput,
+        "Too large presentation size: {}x{}",
+        width,
+        height
+    );
+    track_assert!(
+        record.length_size == LengthSize::FOUR,
+        ErrorKind::InvalidInput,
+        "The media segments use four-byte NAL unit lengths, but the record uses {} bytes",
+        record.length_size.get()
+    );
+    let sps = track!(SpsSummary::from_nal_unit(&record.sequence_parameter_set, false))?;
+    track_assert!(sps.width() <= 0xFFFF && sps.height() <= 0xFFFF, ErrorKind::InvalidInput);
+
+    let mut segment = InitializationSegment {
+        ftyp_box: FileTypeBox::new_with_default(),
+        moov_box: MovieBox::default(),
+    };
+    segment.ftyp_box.compatible_brands =
+        ["isom", "iso6", "avc1"].iter().map(|b| b.to_string()).collect();
+    segment.moov_box.mvhd_box.timescale = timescale;
+    segment.moov_box.mvhd_box.duration = 0;
+
+    let mut track = TrackBox::new(true);
+    track.tkhd_box.width = width << 16;
+    track.tkhd_box.height = height << 16;
+    track.tkhd_box.duration = 0;
+    track.mdia_box.mdhd_box.timescale = timescale;
+    track.mdia_box.mdhd_box.duration = 0;
+
+    let avc_sample_entry = AvcSampleEntry {
+        width: sps.width() as u16,
+        height: sps.height() as u16,
+        avcc_box: AvcConfigurationBox {
+            configuration: record.clone(),
+        },
+    };
+    track
+        .mdia_box
+        .minf_box
+        .stbl_box
+        .stsd_box
+        .sample_entries
+        .push(SampleEntry::Avc(Box::new(avc_sample_entry)));
+    segment.moov_box.trak_boxes.push(track);
+    segment
+        .moov_box
+        .mvex_box
+        .trex_boxes
+        .push(TrackExtendsBox::new(true));
+
+    track!(segment.write_to(writer))
+}
+
 /// 4.3 File Type Box (ISO/IEC 14496-12).
 #[allow(missing_docs)]
 #[derive(Debug, Default)]
@@ -769,7 +843,7 @@ impl Mp4Box for SampleToChunkBox {
 #[allow(missing_docs)]
 #[derive(Debug)]
 pub enum SampleEntry {
-    Avc(AvcSampleEntry),
+    Avc(Box<AvcSampleEntry>),
     Aac(AacSampleEntry),
 }
 impl SampleEntry {
@@ -837,6 +911,25 @@ impl Mp4Box for AvcSampleEntry {
 pub struct AvcConfigurationBox {
     pub configuration: AvcDecoderConfigurationRecord,
 }
+impl AvcConfigurationBox {
+    /// Writes the box (including the box header) at the beginning of the given buffer
+    /// and returns the number of bytes written.
+    ///
+    /// The required size is `box_size()`. An `ErrorKind::InvalidInput` error is returned
+    /// if `buf` is smaller than that, in which case its content is left untouched.
+    pub fn write_box_into(&self, buf: &mut [u8]) -> Result<usize> {
+        let size = track!(self.box_size())? as usize;
+        track_assert!(
+            size <= buf.len(),
+            ErrorKind::InvalidInput,
+            "Too small buffer: required={}, actual={}",
+            size,
+            buf.len()
+        );
+        track!(self.write_box(&mut buf[..size]))?;
+        Ok(size)
+    }
+}
 impl Mp4Box for AvcConfigurationBox {
     const BOX_TYPE: [u8; 4] = *b"avcC";
 
@@ -849,6 +942,65 @@ impl Mp4Box for AvcConfigurationBox {
     }
 }
 
+/// Box that contains HEVC Decoder Configuration Record.
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct HvcConfigurationBox {
+    pub configuration: HvcDecoderConfigurationRecord,
+}
+impl Mp4Box for HvcConfigurationBox {
+    const BOX_TYPE: [u8; 4] = *b"hvcC";
+
+    fn box_payload_size(&self) -> Result<u32> {
+        let size = track!(ByteCounter::calculate(|w| self.configuration.write_to(w)))?;
+        Ok(size as u32)
+    }
+    fn write_box_payload<W: Write>(&self, writer: W) -> Result<()> {
+        track!(self.configuration.write_to(writer))
+    }
+}
+
+/// Box that contains VVC Decoder Configuration Record.
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct VvcConfigurationBox {
+    pub configuration: VvcDecoderConfigurationRecord,
+}
+impl Mp4Box for VvcConfigurationBox {
+    const BOX_TYPE: [u8; 4] = *b"vvcC";
+
+    fn box_version(&self) -> Option<u8> {
+        Some(0)
+    }
+    fn box_payload_size(&self) -> Result<u32> {
+        let size = track!(ByteCounter::calculate(|w| self.configuration.write_to(w)))?;
+        Ok(size as u32)
+    }
+    fn write_box_payload<W: Write>(&self, writer: W) -> Result<()> {
+        track!(self.configuration.write_to(writer))
+    }
+}
+
+/// 12.1.4 Pixel Aspect Ratio Box (ISO/IEC 14496-12).
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelAspectRatioBox {
+    pub h_spacing: u32,
+    pub v_spacing: u32,
+}
+impl Mp4Box for PixelAspectRatioBox {
+    const BOX_TYPE: [u8; 4] = *b"pasp";
+
+    fn box_payload_size(&self) -> Result<u32> {
+        Ok(4 + 4)
+    }
+    fn write_box_payload<W: Write>(&self, mut writer: W) -> Result<()> {
+        write_u32!(writer, self.h_spacing);
+        write_u32!(writer, self.v_spacing);
+        Ok(())
+    }
+}
+
 /// Sample Entry for AAC.
 #[allow(missing_docs)]
 #[derive(Debug)]
@@ -945,3 +1097,51 @@ impl Mp4Box for Mpeg4EsDescriptorBox {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::avc::tests::{BASELINE_PPS, BASELINE_SPS};
+
+    #[test]
+    fn test_write_init_segment() {
+        let record =
+            AvcDecoderConfigurationRecord::from_parameter_sets(&BASELINE_SPS, &BASELINE_PPS)
+                .unwrap();
+        let mut bytes = Vec::new();
+        write_init_segment(&record, 640, 480, 90_000, &mut bytes).unwrap();
+
+        let ftyp_size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        assert_eq!(&bytes[4..12], b"ftypisom");
+        let moov = &bytes[ftyp_size..];
+        assert_eq!(&moov[4..8], b"moov");
+        assert_eq!(u32::from_be_bytes([moov[0], moov[1], moov[2], moov[3]]) as usize, moov.len());
+
+        let find = |name: &[u8]| bytes.windows(4).position(|w| w == name).unwrap();
+        for name in [b"trak", b"mdia", b"minf", b"stbl", b"stsd", b"mvex"].iter() {
+            find(&name[..]);
+        }
+        let tkhd = find(b"tkhd");
+        assert_eq!(&bytes[tkhd + 80..tkhd + 88], [0x02, 0x80, 0, 0, 0x01, 0xe0, 0, 0]);
+        let mdhd = find(b"mdhd");
+        assert_eq!(&bytes[mdhd + 16..mdhd + 20], 90_000u32.to_be_bytes());
+
+        // The sample entry has the size in the SPS (the first "avc1" is a compatible brand)
+        let avc1 = bytes.windows(4).rposition(|w| w == b"avc1").unwrap();
+        assert_eq!(&bytes[avc1 + 28..avc1 + 32], [0x02, 0xd0, 0x01, 0xe0]);
+        let mut avcc = Vec::new();
+        AvcConfigurationBox {
+            configuration: record.clone(),
+        }
+        .write_box(&mut avcc)
+        .unwrap();
+        assert_eq!(&bytes[find(b"avcC") - 4..][..avcc.len()], &avcc[..]);
+
+        assert!(write_init_segment(&record, 0x1_0000, 480, 90_000, Vec::new()).is_err());
+
+        // The NAL unit lengths must match those of `write_media_segment()`
+        let mut two_bytes = record;
+        two_bytes.length_size = LengthSize::new(2).unwrap();
+        assert!(write_init_segment(&two_bytes, 640, 480, 90_000, Vec::new()).is_err());
+    }
+}
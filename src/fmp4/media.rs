@@ -1,8 +1,66 @@
+use crate::access_unit::{composition_time_offsets, AuInfo};
+use crate::codec::LengthSize;
 use crate::fmp4::{Mp4Box, AUDIO_TRACK_ID, VIDEO_TRACK_ID};
 use crate::io::{ByteCounter, WriteTo};
 use crate::{ErrorKind, Result};
 use std::io::Write;
 
+/// Writes a media segment (`moof` and `mdat`) of the video track made by `write_init_segment()`.
+///
+/// Each access unit becomes a sample whose NAL units (including parameter sets, if any)
+/// are prefixed with four-byte lengths, so the record given to `write_init_segment()` must
+/// use `LengthSize::FOUR`.
+/// The sample durations are the `duration`s of `aus`, the sync samples are the ones
+/// containing an IDR picture, and the composition time offsets are derived from
+/// the picture order counts by `composition_time_offsets()`.
+pub fn write_media_segment<W: Write>(
+    aus: &[AuInfo],
+    sequence_number: u32,
+    base_decode_time: u64,
+    writer: W,
+) -> Result<()> {
+    track_assert!(!aus.is_empty(), ErrorKind::InvalidInput, "No access units");
+    let offsets = track!(composition_time_offsets(aus))?;
+
+    let mut traf = TrackFragmentBox::new(true);
+    traf.tfdt_box.base_media_decode_time = base_decode_time;
+    traf.trun_box.data_offset = Some(0); // dummy
+    let mut data = Vec::new();
+    for (au, &offset) in aus.iter().zip(offsets.iter()) {
+        let size = data.len();
+        for nal_unit in &au.nal_units {
+            track!(LengthSize::FOUR.write_nal_unit(&mut data, nal_unit))?;
+        }
+        track_assert!(au.duration <= 0xFFFF_FFFF, ErrorKind::InvalidInput);
+        track_assert!(
+            i64::from(i32::MIN) <= offset && offset <= i64::from(i32::MAX),
+            ErrorKind::InvalidInput
+        );
+        traf.trun_box.samples.push(Sample {
+            duration: Some(au.duration as u32),
+            size: Some((data.len() - size) as u32),
+            flags: Some(SampleFlags {
+                is_leading: 0,
+                sample_depends_on: if au.is_sync { 2 } else { 1 },
+                sample_is_depdended_on: 0,
+                sample_has_redundancy: 0,
+                sample_padding_value: 0,
+                sample_is_non_sync_sample: !au.is_sync,
+                sample_degradation_priority: 0,
+            }),
+            composition_time_offset: Some(offset as i32),
+        });
+    }
+
+    let mut segment = MediaSegment::default();
+    segment.moof_box.mfhd_box.sequence_number = sequence_number;
+    segment.moof_box.traf_boxes.push(traf);
+    let moof_size = track!(segment.moof_box.box_size())?;
+    segment.moof_box.traf_boxes[0].trun_box.data_offset = Some(moof_size as i32 + 8);
+    segment.mdat_boxes.push(MediaDataBox { data });
+    track!(segment.write_to(writer))
+}
+
 /// [ISO BMFF Byte Stream Format: 4. Media Segments][media_segment]
 ///
 /// [media_segment]: https://w3c.github.io/media-source/isobmff-byte-stream-format.html#iso-media-segments
@@ -248,8 +306,13 @@ pub struct TrackRunBox {
 impl Mp4Box for TrackRunBox {
     const BOX_TYPE: [u8; 4] = *b"trun";
 
+    /// Returns `1` (signed composition time offsets) if any offset is negative, `0` otherwise.
     fn box_version(&self) -> Option<u8> {
-        Some(1)
+        let has_negative_offset = self
+            .samples
+            .iter()
+            .any(|sample| matches!(sample.composition_time_offset, Some(x) if x < 0));
+        Some(has_negative_offset as u8)
     }
     fn box_flags(&self) -> Option<u32> {
         let sample = self
@@ -412,3 +475,55 @@ impl SampleFlags {
             | u32::from(self.sample_degradation_priority)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_unit::analyze_access_units;
+    use crate::access_unit::tests::IBP_STREAM;
+
+    #[test]
+    fn test_write_media_segment() {
+        let mut aus = analyze_access_units(&IBP_STREAM).unwrap();
+        for au in &mut aus {
+            au.duration = 1000;
+        }
+        let mut segment = Vec::new();
+        write_media_segment(&aus, 7, 3000, &mut segment).unwrap();
+        let be32 = |i: usize| {
+            u32::from_be_bytes([segment[i], segment[i + 1], segment[i + 2], segment[i + 3]])
+        };
+        assert_eq!(&segment[4..8], b"moof");
+        assert_eq!(&segment[12..16], b"mfhd");
+        assert_eq!(be32(20), 7);
+        let moof_size = be32(0) as usize;
+        assert_eq!(&segment[moof_size + 4..moof_size + 8], b"mdat");
+        assert_eq!(be32(moof_size) as usize, segment.len() - moof_size);
+
+        let find = |name: &[u8]| segment.windows(4).position(|w| w == name).unwrap();
+        let tfdt = find(b"tfdt");
+        assert_eq!(&segment[tfdt + 8..tfdt + 16], 3000u64.to_be_bytes());
+
+        // sample_count, data_offset, and then (duration, size, flags, offset) per sample
+        let trun = find(b"trun");
+        assert_eq!(be32(trun + 8), 3);
+        assert_eq!(be32(trun + 12) as usize, moof_size + 8);
+        assert_eq!(be32(trun + 16), 1000);
+        assert_eq!(be32(trun + 20), 4 * 3 + 10 + 4 + 4);
+        assert_eq!(be32(trun + 24), 0x0200_0000);
+        assert_eq!(be32(trun + 40), 0x0101_0000);
+        assert_eq!(be32(trun + 44), 1000);
+        assert_eq!(be32(trun + 60) as i32, -1000);
+        assert_eq!(segment[trun + 4], 1); // version
+        assert_eq!(be32(moof_size + 8), 10);
+        assert_eq!(segment[moof_size + 12], 0x67);
+
+        // Without the B picture, all the offsets are non-negative
+        let mut segment = Vec::new();
+        write_media_segment(&aus[..2], 8, 0, &mut segment).unwrap();
+        let trun = segment.windows(4).position(|w| w == b"trun").unwrap();
+        assert_eq!(segment[trun + 4], 0);
+
+        assert!(write_media_segment(&[], 1, 0, Vec::new()).is_err());
+    }
+}
@@ -3,16 +3,16 @@ pub use self::common::Mp4Box;
 pub use self::initialization::{
     AacSampleEntry, AvcConfigurationBox, AvcSampleEntry, ChunkOffsetBox, DataEntryUrlBox,
     DataInformationBox, DataReferenceBox, EditBox, EditListBox, FileTypeBox, HandlerReferenceBox,
-    InitializationSegment, MediaBox, MediaHeaderBox, MediaInformationBox, MovieBox,
-    MovieExtendsBox, MovieExtendsHeaderBox, MovieHeaderBox, Mpeg4EsDescriptorBox,
-    SampleDescriptionBox, SampleEntry, SampleSizeBox, SampleTableBox, SampleToChunkBox,
-    SoundMediaHeaderBox, TimeToSampleBox, TrackBox, TrackExtendsBox, TrackHeaderBox,
-    VideoMediaHeaderBox,
+    HvcConfigurationBox, InitializationSegment, MediaBox, MediaHeaderBox, MediaInformationBox,
+    MovieBox, MovieExtendsBox, MovieExtendsHeaderBox, MovieHeaderBox, Mpeg4EsDescriptorBox,
+    PixelAspectRatioBox, SampleDescriptionBox, SampleEntry, SampleSizeBox, SampleTableBox,
+    SampleToChunkBox, SoundMediaHeaderBox, TimeToSampleBox, TrackBox, TrackExtendsBox,
+    TrackHeaderBox, VideoMediaHeaderBox, VvcConfigurationBox, write_init_segment,
 };
 pub use self::media::{
     MediaDataBox, MediaSegment, MovieFragmentBox, MovieFragmentHeaderBox, Sample, SampleFlags,
     TrackFragmentBaseMediaDecodeTimeBox, TrackFragmentBox, TrackFragmentHeaderBox, TrackRunBox,
-    EventMessageBox
+    EventMessageBox, write_media_segment,
 };
 
 const VIDEO_TRACK_ID: u32 = 1;
@@ -0,0 +1,1745 @@
+//! HEVC (H.265) related constituent elements.
+use crate::avc::{AspectRatioInfo, ByteStreamFormatNalUnits, ChromaLocInfo, VideoSignalType};
+use crate::codec::{serializes_identically, Codec, LengthSize, SampleEntryMode};
+use crate::fmp4::{HvcConfigurationBox, Mp4Box};
+use crate::io::{AvcBitReader, RbspReader};
+use crate::sei::{SeiMessages, UserDataRegisteredItuTT35};
+use crate::{ErrorKind, Result};
+use byteorder::{BigEndian, ReadBytesExt};
+use std::cmp;
+use std::io::{Read, Write};
+use std::ops::Range;
+
+/// HEVC NAL unit header (7.3.1.2, ISO/IEC 23008-2).
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct HevcNalUnit {
+    pub nal_unit_type: HevcNalUnitType,
+    pub nuh_layer_id: u8,
+    pub nuh_temporal_id_plus1: u8,
+}
+impl HevcNalUnit {
+    /// Reads the two bytes NAL unit header.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let n = track_io!(reader.read_u16::<BigEndian>())?;
+        track_assert_eq!(n >> 15, 0, ErrorKind::InvalidInput); // forbidden_zero_bit
+
+        let nal_unit_type = track!(HevcNalUnitType::from_u8(((n >> 9) & 0b11_1111) as u8))?;
+        let nuh_layer_id = ((n >> 3) & 0b11_1111) as u8;
+        let nuh_temporal_id_plus1 = (n & 0b111) as u8;
+        track_assert_ne!(nuh_temporal_id_plus1, 0, ErrorKind::InvalidInput);
+        Ok(HevcNalUnit {
+            nal_unit_type,
+            nuh_layer_id,
+            nuh_temporal_id_plus1,
+        })
+    }
+}
+
+/// HEVC NAL unit type (Table 7-1, ISO/IEC 23008-2).
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HevcNalUnitType {
+    TrailN = 0,
+    TrailR = 1,
+    TsaN = 2,
+    TsaR = 3,
+    StsaN = 4,
+    StsaR = 5,
+    RadlN = 6,
+    RadlR = 7,
+    RaslN = 8,
+    RaslR = 9,
+    BlaWLp = 16,
+    BlaWRadl = 17,
+    BlaNLp = 18,
+    IdrWRadl = 19,
+    IdrNLp = 20,
+    CraNut = 21,
+    VideoParameterSet = 32,
+    SequenceParameterSet = 33,
+    PictureParameterSet = 34,
+    AccessUnitDelimiter = 35,
+    EndOfSequence = 36,
+    EndOfBitstream = 37,
+    FillerData = 38,
+    PrefixSupplementalEnhancementInformation = 39,
+    SuffixSupplementalEnhancementInformation = 40,
+}
+impl HevcNalUnitType {
+    /// Returns `true` if this is the type of an IRAP (intra random access point) picture,
+    /// i.e., a BLA, IDR or CRA picture (`BLA_W_LP`..=`RSV_IRAP_VCL23`).
+    pub fn is_irap(self) -> bool {
+        (16..=23).contains(&(self as u8))
+    }
+
+    fn from_u8(n: u8) -> Result<Self> {
+        Ok(track_assert_some!(
+            Self::try_from_u8(n),
+            ErrorKind::InvalidInput,
+            "Unknown HEVC NAL unit type: {}",
+            n
+        ))
+    }
+
+    /// Converts `n` into a `HevcNalUnitType`, or returns `None` if `n` is unspecified or reserved.
+    pub fn try_from_u8(n: u8) -> Option<Self> {
+        Some(match n {
+            0 => HevcNalUnitType::TrailN,
+            1 => HevcNalUnitType::TrailR,
+            2 => HevcNalUnitType::TsaN,
+            3 => HevcNalUnitType::TsaR,
+            4 => HevcNalUnitType::StsaN,
+            5 => HevcNalUnitType::StsaR,
+            6 => HevcNalUnitType::RadlN,
+            7 => HevcNalUnitType::RadlR,
+            8 => HevcNalUnitType::RaslN,
+            9 => HevcNalUnitType::RaslR,
+            16 => HevcNalUnitType::BlaWLp,
+            17 => HevcNalUnitType::BlaWRadl,
+            18 => HevcNalUnitType::BlaNLp,
+            19 => HevcNalUnitType::IdrWRadl,
+            20 => HevcNalUnitType::IdrNLp,
+            21 => HevcNalUnitType::CraNut,
+            32 => HevcNalUnitType::VideoParameterSet,
+            33 => HevcNalUnitType::SequenceParameterSet,
+            34 => HevcNalUnitType::PictureParameterSet,
+            35 => HevcNalUnitType::AccessUnitDelimiter,
+            36 => HevcNalUnitType::EndOfSequence,
+            37 => HevcNalUnitType::EndOfBitstream,
+            38 => HevcNalUnitType::FillerData,
+            39 => HevcNalUnitType::PrefixSupplementalEnhancementInformation,
+            40 => HevcNalUnitType::SuffixSupplementalEnhancementInformation,
+            _ => return None,
+        })
+    }
+}
+
+/// HEVC access unit delimiter (7.3.2.5, ISO/IEC 23008-2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HevcAccessUnitDelimiter {
+    /// The slice types that may be present in the access unit (Table 7-2, ISO/IEC 23008-2):
+    /// `0` (I), `1` (P and I) or `2` (B, P and I). The other values are reserved.
+    pub pic_type: u8,
+}
+impl HevcAccessUnitDelimiter {
+    /// Reads an access unit delimiter from the bytes following the NAL unit header.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let b = track_io!(reader.read_u8())?;
+        Ok(HevcAccessUnitDelimiter { pic_type: b >> 5 })
+    }
+}
+
+/// HEVC decoder configuration record (8.3.3.1, ISO/IEC 14496-15).
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HvcDecoderConfigurationRecord {
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+
+    /// The 48 bits of `general_constraint_indicator_flags` (stored in the low bits).
+    pub general_constraint_indicator_flags: u64,
+    pub general_level_idc: u8,
+    pub min_spatial_segmentation_idc: u16,
+    pub parallelism_type: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub avg_frame_rate: u16,
+    pub constant_frame_rate: u8,
+    pub num_temporal_layers: u8,
+    pub temporal_id_nested: bool,
+
+    /// The size of the NAL unit length field of each sample.
+    pub length_size: LengthSize,
+
+    /// VPS NAL units (including their NAL unit headers).
+    pub vps_data: Vec<Vec<u8>>,
+
+    /// SPS NAL units (including their NAL unit headers).
+    pub sps_data: Vec<Vec<u8>>,
+
+    /// PPS NAL units (including their NAL unit headers).
+    pub pps_data: Vec<Vec<u8>>,
+}
+impl HvcDecoderConfigurationRecord {
+    /// Returns the codec that this record targets.
+    pub fn codec(&self) -> Codec {
+        Codec::H265
+    }
+
+    /// Returns the codec string of RFC 6381 for an `hvc1` sample entry (e.g., `hvc1.1.6.L93.B0`).
+    ///
+    /// See `codec_string_for_mode()` for the elements following `hvc1`.
+    pub fn codec_string(&self) -> String {
+        self.codec_string_for_mode(SampleEntryMode::OutOfBand)
+    }
+
+    /// Returns the codec string of RFC 6381 for the sample entry type selected by `mode`.
+    ///
+    /// The dot-separated elements following the sample entry type (`hvc1` or `hev1`) are
+    /// as defined in E.3 of ISO/IEC 14496-15:
+    /// - `general_profile_idc` in decimal, prefixed by `A`, `B` or `C`
+    ///   if `general_profile_space` is `1`, `2` or `3`,
+    /// - `general_profile_compatibility_flags` in reverse bit order, in hexadecimal,
+    /// - `L` (Main tier) or `H` (High tier) followed by `general_level_idc` in decimal, and
+    /// - the six bytes of `general_constraint_indicator_flags` in hexadecimal,
+    ///   omitting the trailing zero bytes.
+    pub fn codec_string_for_mode(&self, mode: SampleEntryMode) -> String {
+        let fourcc = self.codec().sample_entry_fourcc(mode);
+        let mut s = format!(
+            "{}.{}{}.{:X}.{}{}",
+            String::from_utf8_lossy(&fourcc),
+            ["", "A", "B", "C"][usize::from(self.general_profile_space & 0b11)],
+            self.general_profile_idc,
+            self.general_profile_compatibility_flags.reverse_bits(),
+            if self.general_tier_flag { 'H' } else { 'L' },
+            self.general_level_idc
+        );
+        let constraint_bytes = (0..6)
+            .map(|i| (self.general_constraint_indicator_flags >> (40 - 8 * i)) as u8)
+            .collect::<Vec<_>>();
+        let len = constraint_bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        for b in &constraint_bytes[..len] {
+            s.push_str(&format!(".{:X}", b));
+        }
+        s
+    }
+
+    /// Makes a record from the given VPS, SPS and PPS NAL units (including their NAL unit headers).
+    pub fn from_parameter_sets(vps: &[u8], sps: &[u8], pps: &[u8]) -> Result<Self> {
+        for (nal_unit, nal_unit_type) in &[
+            (vps, HevcNalUnitType::VideoParameterSet),
+            (sps, HevcNalUnitType::SequenceParameterSet),
+            (pps, HevcNalUnitType::PictureParameterSet),
+        ] {
+            let header = track!(HevcNalUnit::read_from(*nal_unit))?;
+            track_assert_eq!(header.nal_unit_type, *nal_unit_type, ErrorKind::InvalidInput);
+        }
+
+        let vps_summary = track!(HevcVpsSummary::read_from(&vps[2..]))?;
+        let sps_summary = track!(HevcSpsSummary::read_from(&sps[2..]))?;
+        track_assert!(
+            sps_summary.sps_max_sub_layers_minus1 <= vps_summary.vps_max_sub_layers_minus1,
+            ErrorKind::InvalidInput,
+            "sps_max_sub_layers_minus1={}, vps_max_sub_layers_minus1={}",
+            sps_summary.sps_max_sub_layers_minus1,
+            vps_summary.vps_max_sub_layers_minus1
+        );
+        let ptl = &sps_summary.profile_tier_level;
+        let mut record = HvcDecoderConfigurationRecord {
+            general_profile_space: ptl.general_profile_space,
+            general_tier_flag: ptl.general_tier_flag,
+            general_profile_idc: ptl.general_profile_idc,
+            general_profile_compatibility_flags: ptl.general_profile_compatibility_flags,
+            general_constraint_indicator_flags: ptl.general_constraint_indicator_flags,
+            general_level_idc: ptl.general_level_idc,
+            min_spatial_segmentation_idc: 0,
+            parallelism_type: 0,
+            chroma_format_idc: sps_summary.chroma_format_idc as u8,
+            bit_depth_luma_minus8: sps_summary.bit_depth_luma_minus8 as u8,
+            bit_depth_chroma_minus8: sps_summary.bit_depth_chroma_minus8 as u8,
+            avg_frame_rate: 0,
+            constant_frame_rate: 0,
+            num_temporal_layers: sps_summary.sps_max_sub_layers_minus1 + 1,
+            temporal_id_nested: sps_summary.sps_temporal_id_nesting_flag,
+            length_size: LengthSize::FOUR,
+            vps_data: vec![vps.to_owned()],
+            sps_data: vec![sps.to_owned()],
+            pps_data: vec![pps.to_owned()],
+        };
+        record.set_profile_compatibility_flag();
+        Ok(record)
+    }
+
+    /// Sets the `general_profile_compatibility_flag` corresponding to `general_profile_idc`.
+    ///
+    /// `general_profile_compatibility_flag[j]` is the `j`-th bit from the most significant bit
+    /// of `general_profile_compatibility_flags`, so bit `31 - general_profile_idc` is set.
+    /// The other flags are left as is.
+    /// Nothing is done if `general_profile_idc` is `32` or more.
+    pub fn set_profile_compatibility_flag(&mut self) {
+        if self.general_profile_idc < 32 {
+            self.general_profile_compatibility_flags |= 1 << (31 - self.general_profile_idc);
+        }
+    }
+
+    /// Returns `general_progressive_source_flag`
+    /// (the most significant bit of `general_constraint_indicator_flags`).
+    pub fn general_progressive_source_flag(&self) -> bool {
+        self.general_constraint_indicator_flag(0)
+    }
+
+    /// Returns `general_interlaced_source_flag`.
+    pub fn general_interlaced_source_flag(&self) -> bool {
+        self.general_constraint_indicator_flag(1)
+    }
+
+    /// Returns `general_non_packed_constraint_flag`.
+    pub fn general_non_packed_constraint_flag(&self) -> bool {
+        self.general_constraint_indicator_flag(2)
+    }
+
+    /// Returns `general_frame_only_constraint_flag`.
+    ///
+    /// If this is `true`, the stream contains only frames (i.e., no fields).
+    pub fn general_frame_only_constraint_flag(&self) -> bool {
+        self.general_constraint_indicator_flag(3)
+    }
+
+    /// Returns the `i`-th bit from the most significant bit of the 48-bit
+    /// `general_constraint_indicator_flags`.
+    fn general_constraint_indicator_flag(&self, i: u32) -> bool {
+        (self.general_constraint_indicator_flags >> (47 - i)) & 1 == 1
+    }
+
+    /// Returns the name of the general profile (Annex A, ISO/IEC 23008-2).
+    ///
+    /// `None` is returned if `general_profile_idc` is unknown.
+    pub fn profile_name(&self) -> Option<&'static str> {
+        Some(match self.general_profile_idc {
+            1 => "Main",
+            2 => "Main 10",
+            3 => "Main Still Picture",
+            4 => "Format Range Extensions",
+            5 => "High Throughput",
+            6 => "Multiview Main",
+            7 => "Scalable Main",
+            8 => "3D Main",
+            9 => "Screen Content Coding Extensions",
+            10 => "Scalable Format Range Extensions",
+            11 => "High Throughput Screen Content Coding Extensions",
+            _ => return None,
+        })
+    }
+
+    /// Returns `true` if the general profile is Main 10.
+    pub fn is_main10(&self) -> bool {
+        self.general_profile_idc == 2
+    }
+
+    /// Returns the chroma subsampling denoted by `chroma_format_idc` (e.g., `"4:2:0"`).
+    pub fn chroma_subsampling(&self) -> &'static str {
+        match self.chroma_format_idc {
+            0 => "4:0:0",
+            1 => "4:2:0",
+            2 => "4:2:2",
+            _ => "4:4:4",
+        }
+    }
+
+    /// Returns the bit depth of the luma samples.
+    pub fn bit_depth_luma(&self) -> u8 {
+        self.bit_depth_luma_minus8 + 8
+    }
+
+    /// Returns the bit depth of the chroma samples.
+    pub fn bit_depth_chroma(&self) -> u8 {
+        self.bit_depth_chroma_minus8 + 8
+    }
+
+    /// Returns the larger of the luma and chroma bit depths.
+    pub fn max_bit_depth(&self) -> u8 {
+        self.bit_depth_luma().max(self.bit_depth_chroma())
+    }
+
+    /// Returns a human readable summary of the codec parameters.
+    pub fn codec_details(&self) -> CodecDetails {
+        CodecDetails {
+            profile: self.profile_name(),
+            tier: if self.general_tier_flag { "High" } else { "Main" },
+            level: format!(
+                "{}.{}",
+                self.general_level_idc / 30,
+                self.general_level_idc % 30 / 3
+            ),
+            chroma_subsampling: self.chroma_subsampling(),
+            bit_depth_luma: self.bit_depth_luma(),
+            bit_depth_chroma: self.bit_depth_chroma(),
+        }
+    }
+
+    /// Returns `true` if this record and `other` write the same `hvcC` payload.
+    ///
+    /// Matching `codec_details()` is not enough: the VPS, SPS and PPS arrays must be identical
+    /// too. A record that cannot be written is never compatible.
+    pub fn is_append_compatible(&self, other: &Self) -> bool {
+        serializes_identically(self, other, |record, bytes| record.write_to(bytes))
+    }
+
+    /// Reads a record from the given reader.
+    ///
+    /// The arrays may appear in any order, and the NAL units of the arrays of the same type
+    /// are concatenated. The arrays of the other NAL unit types (e.g., SEI) are skipped.
+    /// `lengthSizeMinusOne` must be `0`, `1` or `3` (i.e., one-, two- or four-byte lengths).
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let configuration_version = track_io!(reader.read_u8())?;
+        track_assert_eq!(configuration_version, 1, ErrorKind::Unsupported);
+        let b = track_io!(reader.read_u8())?;
+        let general_profile_space = b >> 6;
+        let general_tier_flag = (b >> 5) & 1 == 1;
+        let general_profile_idc = b & 0b1_1111;
+        let general_profile_compatibility_flags = track_io!(reader.read_u32::<BigEndian>())?;
+        let general_constraint_indicator_flags =
+            (u64::from(track_io!(reader.read_u16::<BigEndian>())?) << 32)
+                | u64::from(track_io!(reader.read_u32::<BigEndian>())?);
+        let general_level_idc = track_io!(reader.read_u8())?;
+        let min_spatial_segmentation_idc = track_io!(reader.read_u16::<BigEndian>())? & 0xFFF;
+        let parallelism_type = track_io!(reader.read_u8())? & 0b11;
+        let chroma_format_idc = track_io!(reader.read_u8())? & 0b11;
+        let bit_depth_luma_minus8 = track_io!(reader.read_u8())? & 0b111;
+        let bit_depth_chroma_minus8 = track_io!(reader.read_u8())? & 0b111;
+        let avg_frame_rate = track_io!(reader.read_u16::<BigEndian>())?;
+        let b = track_io!(reader.read_u8())?;
+        let length_size = track!(LengthSize::from_length_size_minus_one(b & 0b11))?;
+
+        let mut vps_data = Vec::new();
+        let mut sps_data = Vec::new();
+        let mut pps_data = Vec::new();
+        let num_of_arrays = track_io!(reader.read_u8())?;
+        for _ in 0..num_of_arrays {
+            let nal_unit_type = track_io!(reader.read_u8())? & 0b11_1111;
+            let num_nalus = track_io!(reader.read_u16::<BigEndian>())?;
+            for _ in 0..num_nalus {
+                let len = track_io!(reader.read_u16::<BigEndian>())?;
+                let mut nal_unit = vec![0; usize::from(len)];
+                track_io!(reader.read_exact(&mut nal_unit))?;
+
+                match nal_unit_type {
+                    32 => vps_data.push(nal_unit), // VPS_NUT
+                    33 => sps_data.push(nal_unit), // SPS_NUT
+                    34 => pps_data.push(nal_unit), // PPS_NUT
+                    _ => {}
+                }
+            }
+        }
+        Ok(HvcDecoderConfigurationRecord {
+            general_profile_space,
+            general_tier_flag,
+            general_profile_idc,
+            general_profile_compatibility_flags,
+            general_constraint_indicator_flags,
+            general_level_idc,
+            min_spatial_segmentation_idc,
+            parallelism_type,
+            chroma_format_idc,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            avg_frame_rate,
+            constant_frame_rate: b >> 6,
+            num_temporal_layers: (b >> 3) & 0b111,
+            temporal_id_nested: (b >> 2) & 1 == 1,
+            length_size,
+            vps_data,
+            sps_data,
+            pps_data,
+        })
+    }
+
+    /// Writes the record to the given writer.
+    ///
+    /// `num_temporal_layers` must not exceed the number of sub-layers signaled by the VPS
+    /// (`0` means that the number is unknown).
+    /// Note that the record has no room for the sub-layer profile and level information;
+    /// it is available only from the parameter sets themselves.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        track_assert!(self.general_profile_space <= 3, ErrorKind::InvalidInput);
+        track_assert!(self.general_profile_idc <= 31, ErrorKind::InvalidInput);
+        track_assert!(
+            self.general_constraint_indicator_flags >> 48 == 0,
+            ErrorKind::InvalidInput
+        );
+        track_assert!(self.min_spatial_segmentation_idc <= 0xFFF, ErrorKind::InvalidInput);
+        track_assert!(self.num_temporal_layers <= 7, ErrorKind::InvalidInput);
+        match self.vps_data.first() {
+            Some(vps) if self.num_temporal_layers > 0 && vps.len() > 2 => {
+                let vps_summary = track!(HevcVpsSummary::read_from(&vps[2..]))?;
+                track_assert!(
+                    self.num_temporal_layers <= vps_summary.vps_max_sub_layers_minus1 + 1,
+                    ErrorKind::InvalidInput,
+                    "num_temporal_layers={}, vps_max_sub_layers_minus1={}",
+                    self.num_temporal_layers,
+                    vps_summary.vps_max_sub_layers_minus1
+                );
+            }
+            _ => {}
+        }
+
+        write_u8!(writer, 1); // configuration_version
+        write_u8!(
+            writer,
+            (self.general_profile_space << 6)
+                | ((self.general_tier_flag as u8) << 5)
+                | self.general_profile_idc
+        );
+        write_u32!(writer, self.general_profile_compatibility_flags);
+        write_u16!(writer, (self.general_constraint_indicator_flags >> 32) as u16);
+        write_u32!(writer, self.general_constraint_indicator_flags as u32);
+        write_u8!(writer, self.general_level_idc);
+        write_u16!(writer, 0xF000 | self.min_spatial_segmentation_idc);
+        write_u8!(writer, 0b1111_1100 | (self.parallelism_type & 0b11));
+        write_u8!(writer, 0b1111_1100 | (self.chroma_format_idc & 0b11));
+        write_u8!(writer, 0b1111_1000 | (self.bit_depth_luma_minus8 & 0b111));
+        write_u8!(writer, 0b1111_1000 | (self.bit_depth_chroma_minus8 & 0b111));
+        write_u16!(writer, self.avg_frame_rate);
+        write_u8!(
+            writer,
+            ((self.constant_frame_rate & 0b11) << 6)
+                | (self.num_temporal_layers << 3)
+                | ((self.temporal_id_nested as u8) << 2)
+                | self.length_size.length_size_minus_one()
+        );
+
+        // The arrays without NAL units (e.g., no VPS) are omitted
+        let arrays = [
+            (HevcNalUnitType::VideoParameterSet, &self.vps_data),
+            (HevcNalUnitType::SequenceParameterSet, &self.sps_data),
+            (HevcNalUnitType::PictureParameterSet, &self.pps_data),
+        ];
+        let arrays = arrays
+            .iter()
+            .copied()
+            .filter(|(_, nal_units)| !nal_units.is_empty())
+            .collect::<Vec<_>>();
+        write_u8!(writer, arrays.len() as u8); // num_of_arrays
+        for (nal_unit_type, nal_units) in arrays {
+            track_assert!(nal_units.len() <= 0xFFFF, ErrorKind::InvalidInput);
+            // array_completeness (1), reserved (0) and NAL_unit_type
+            write_u8!(writer, 0b1000_0000 | nal_unit_type as u8);
+            write_u16!(writer, nal_units.len() as u16); // num_nalus
+            for nal_unit in nal_units {
+                track_assert!(nal_unit.len() <= 0xFFFF, ErrorKind::InvalidInput);
+                write_u16!(writer, nal_unit.len() as u16);
+                write_all!(writer, nal_unit);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Human readable summary of the codec parameters of an `HvcDecoderConfigurationRecord`.
+///
+/// Unlike the codec string of RFC 6381, this includes the chroma format and the bit depths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecDetails {
+    /// The name of the general profile (`None` if it is unknown).
+    pub profile: Option<&'static str>,
+
+    /// `"Main"` or `"High"`.
+    pub tier: &'static str,
+
+    /// The level number (e.g., `"3.1"`).
+    pub level: String,
+
+    /// The chroma subsampling (e.g., `"4:2:0"`).
+    pub chroma_subsampling: &'static str,
+
+    /// The bit depth of the luma samples.
+    pub bit_depth_luma: u8,
+
+    /// The bit depth of the chroma samples.
+    pub bit_depth_chroma: u8,
+}
+
+/// HEVC video parameter set (7.3.2.1, ISO/IEC 23008-2).
+///
+/// Only the leading fields of the VPS are parsed.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct HevcVpsSummary {
+    pub vps_video_parameter_set_id: u8,
+    pub vps_max_layers_minus1: u8,
+    pub vps_max_sub_layers_minus1: u8,
+    pub vps_temporal_id_nesting_flag: bool,
+    pub profile_tier_level: ProfileTierLevel,
+}
+impl HevcVpsSummary {
+    /// Reads a VPS from the bytes following the NAL unit header.
+    ///
+    /// Emulation prevention bytes are removed while reading.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let mut reader = AvcBitReader::new(RbspReader::new(reader));
+        let vps_video_parameter_set_id = track!(reader.read_bits(4))? as u8;
+        let _vps_base_layer_internal_flag = track!(reader.read_bit())?;
+        let _vps_base_layer_available_flag = track!(reader.read_bit())?;
+        let vps_max_layers_minus1 = track!(reader.read_bits(6))? as u8;
+        let vps_max_sub_layers_minus1 = track!(reader.read_bits(3))? as u8;
+        track_assert!(vps_max_sub_layers_minus1 <= 6, ErrorKind::InvalidInput);
+        let vps_temporal_id_nesting_flag = track!(reader.read_bit())? == 1;
+        let vps_reserved_0xffff_16bits = track!(reader.read_bits(16))?;
+        track_assert_eq!(vps_reserved_0xffff_16bits, 0xFFFF, ErrorKind::InvalidInput);
+        let profile_tier_level = track!(ProfileTierLevel::read_from_bit_reader(
+            &mut reader,
+            vps_max_sub_layers_minus1
+        ))?;
+        Ok(HevcVpsSummary {
+            vps_video_parameter_set_id,
+            vps_max_layers_minus1,
+            vps_max_sub_layers_minus1,
+            vps_temporal_id_nesting_flag,
+            profile_tier_level,
+        })
+    }
+}
+
+/// HEVC sequence parameter set (7.3.2.2, ISO/IEC 23008-2).
+///
+/// Only the fields up to the short-term reference picture sets are parsed.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct HevcSpsSummary {
+    pub sps_video_parameter_set_id: u8,
+    pub sps_max_sub_layers_minus1: u8,
+    pub sps_temporal_id_nesting_flag: bool,
+    pub profile_tier_level: ProfileTierLevel,
+    pub sps_seq_parameter_set_id: u64,
+    pub chroma_format_idc: u64,
+    pub separate_colour_plane_flag: bool,
+    pub pic_width_in_luma_samples: u64,
+    pub pic_height_in_luma_samples: u64,
+
+    /// `conf_win_left_offset`, `conf_win_right_offset`, `conf_win_top_offset` and
+    /// `conf_win_bottom_offset` (all zero if `conformance_window_flag` is not set).
+    pub conformance_window: [u64; 4],
+    pub bit_depth_luma_minus8: u64,
+    pub bit_depth_chroma_minus8: u64,
+    pub log2_max_pic_order_cnt_lsb_minus4: u64,
+
+    /// The DPB parameters of each sub-layer (`sps_max_sub_layers_minus1 + 1` entries).
+    ///
+    /// If `sps_sub_layer_ordering_info_present_flag` is not set,
+    /// all the entries have the values signaled for the highest sub-layer.
+    pub sub_layer_ordering_info: Vec<SubLayerOrderingInfo>,
+    pub scaling_list_enabled_flag: bool,
+
+    /// The explicitly signaled scaling lists
+    /// (`None` if `sps_scaling_list_data_present_flag` is not set).
+    pub scaling_list_data: Option<ScalingListData>,
+
+    /// Whether asymmetric motion partitions may be used.
+    pub amp_enabled_flag: bool,
+
+    /// Whether the sample adaptive offset (SAO) filter may be applied.
+    pub sample_adaptive_offset_enabled_flag: bool,
+
+    /// The `st_ref_pic_set()`s (the number of them is `num_short_term_ref_pic_sets`).
+    pub short_term_ref_pic_sets: Vec<ShortTermRefPicSet>,
+
+    /// Whether temporal motion vector predictors may be used.
+    pub sps_temporal_mvp_enabled_flag: bool,
+
+    /// Whether the bi-linear interpolation may be used for the intra smoothing.
+    pub strong_intra_smoothing_enabled_flag: bool,
+
+    /// The VUI parameters (`None` if `vui_parameters_present_flag` is not set).
+    pub vui_parameters: Option<HevcVuiParameters>,
+}
+impl HevcSpsSummary {
+    /// Reads an SPS from the bytes following the NAL unit header.
+    ///
+    /// Emulation prevention bytes are removed while reading.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let mut reader = AvcBitReader::new(RbspReader::new(reader));
+        let sps_video_parameter_set_id = track!(reader.read_bits(4))? as u8;
+        let sps_max_sub_layers_minus1 = track!(reader.read_bits(3))? as u8;
+        track_assert!(sps_max_sub_layers_minus1 <= 6, ErrorKind::InvalidInput);
+        let sps_temporal_id_nesting_flag = track!(reader.read_bit())? == 1;
+        let profile_tier_level = track!(ProfileTierLevel::read_from_bit_reader(
+            &mut reader,
+            sps_max_sub_layers_minus1
+        ))?;
+        let sps_seq_parameter_set_id = track!(reader.read_ue())?;
+        let chroma_format_idc = track!(reader.read_ue())?;
+        track_assert!(chroma_format_idc <= 3, ErrorKind::InvalidInput);
+        let separate_colour_plane_flag = if chroma_format_idc == 3 {
+            track!(reader.read_bit())? == 1
+        } else {
+            false
+        };
+        let pic_width_in_luma_samples = track!(reader.read_ue())?;
+        let pic_height_in_luma_samples = track!(reader.read_ue())?;
+        let conformance_window_flag = track!(reader.read_bit())?;
+        let mut conformance_window = [0; 4];
+        if conformance_window_flag == 1 {
+            for offset in &mut conformance_window {
+                *offset = track!(reader.read_ue())?;
+            }
+        }
+        let bit_depth_luma_minus8 = track!(reader.read_ue())?;
+        let bit_depth_chroma_minus8 = track!(reader.read_ue())?;
+        track_assert!(bit_depth_luma_minus8 <= 8, ErrorKind::InvalidInput);
+        track_assert!(bit_depth_chroma_minus8 <= 8, ErrorKind::InvalidInput);
+        let log2_max_pic_order_cnt_lsb_minus4 = track!(reader.read_ue())?;
+        track_assert!(log2_max_pic_order_cnt_lsb_minus4 <= 12, ErrorKind::InvalidInput);
+
+        let sps_sub_layer_ordering_info_present_flag = track!(reader.read_bit())? == 1;
+        let first_sub_layer = if sps_sub_layer_ordering_info_present_flag {
+            0
+        } else {
+            sps_max_sub_layers_minus1
+        };
+        let mut sub_layer_ordering_info =
+            Vec::with_capacity(usize::from(sps_max_sub_layers_minus1) + 1);
+        for _ in first_sub_layer..=sps_max_sub_layers_minus1 {
+            sub_layer_ordering_info.push(SubLayerOrderingInfo {
+                sps_max_dec_pic_buffering_minus1: track!(reader.read_ue())?,
+                sps_max_num_reorder_pics: track!(reader.read_ue())?,
+                sps_max_latency_increase_plus1: track!(reader.read_ue())?,
+            });
+        }
+        if !sps_sub_layer_ordering_info_present_flag {
+            // The values of the lower sub-layers are inferred to be those of the highest one.
+            let highest = sub_layer_ordering_info[0].clone();
+            sub_layer_ordering_info.resize(usize::from(sps_max_sub_layers_minus1) + 1, highest);
+        }
+
+        let _log2_min_luma_coding_block_size_minus3 = track!(reader.read_ue())?;
+        let _log2_diff_max_min_luma_coding_block_size = track!(reader.read_ue())?;
+        let _log2_min_luma_transform_block_size_minus2 = track!(reader.read_ue())?;
+        let _log2_diff_max_min_luma_transform_block_size = track!(reader.read_ue())?;
+        let _max_transform_hierarchy_depth_inter = track!(reader.read_ue())?;
+        let _max_transform_hierarchy_depth_intra = track!(reader.read_ue())?;
+        let scaling_list_enabled_flag = track!(reader.read_bit())? == 1;
+        let mut scaling_list_data = None;
+        if scaling_list_enabled_flag {
+            let sps_scaling_list_data_present_flag = track!(reader.read_bit())? == 1;
+            if sps_scaling_list_data_present_flag {
+                scaling_list_data = Some(track!(ScalingListData::read_from_bit_reader(
+                    &mut reader
+                ))?);
+            }
+        }
+        let amp_enabled_flag = track!(reader.read_bit())? == 1;
+        let sample_adaptive_offset_enabled_flag = track!(reader.read_bit())? == 1;
+        let pcm_enabled_flag = track!(reader.read_bit())? == 1;
+        if pcm_enabled_flag {
+            let _pcm_sample_bit_depth_luma_minus1 = track!(reader.read_bits(4))?;
+            let _pcm_sample_bit_depth_chroma_minus1 = track!(reader.read_bits(4))?;
+            let _log2_min_pcm_luma_coding_block_size_minus3 = track!(reader.read_ue())?;
+            let _log2_diff_max_min_pcm_luma_coding_block_size = track!(reader.read_ue())?;
+            let _pcm_loop_filter_disabled_flag = track!(reader.read_bit())?;
+        }
+
+        let num_short_term_ref_pic_sets = track!(reader.read_ue())?;
+        track_assert!(num_short_term_ref_pic_sets <= 64, ErrorKind::InvalidInput);
+        let mut short_term_ref_pic_sets = Vec::with_capacity(num_short_term_ref_pic_sets as usize);
+        for _ in 0..num_short_term_ref_pic_sets {
+            let rps = track!(ShortTermRefPicSet::read_from_bit_reader(
+                &mut reader,
+                &short_term_ref_pic_sets
+            ))?;
+            short_term_ref_pic_sets.push(rps);
+        }
+
+        let long_term_ref_pics_present_flag = track!(reader.read_bit())? == 1;
+        if long_term_ref_pics_present_flag {
+            let num_long_term_ref_pics_sps = track!(reader.read_ue())?;
+            track_assert!(num_long_term_ref_pics_sps <= 32, ErrorKind::InvalidInput);
+            let lsb_bits = log2_max_pic_order_cnt_lsb_minus4 as usize + 4;
+            for _ in 0..num_long_term_ref_pics_sps {
+                let _lt_ref_pic_poc_lsb_sps = track!(reader.read_bits(lsb_bits))?;
+                let _used_by_curr_pic_lt_sps_flag = track!(reader.read_bit())?;
+            }
+        }
+        let sps_temporal_mvp_enabled_flag = track!(reader.read_bit())? == 1;
+        let strong_intra_smoothing_enabled_flag = track!(reader.read_bit())? == 1;
+        let vui_parameters_present_flag = track!(reader.read_bit())? == 1;
+        let vui_parameters = if vui_parameters_present_flag {
+            Some(track!(HevcVuiParameters::read_from_bit_reader(&mut reader))?)
+        } else {
+            None
+        };
+        Ok(HevcSpsSummary {
+            sps_video_parameter_set_id,
+            sps_max_sub_layers_minus1,
+            sps_temporal_id_nesting_flag,
+            profile_tier_level,
+            sps_seq_parameter_set_id,
+            chroma_format_idc,
+            separate_colour_plane_flag,
+            pic_width_in_luma_samples,
+            pic_height_in_luma_samples,
+            conformance_window,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            log2_max_pic_order_cnt_lsb_minus4,
+            sub_layer_ordering_info,
+            scaling_list_enabled_flag,
+            scaling_list_data,
+            amp_enabled_flag,
+            sample_adaptive_offset_enabled_flag,
+            short_term_ref_pic_sets,
+            sps_temporal_mvp_enabled_flag,
+            strong_intra_smoothing_enabled_flag,
+            vui_parameters,
+        })
+    }
+
+    /// Returns the width of the cropped pictures.
+    ///
+    /// This is `0` if the conformance window is wider than the decoded pictures.
+    pub fn width(&self) -> usize {
+        let [left, right, _, _] = self.conformance_window;
+        let crop = self.sub_width_c().saturating_mul(left.saturating_add(right));
+        self.pic_width_in_luma_samples.saturating_sub(crop) as usize
+    }
+
+    /// Returns the height of the cropped pictures.
+    ///
+    /// This is `0` if the conformance window is taller than the decoded pictures.
+    pub fn height(&self) -> usize {
+        let [_, _, top, bottom] = self.conformance_window;
+        let crop = self.sub_height_c().saturating_mul(top.saturating_add(bottom));
+        self.pic_height_in_luma_samples.saturating_sub(crop) as usize
+    }
+
+    /// Returns the maximum required size of the DPB in pictures
+    /// (`sps_max_dec_pic_buffering_minus1 + 1`) of the given sub-layer.
+    ///
+    /// `None` is returned if `sub_layer` exceeds `sps_max_sub_layers_minus1`.
+    pub fn max_dec_pic_buffering(&self, sub_layer: usize) -> Option<usize> {
+        self.sub_layer_ordering_info
+            .get(sub_layer)
+            .map(|x| x.sps_max_dec_pic_buffering_minus1 as usize + 1)
+    }
+
+    /// Returns the maximum number of pictures that can precede any picture in decoding order
+    /// and follow it in output order (`sps_max_num_reorder_pics`) of the given sub-layer.
+    ///
+    /// `None` is returned if `sub_layer` exceeds `sps_max_sub_layers_minus1`.
+    pub fn max_num_reorder_pics(&self, sub_layer: usize) -> Option<usize> {
+        self.sub_layer_ordering_info
+            .get(sub_layer)
+            .map(|x| x.sps_max_num_reorder_pics as usize)
+    }
+
+    fn sub_width_c(&self) -> u64 {
+        match self.chroma_format_idc {
+            1 | 2 if !self.separate_colour_plane_flag => 2,
+            _ => 1,
+        }
+    }
+
+    fn sub_height_c(&self) -> u64 {
+        match self.chroma_format_idc {
+            1 if !self.separate_colour_plane_flag => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// The DPB parameters of a sub-layer signaled in an HEVC SPS.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubLayerOrderingInfo {
+    pub sps_max_dec_pic_buffering_minus1: u64,
+    pub sps_max_num_reorder_pics: u64,
+    pub sps_max_latency_increase_plus1: u64,
+}
+
+/// Video usability information (E.2.1, ISO/IEC 23008-2).
+///
+/// Only the fields up to `chroma_loc_info` are parsed.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct HevcVuiParameters {
+    pub aspect_ratio_info: Option<AspectRatioInfo>,
+    pub overscan_appropriate_flag: Option<bool>,
+    pub video_signal_type: Option<VideoSignalType>,
+    pub chroma_loc_info: Option<ChromaLocInfo>,
+}
+impl HevcVuiParameters {
+    fn read_from_bit_reader<R: Read>(reader: &mut AvcBitReader<R>) -> Result<Self> {
+        let aspect_ratio_info = track!(AspectRatioInfo::read_if_present(reader))?;
+        let overscan_info_present_flag = track!(reader.read_bit())?;
+        let overscan_appropriate_flag = if overscan_info_present_flag == 1 {
+            Some(track!(reader.read_bit())? == 1)
+        } else {
+            None
+        };
+        let video_signal_type = track!(VideoSignalType::read_if_present(reader))?;
+        let chroma_loc_info = track!(ChromaLocInfo::read_if_present(reader))?;
+        Ok(HevcVuiParameters {
+            aspect_ratio_info,
+            overscan_appropriate_flag,
+            video_signal_type,
+            chroma_loc_info,
+        })
+    }
+}
+
+/// Scaling list data (7.3.4, ISO/IEC 23008-2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScalingListData {
+    /// The scaling lists indexed by `sizeId` and then by `matrixId`.
+    ///
+    /// For `sizeId == 3`, only the lists of `matrixId` `0` and `3` are coded,
+    /// so the innermost vector has two elements.
+    pub scaling_lists: Vec<Vec<ScalingList>>,
+}
+impl ScalingListData {
+    fn read_from_bit_reader<R: Read>(reader: &mut AvcBitReader<R>) -> Result<Self> {
+        let mut scaling_lists = Vec::with_capacity(4);
+        for size_id in 0..4 {
+            let matrix_id_step = if size_id == 3 { 3 } else { 1 };
+            let mut lists = Vec::with_capacity(6 / matrix_id_step);
+            for matrix_id in (0..6).step_by(matrix_id_step) {
+                let scaling_list_pred_mode_flag = track!(reader.read_bit())? == 1;
+                if !scaling_list_pred_mode_flag {
+                    let scaling_list_pred_matrix_id_delta = track!(reader.read_ue())?;
+                    track_assert!(
+                        scaling_list_pred_matrix_id_delta <= (matrix_id / matrix_id_step) as u64,
+                        ErrorKind::InvalidInput,
+                        "scaling_list_pred_matrix_id_delta={}, sizeId={}, matrixId={}",
+                        scaling_list_pred_matrix_id_delta,
+                        size_id,
+                        matrix_id
+                    );
+                    lists.push(ScalingList::Predicted {
+                        scaling_list_pred_matrix_id_delta,
+                    });
+                } else {
+                    let coef_num = cmp::min(64, 1 << (4 + (size_id << 1)));
+                    let mut next_coef = 8;
+                    let mut dc_coef = None;
+                    if size_id > 1 {
+                        let scaling_list_dc_coef_minus8 = track!(reader.read_se())?;
+                        track_assert!(
+                            (-7..=247).contains(&scaling_list_dc_coef_minus8),
+                            ErrorKind::InvalidInput,
+                            "scaling_list_dc_coef_minus8={}",
+                            scaling_list_dc_coef_minus8
+                        );
+                        next_coef = scaling_list_dc_coef_minus8 + 8;
+                        dc_coef = Some(next_coef as u8);
+                    }
+                    let mut coefficients = Vec::with_capacity(coef_num);
+                    for _ in 0..coef_num {
+                        let scaling_list_delta_coef = track!(reader.read_se())?;
+                        track_assert!(
+                            (-128..=127).contains(&scaling_list_delta_coef),
+                            ErrorKind::InvalidInput,
+                            "scaling_list_delta_coef={}",
+                            scaling_list_delta_coef
+                        );
+                        next_coef = (next_coef + scaling_list_delta_coef + 256) % 256;
+                        coefficients.push(next_coef as u8);
+                    }
+                    lists.push(ScalingList::Explicit {
+                        dc_coef,
+                        coefficients,
+                    });
+                }
+            }
+            scaling_lists.push(lists);
+        }
+        Ok(ScalingListData { scaling_lists })
+    }
+}
+
+/// A scaling list in `ScalingListData`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScalingList {
+    /// The list is inferred from a reference list (`scaling_list_pred_mode_flag` is `0`).
+    ///
+    /// `scaling_list_pred_matrix_id_delta == 0` denotes the default list.
+    Predicted {
+        /// `scaling_list_pred_matrix_id_delta`.
+        scaling_list_pred_matrix_id_delta: u64,
+    },
+
+    /// The list is explicitly signaled (`scaling_list_pred_mode_flag` is `1`).
+    Explicit {
+        /// `scaling_list_dc_coef_minus8 + 8` (only present for the 16x16 and 32x32 sizes).
+        dc_coef: Option<u8>,
+
+        /// The `ScalingList` values in the up-right diagonal scan order.
+        coefficients: Vec<u8>,
+    },
+}
+
+/// Short-term reference picture set (7.3.7, ISO/IEC 23008-2).
+///
+/// The sets predicted from another set (`inter_ref_pic_set_prediction_flag`) are stored
+/// in the derived form (7.4.8, ISO/IEC 23008-2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShortTermRefPicSet {
+    /// `DeltaPocS0` (negative POC deltas in decreasing order).
+    pub delta_poc_s0: Vec<i64>,
+
+    /// `UsedByCurrPicS0`.
+    pub used_by_curr_pic_s0: Vec<bool>,
+
+    /// `DeltaPocS1` (positive POC deltas in increasing order).
+    pub delta_poc_s1: Vec<i64>,
+
+    /// `UsedByCurrPicS1`.
+    pub used_by_curr_pic_s1: Vec<bool>,
+}
+impl ShortTermRefPicSet {
+    /// Returns `NumDeltaPocs`.
+    pub fn num_delta_pocs(&self) -> usize {
+        self.delta_poc_s0.len() + self.delta_poc_s1.len()
+    }
+
+    /// Reads the `st_ref_pic_set(stRpsIdx)` of an SPS, where `stRpsIdx` is `sets.len()`.
+    fn read_from_bit_reader<R: Read>(
+        reader: &mut AvcBitReader<R>,
+        sets: &[ShortTermRefPicSet],
+    ) -> Result<Self> {
+        let inter_ref_pic_set_prediction_flag = if sets.is_empty() {
+            false
+        } else {
+            track!(reader.read_bit())? == 1
+        };
+        if inter_ref_pic_set_prediction_flag {
+            // `delta_idx_minus1` is only present in slice headers, so the reference is
+            // always the preceding set.
+            let r = &sets[sets.len() - 1];
+            let delta_rps_sign = track!(reader.read_bit())?;
+            let abs_delta_rps_minus1 = track!(reader.read_ue())?;
+            track_assert!(abs_delta_rps_minus1 < 1 << 15, ErrorKind::InvalidInput);
+            let delta_rps = (1 - 2 * i64::from(delta_rps_sign)) * (abs_delta_rps_minus1 as i64 + 1);
+
+            // `used_by_curr_pic_flag` and `use_delta_flag` for each of `DeltaPocS0`,
+            // `DeltaPocS1` and `deltaRps` (in this order).
+            let mut flags = Vec::with_capacity(r.num_delta_pocs() + 1);
+            for _ in 0..=r.num_delta_pocs() {
+                let used_by_curr_pic_flag = track!(reader.read_bit())? == 1;
+                let use_delta_flag = used_by_curr_pic_flag || track!(reader.read_bit())? == 1;
+                flags.push((used_by_curr_pic_flag, use_delta_flag));
+            }
+            let (s0_flags, rest) = flags.split_at(r.delta_poc_s0.len());
+            let (s1_flags, rps_flags) = rest.split_at(r.delta_poc_s1.len());
+            let candidates = r
+                .delta_poc_s0
+                .iter()
+                .zip(s0_flags)
+                .chain(r.delta_poc_s1.iter().zip(s1_flags))
+                .map(|(&d, &flags)| (d + delta_rps, flags))
+                .chain(Some((delta_rps, rps_flags[0])));
+
+            let mut negative = Vec::new();
+            let mut positive = Vec::new();
+            for (d_poc, (used, use_delta)) in candidates {
+                if !use_delta {
+                    continue;
+                }
+                if d_poc < 0 {
+                    negative.push((d_poc, used));
+                } else if d_poc > 0 {
+                    positive.push((d_poc, used));
+                }
+            }
+            negative.sort_by_key(|&(d_poc, _)| -d_poc);
+            positive.sort_by_key(|&(d_poc, _)| d_poc);
+            Ok(ShortTermRefPicSet {
+                delta_poc_s0: negative.iter().map(|x| x.0).collect(),
+                used_by_curr_pic_s0: negative.iter().map(|x| x.1).collect(),
+                delta_poc_s1: positive.iter().map(|x| x.0).collect(),
+                used_by_curr_pic_s1: positive.iter().map(|x| x.1).collect(),
+            })
+        } else {
+            let num_negative_pics = track!(reader.read_ue())?;
+            let num_positive_pics = track!(reader.read_ue())?;
+            track_assert!(num_negative_pics <= 16, ErrorKind::InvalidInput);
+            track_assert!(num_positive_pics <= 16, ErrorKind::InvalidInput);
+
+            let mut rps = ShortTermRefPicSet {
+                delta_poc_s0: Vec::with_capacity(num_negative_pics as usize),
+                used_by_curr_pic_s0: Vec::with_capacity(num_negative_pics as usize),
+                delta_poc_s1: Vec::with_capacity(num_positive_pics as usize),
+                used_by_curr_pic_s1: Vec::with_capacity(num_positive_pics as usize),
+            };
+            let mut poc = 0;
+            for _ in 0..num_negative_pics {
+                let delta_poc_s0_minus1 = track!(reader.read_ue())?;
+                track_assert!(delta_poc_s0_minus1 < 1 << 15, ErrorKind::InvalidInput);
+                poc -= delta_poc_s0_minus1 as i64 + 1;
+                rps.delta_poc_s0.push(poc);
+                rps.used_by_curr_pic_s0.push(track!(reader.read_bit())? == 1);
+            }
+            poc = 0;
+            for _ in 0..num_positive_pics {
+                let delta_poc_s1_minus1 = track!(reader.read_ue())?;
+                track_assert!(delta_poc_s1_minus1 < 1 << 15, ErrorKind::InvalidInput);
+                poc += delta_poc_s1_minus1 as i64 + 1;
+                rps.delta_poc_s1.push(poc);
+                rps.used_by_curr_pic_s1.push(track!(reader.read_bit())? == 1);
+            }
+            Ok(rps)
+        }
+    }
+}
+
+/// General profile, tier and level (7.3.3, ISO/IEC 23008-2).
+///
+/// The sub-layer information is skipped.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct ProfileTierLevel {
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+
+    /// The 48 bits of `general_constraint_indicator_flags` (stored in the low bits).
+    pub general_constraint_indicator_flags: u64,
+    pub general_level_idc: u8,
+}
+impl ProfileTierLevel {
+    fn read_from_bit_reader<R: Read>(
+        reader: &mut AvcBitReader<R>,
+        max_sub_layers_minus1: u8,
+    ) -> Result<Self> {
+        let general_profile_space = track!(reader.read_bits(2))? as u8;
+        let general_tier_flag = track!(reader.read_bit())? == 1;
+        let general_profile_idc = track!(reader.read_bits(5))? as u8;
+        let general_profile_compatibility_flags = track!(reader.read_bits(32))? as u32;
+        let general_constraint_indicator_flags = track!(reader.read_bits(48))?;
+        let general_level_idc = track!(reader.read_bits(8))? as u8;
+
+        let mut sub_layer_flags = Vec::with_capacity(max_sub_layers_minus1 as usize);
+        for _ in 0..max_sub_layers_minus1 {
+            let sub_layer_profile_present_flag = track!(reader.read_bit())? == 1;
+            let sub_layer_level_present_flag = track!(reader.read_bit())? == 1;
+            sub_layer_flags.push((sub_layer_profile_present_flag, sub_layer_level_present_flag));
+        }
+        if max_sub_layers_minus1 > 0 {
+            for _ in max_sub_layers_minus1..8 {
+                let _reserved_zero_2bits = track!(reader.read_bits(2))?;
+            }
+        }
+        for (profile_present, level_present) in sub_layer_flags {
+            if profile_present {
+                let _sub_layer_profile = track!(reader.read_bits(56))?;
+                let _sub_layer_constraint_flags = track!(reader.read_bits(32))?;
+            }
+            if level_present {
+                let _sub_layer_level_idc = track!(reader.read_bits(8))?;
+            }
+        }
+
+        Ok(ProfileTierLevel {
+            general_profile_space,
+            general_tier_flag,
+            general_profile_idc,
+            general_profile_compatibility_flags,
+            general_constraint_indicator_flags,
+            general_level_idc,
+        })
+    }
+}
+
+/// Returns the first VPS, SPS and PPS NAL units (without start codes)
+/// in the given Annex B HEVC stream.
+pub fn split_parameter_sets(bytes: &[u8]) -> Result<(&[u8], &[u8], &[u8])> {
+    let mut vps = None;
+    let mut sps = None;
+    let mut pps = None;
+    for nal_unit in track!(ByteStreamFormatNalUnits::new(bytes))? {
+        let nal_unit_type = match HevcNalUnit::read_from(nal_unit) {
+            Ok(header) => header.nal_unit_type,
+            Err(_) => continue,
+        };
+        match nal_unit_type {
+            HevcNalUnitType::VideoParameterSet if vps.is_none() => vps = Some(nal_unit),
+            HevcNalUnitType::SequenceParameterSet if sps.is_none() => sps = Some(nal_unit),
+            HevcNalUnitType::PictureParameterSet if pps.is_none() => pps = Some(nal_unit),
+            _ => {}
+        }
+        if vps.is_some() && sps.is_some() && pps.is_some() {
+            break;
+        }
+    }
+    let vps = track_assert_some!(vps, ErrorKind::InvalidInput, "Missing VPS");
+    let sps = track_assert_some!(sps, ErrorKind::InvalidInput, "Missing SPS");
+    let pps = track_assert_some!(pps, ErrorKind::InvalidInput, "Missing PPS");
+    Ok((vps, sps, pps))
+}
+
+/// Returns the byte range of `profile_tier_level()` in the given SPS NAL unit
+/// (including its NAL unit header).
+///
+/// The range is in the escaped (on-wire) byte space, i.e., it may contain emulation
+/// prevention bytes. The structure is byte aligned in the SPS, and its first 12 bytes
+/// (once the emulation prevention bytes are removed) are the general profile, tier and level
+/// fields, which can be copied verbatim into the `hvcC` record.
+pub fn sps_profile_tier_level_range(nal_unit: &[u8]) -> Result<Range<usize>> {
+    let header = track!(HevcNalUnit::read_from(nal_unit))?;
+    track_assert_eq!(
+        header.nal_unit_type,
+        HevcNalUnitType::SequenceParameterSet,
+        ErrorKind::InvalidInput
+    );
+
+    // The offsets in `nal_unit` of the RBSP bytes following the NAL unit header.
+    let mut offsets = Vec::with_capacity(nal_unit.len());
+    let mut zeros = 0;
+    for (i, &b) in nal_unit.iter().enumerate().skip(2) {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        offsets.push(i);
+    }
+    let rbsp_byte = |i: usize| -> Result<u8> {
+        let offset = track_assert_some!(
+            offsets.get(i),
+            ErrorKind::InvalidInput,
+            "Truncated profile_tier_level"
+        );
+        Ok(nal_unit[*offset])
+    };
+
+    // `sps_video_parameter_set_id`, `sps_max_sub_layers_minus1` and
+    // `sps_temporal_id_nesting_flag` precede `profile_tier_level()`.
+    let max_sub_layers_minus1 = (track!(rbsp_byte(0))? >> 1) & 0b111;
+    track_assert!(max_sub_layers_minus1 <= 6, ErrorKind::InvalidInput);
+    let mut size = 12;
+    if max_sub_layers_minus1 > 0 {
+        let flags = (u16::from(track!(rbsp_byte(1 + size))?) << 8)
+            | u16::from(track!(rbsp_byte(2 + size))?);
+        size += 2;
+        for i in 0..max_sub_layers_minus1 {
+            let sub_layer_profile_present_flag = (flags >> (15 - 2 * i)) & 1 == 1;
+            let sub_layer_level_present_flag = (flags >> (14 - 2 * i)) & 1 == 1;
+            if sub_layer_profile_present_flag {
+                size += 11;
+            }
+            if sub_layer_level_present_flag {
+                size += 1;
+            }
+        }
+    }
+    track!(rbsp_byte(size))?;
+    Ok(offsets[1]..offsets[size] + 1)
+}
+
+/// Makes an `hvcC` box (including the box header) from the VPS, SPS and PPS
+/// in the given Annex B HEVC stream.
+///
+/// If there are multiple parameter sets of the same type, the first one is used.
+pub fn hvcc_box_from_annexb(bytes: &[u8]) -> Result<Vec<u8>> {
+    let (vps, sps, pps) = track!(split_parameter_sets(bytes))?;
+    let hvcc_box = HvcConfigurationBox {
+        configuration: track!(HvcDecoderConfigurationRecord::from_parameter_sets(vps, sps, pps))?,
+    };
+    let mut buf = Vec::new();
+    write_box!(buf, hvcc_box);
+    Ok(buf)
+}
+
+const HDR10_PLUS_COUNTRY_CODE: u8 = 0xB5;
+const HDR10_PLUS_TERMINAL_PROVIDER_CODE: u16 = 0x003C;
+const HDR10_PLUS_TERMINAL_PROVIDER_ORIENTED_CODE: u16 = 0x0001;
+const HDR10_PLUS_APPLICATION_IDENTIFIER: u8 = 4;
+
+/// Extracts HDR10+ dynamic metadata (SMPTE ST 2094-40) from an HEVC SEI NAL unit.
+///
+/// `nal_unit` is a NAL unit including its header.
+/// The returned bytes are the application data following `terminal_provider_oriented_code`
+/// (i.e., they start with `application_identifier`).
+/// If the NAL unit carries no HDR10+ metadata, `None` is returned.
+pub fn read_hdr10_plus_metadata(nal_unit: &[u8]) -> Result<Option<Vec<u8>>> {
+    let header = track!(HevcNalUnit::read_from(nal_unit))?;
+    track_assert!(
+        header.nal_unit_type == HevcNalUnitType::PrefixSupplementalEnhancementInformation
+            || header.nal_unit_type == HevcNalUnitType::SuffixSupplementalEnhancementInformation,
+        ErrorKind::InvalidInput
+    );
+
+    let mut rbsp = Vec::new();
+    track_io!(RbspReader::new(&nal_unit[2..]).read_to_end(&mut rbsp))?;
+    for message in SeiMessages::new(&rbsp) {
+        let message = track!(message)?;
+        if message.payload_type != UserDataRegisteredItuTT35::PAYLOAD_TYPE {
+            continue;
+        }
+
+        let t35 = track!(UserDataRegisteredItuTT35::from_payload(message.payload))?;
+        let mut payload = t35.payload;
+        if t35.itu_t_t35_country_code != HDR10_PLUS_COUNTRY_CODE || payload.len() < 5 {
+            continue;
+        }
+        let terminal_provider_code = track_io!(payload.read_u16::<BigEndian>())?;
+        let terminal_provider_oriented_code = track_io!(payload.read_u16::<BigEndian>())?;
+        if terminal_provider_code == HDR10_PLUS_TERMINAL_PROVIDER_CODE
+            && terminal_provider_oriented_code == HDR10_PLUS_TERMINAL_PROVIDER_ORIENTED_CODE
+            && payload[0] == HDR10_PLUS_APPLICATION_IDENTIFIER
+        {
+            return Ok(Some(payload.to_owned()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_hdr10_plus_metadata() {
+        let nal_unit = [
+            0x4E, 0x01, // prefix SEI
+            0x04, 0x08, // user_data_registered_itu_t_t35, 8 bytes
+            0xB5, 0x00, 0x3C, 0x00, 0x01, 0x04, 0x01, 0x40, // HDR10+ header
+            0x80,
+        ];
+        let metadata = read_hdr10_plus_metadata(&nal_unit).unwrap();
+        assert_eq!(metadata, Some(vec![0x04, 0x01, 0x40]));
+
+        let other_provider = [
+            0x4E, 0x01, 0x04, 0x08, 0xB5, 0x00, 0x31, 0x47, 0x41, 0x39, 0x34, 0x03, 0x80,
+        ];
+        assert_eq!(read_hdr10_plus_metadata(&other_provider).unwrap(), None);
+
+        let not_sei = [0x40, 0x01, 0x0C];
+        assert!(read_hdr10_plus_metadata(&not_sei).is_err());
+    }
+
+    const VPS: [u8; 24] = [
+        0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00,
+        0x03, 0x00, 0x00, 0x03, 0x00, 0x5d, 0x95, 0xc0, 0x90,
+    ];
+
+    // Main profile, 1280x720, level 3.1
+    const SPS: [u8; 29] = [
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x03, 0x00, 0x5d, 0xa0, 0x02, 0x80, 0x80, 0x2d, 0x16, 0x59, 0x5e, 0x49, 0x36, 0xb2,
+    ];
+    const PPS: [u8; 6] = [0x44, 0x01, 0xc0, 0x71, 0x80, 0x89];
+
+    #[test]
+    fn test_read_vps() {
+        let vps = HevcVpsSummary::read_from(&VPS[2..]).unwrap();
+        assert_eq!(vps.vps_video_parameter_set_id, 0);
+        assert_eq!(vps.vps_max_layers_minus1, 0);
+        assert_eq!(vps.vps_max_sub_layers_minus1, 0);
+        assert!(vps.vps_temporal_id_nesting_flag);
+        assert_eq!(vps.profile_tier_level.general_level_idc, 93);
+    }
+
+    #[test]
+    fn test_codec_details() {
+        let mut record =
+            HvcDecoderConfigurationRecord::from_parameter_sets(&VPS, &SPS, &PPS).unwrap();
+        assert!(!record.is_main10());
+        assert_eq!(
+            record.codec_details(),
+            CodecDetails {
+                profile: Some("Main"),
+                tier: "Main",
+                level: "3.1".to_owned(),
+                chroma_subsampling: "4:2:0",
+                bit_depth_luma: 8,
+                bit_depth_chroma: 8,
+            }
+        );
+
+        record.general_profile_idc = 2;
+        record.general_tier_flag = true;
+        record.general_level_idc = 150;
+        record.bit_depth_luma_minus8 = 2;
+        record.bit_depth_chroma_minus8 = 2;
+        let details = record.codec_details();
+        assert!(record.is_main10());
+        assert_eq!((details.profile, details.tier), (Some("Main 10"), "High"));
+        assert_eq!(details.level, "5.0");
+        assert_eq!((details.bit_depth_luma, details.bit_depth_chroma), (10, 10));
+
+        record.bit_depth_chroma_minus8 = 4;
+        assert_eq!(record.max_bit_depth(), 12);
+    }
+
+    #[test]
+    fn test_read_sps_vui() {
+        let sps = HevcSpsSummary::read_from(&SPS[2..]).unwrap();
+        assert!(sps.vui_parameters.is_none());
+
+        // Main 10, 3840x2160, BT.2020 PQ, chroma_sample_loc_type_top_field=2
+        let sps = [
+            0x42, 0x01, 0x01, 0x02, 0x20, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x78, 0xa0, 0x01, 0xe0, 0x20, 0x02, 0x1c, 0x4d, 0x96, 0x57, 0x92,
+            0x4d, 0xae, 0x6a, 0x12, 0x20, 0x13, 0x6c, 0x04,
+        ];
+        let sps = HevcSpsSummary::read_from(&sps[2..]).unwrap();
+        assert_eq!((sps.width(), sps.height()), (3840, 2160));
+        let vui = sps.vui_parameters.unwrap();
+        assert!(vui.aspect_ratio_info.is_none());
+        let video_signal_type = vui.video_signal_type.unwrap();
+        assert_eq!(video_signal_type.video_format, 5);
+        let colour_description = video_signal_type.colour_description.unwrap();
+        assert_eq!(colour_description.transfer_characteristics, 16);
+        assert_eq!(
+            vui.chroma_loc_info,
+            Some(ChromaLocInfo {
+                chroma_sample_loc_type_top_field: 2,
+                chroma_sample_loc_type_bottom_field: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_general_constraint_flags() {
+        let mut record =
+            HvcDecoderConfigurationRecord::from_parameter_sets(&VPS, &SPS, &PPS).unwrap();
+        assert_eq!(record.general_constraint_indicator_flags, 0x9000_0000_0000);
+        assert!(record.general_progressive_source_flag());
+        assert!(!record.general_interlaced_source_flag());
+        assert!(!record.general_non_packed_constraint_flag());
+        assert!(record.general_frame_only_constraint_flag());
+
+        record.general_constraint_indicator_flags = 0x6000_0000_0000;
+        assert!(!record.general_progressive_source_flag());
+        assert!(record.general_interlaced_source_flag());
+        assert!(record.general_non_packed_constraint_flag());
+        assert!(!record.general_frame_only_constraint_flag());
+    }
+
+    #[test]
+    fn test_codec_string() {
+        let mut record =
+            HvcDecoderConfigurationRecord::from_parameter_sets(&VPS, &SPS, &PPS).unwrap();
+        assert_eq!(record.codec_string(), "hvc1.1.6.L93.90");
+        record.general_constraint_indicator_flags = 0xB000_0000_0000;
+        assert_eq!(record.codec_string(), "hvc1.1.6.L93.B0");
+        assert_eq!(record.codec_string_for_mode(SampleEntryMode::InBand), "hev1.1.6.L93.B0");
+
+        // Main 10, High tier, level 5.1
+        record.general_profile_idc = 2;
+        record.general_profile_compatibility_flags = 0;
+        record.set_profile_compatibility_flag();
+        record.general_tier_flag = true;
+        record.general_level_idc = 153;
+        record.general_constraint_indicator_flags = 0x9000_0000_0000;
+        assert_eq!(record.codec_string(), "hvc1.2.4.H153.90");
+
+        record.general_profile_space = 1;
+        record.general_constraint_indicator_flags = 0x9000_0001_0000;
+        assert_eq!(
+            record.codec_string_for_mode(SampleEntryMode::InBand),
+            "hev1.A2.4.H153.90.0.0.1"
+        );
+        record.general_constraint_indicator_flags = 0;
+        assert_eq!(record.codec_string_for_mode(SampleEntryMode::InBand), "hev1.A2.4.H153");
+    }
+
+    #[test]
+    fn test_read_hvc_decoder_configuration_record() {
+        let mut record =
+            HvcDecoderConfigurationRecord::from_parameter_sets(&VPS, &SPS, &PPS).unwrap();
+        record.min_spatial_segmentation_idc = 0x123;
+        record.avg_frame_rate = 3000;
+        record.constant_frame_rate = 1;
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+        assert_eq!(HvcDecoderConfigurationRecord::read_from(&bytes[..]).unwrap(), record);
+
+        // Reordered arrays, an SEI array and an array of two SPSs
+        let mut reordered = bytes[..22].to_vec();
+        reordered.push(4); // num_of_arrays
+        let arrays = [
+            (34, vec![&PPS[..]]),
+            (39, vec![&[0x4e, 0x01, 0x80][..]]),
+            (33, vec![&SPS[..], &SPS_WITH_RPS[..]]),
+            (32, vec![&VPS[..]]),
+        ];
+        for (nal_unit_type, nal_units) in arrays.iter() {
+            reordered.push(0b1000_0000 | nal_unit_type);
+            reordered.extend_from_slice(&(nal_units.len() as u16).to_be_bytes());
+            for nal_unit in nal_units {
+                reordered.extend_from_slice(&(nal_unit.len() as u16).to_be_bytes());
+                reordered.extend_from_slice(nal_unit);
+            }
+        }
+        let mut expected = record.clone();
+        expected.sps_data.push(SPS_WITH_RPS.to_vec());
+        assert_eq!(HvcDecoderConfigurationRecord::read_from(&reordered[..]).unwrap(), expected);
+
+        // Two-byte NAL unit lengths
+        let mut two_bytes = record.clone();
+        two_bytes.length_size = LengthSize::new(2).unwrap();
+        let mut actual = Vec::new();
+        two_bytes.write_to(&mut actual).unwrap();
+        assert_eq!(actual[21] & 0b11, 1); // lengthSizeMinusOne
+        assert_eq!(HvcDecoderConfigurationRecord::read_from(&actual[..]).unwrap(), two_bytes);
+
+        bytes[21] = (bytes[21] & !0b11) | 2; // Three-byte lengths are not allowed
+        assert!(HvcDecoderConfigurationRecord::read_from(&bytes[..]).is_err());
+        assert!(HvcDecoderConfigurationRecord::read_from(&bytes[..30]).is_err());
+    }
+
+    #[test]
+    fn test_write_num_of_arrays() {
+        fn array_types(bytes: &[u8]) -> Vec<u8> {
+            let mut types = Vec::new();
+            let mut rest = &bytes[23..];
+            for _ in 0..bytes[22] {
+                types.push(rest[0] & 0b11_1111);
+                let num_nalus = u16::from_be_bytes([rest[1], rest[2]]);
+                rest = &rest[3..];
+                for _ in 0..num_nalus {
+                    let len = usize::from(u16::from_be_bytes([rest[0], rest[1]]));
+                    rest = &rest[2 + len..];
+                }
+            }
+            assert!(rest.is_empty());
+            types
+        }
+
+        let mut record =
+            HvcDecoderConfigurationRecord::from_parameter_sets(&VPS, &SPS, &PPS).unwrap();
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+        assert_eq!(array_types(&bytes), [32, 33, 34]);
+
+        record.vps_data.clear();
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+        assert_eq!(array_types(&bytes), [33, 34]);
+        assert_eq!(HvcDecoderConfigurationRecord::read_from(&bytes[..]).unwrap(), record);
+
+        // Two SPSs in one array
+        record.sps_data.push(SPS_WITH_RPS.to_vec());
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+        assert_eq!(array_types(&bytes), [33, 34]);
+        assert_eq!(bytes[23], 0b1000_0000 | 33);
+        assert_eq!(&bytes[24..26], [0, 2]); // num_nalus
+        assert_eq!(usize::from(u16::from_be_bytes([bytes[26], bytes[27]])), SPS.len());
+        assert_eq!(&bytes[28..28 + SPS.len()], &SPS[..]);
+        let rest = &bytes[28 + SPS.len()..];
+        assert_eq!(usize::from(u16::from_be_bytes([rest[0], rest[1]])), SPS_WITH_RPS.len());
+        assert_eq!(&rest[2..2 + SPS_WITH_RPS.len()], &SPS_WITH_RPS[..]);
+        assert_eq!(HvcDecoderConfigurationRecord::read_from(&bytes[..]).unwrap(), record);
+    }
+
+    #[test]
+    fn test_set_profile_compatibility_flag() {
+        let mut record =
+            HvcDecoderConfigurationRecord::from_parameter_sets(&VPS, &SPS, &PPS).unwrap();
+        assert_eq!(record.general_profile_idc, 1);
+        assert_eq!(record.general_profile_compatibility_flags, 0x6000_0000);
+
+        record.general_profile_compatibility_flags = 0;
+        record.set_profile_compatibility_flag();
+        assert_eq!(record.general_profile_compatibility_flags, 0x4000_0000);
+
+        record.general_profile_idc = 2;
+        record.set_profile_compatibility_flag();
+        assert_eq!(record.general_profile_compatibility_flags, 0x6000_0000);
+    }
+
+    #[test]
+    fn test_num_temporal_layers_exceeding_vps() {
+        let mut record =
+            HvcDecoderConfigurationRecord::from_parameter_sets(&VPS, &SPS, &PPS).unwrap();
+        assert_eq!(record.num_temporal_layers, 1);
+        assert!(record.write_to(Vec::new()).is_ok());
+
+        record.num_temporal_layers = 2;
+        assert!(record.write_to(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_read_sps() {
+        let sps = HevcSpsSummary::read_from(&SPS[2..]).unwrap();
+        assert_eq!(sps.profile_tier_level.general_profile_idc, 1);
+        assert_eq!(sps.profile_tier_level.general_profile_compatibility_flags, 0x6000_0000);
+        assert_eq!(sps.profile_tier_level.general_constraint_indicator_flags, 0x9000_0000_0000);
+        assert_eq!(sps.profile_tier_level.general_level_idc, 93);
+        assert_eq!(sps.chroma_format_idc, 1);
+        assert_eq!((sps.width(), sps.height()), (1280, 720));
+
+        // A conformance window exceeding the pictures
+        let mut cropped = sps.clone();
+        cropped.conformance_window = [u64::MAX - 1, 1, 400, 0];
+        assert_eq!((cropped.width(), cropped.height()), (0, 0));
+
+        assert!(sps.amp_enabled_flag);
+        assert!(sps.sample_adaptive_offset_enabled_flag);
+        assert!(sps.sps_temporal_mvp_enabled_flag);
+        assert!(sps.strong_intra_smoothing_enabled_flag);
+    }
+
+    // Main profile, 1920x1080, with a scaling list, PCM and two short-term RPSs
+    // (the second one is predicted from the first one).
+    const SPS_WITH_RPS: [u8; 71] = [
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x03, 0x00, 0x5d, 0xa0, 0x03, 0xc0, 0x80, 0x11, 0x07, 0xcb, 0x96, 0x57, 0x92, 0x4f, 0xa4,
+        0x92, 0x49, 0x24, 0x92, 0x49, 0x2a, 0xaa, 0xab, 0x08, 0x24, 0x92, 0x49, 0x24, 0x92, 0x49,
+        0x24, 0x92, 0x49, 0x24, 0x92, 0x49, 0x24, 0x92, 0x49, 0x24, 0x92, 0x49, 0x24, 0x92, 0x49,
+        0x24, 0x92, 0x49, 0x2a, 0xab, 0x5d, 0xea, 0xda, 0xd1, 0x7f, 0xb2,
+    ];
+
+    #[test]
+    fn test_read_short_term_ref_pic_sets() {
+        let sps = HevcSpsSummary::read_from(&SPS[2..]).unwrap();
+        assert_eq!(sps.log2_max_pic_order_cnt_lsb_minus4, 4);
+        assert!(!sps.scaling_list_enabled_flag);
+        assert!(sps.scaling_list_data.is_none());
+        assert!(sps.short_term_ref_pic_sets.is_empty());
+
+        let sps = HevcSpsSummary::read_from(&SPS_WITH_RPS[2..]).unwrap();
+        assert_eq!((sps.width(), sps.height()), (1920, 1080));
+        assert_eq!(sps.short_term_ref_pic_sets.len(), 2);
+        assert!(!sps.sample_adaptive_offset_enabled_flag);
+        assert!(sps.sps_temporal_mvp_enabled_flag);
+
+        assert!(sps.scaling_list_enabled_flag);
+        let scaling_lists = &sps.scaling_list_data.as_ref().unwrap().scaling_lists;
+        assert_eq!(
+            scaling_lists.iter().map(|lists| lists.len()).collect::<Vec<_>>(),
+            [6, 6, 6, 2]
+        );
+        assert_eq!(
+            scaling_lists[0][0],
+            ScalingList::Explicit {
+                dc_coef: None,
+                coefficients: (9..25).collect()
+            }
+        );
+        assert_eq!(
+            scaling_lists[2][0],
+            ScalingList::Explicit {
+                dc_coef: Some(16),
+                coefficients: (17..81).collect()
+            }
+        );
+        assert_eq!(
+            scaling_lists[3][1],
+            ScalingList::Predicted {
+                scaling_list_pred_matrix_id_delta: 0
+            }
+        );
+
+        let rps = &sps.short_term_ref_pic_sets[0];
+        assert_eq!(rps.delta_poc_s0, [-1, -3]);
+        assert_eq!(rps.used_by_curr_pic_s0, [true, false]);
+        assert_eq!(rps.delta_poc_s1, [2]);
+        assert_eq!(rps.used_by_curr_pic_s1, [true]);
+
+        // deltaRps = -1
+        let rps = &sps.short_term_ref_pic_sets[1];
+        assert_eq!(rps.delta_poc_s0, [-1, -2, -4]);
+        assert_eq!(rps.used_by_curr_pic_s0, [true, true, true]);
+        assert_eq!(rps.delta_poc_s1, [1]);
+        assert_eq!(rps.num_delta_pocs(), 4);
+    }
+
+    #[test]
+    fn test_hvcc_box_from_annexb() {
+        let mut bytes = Vec::new();
+        for nal_unit in &[&VPS[..], &SPS[..], &PPS[..]] {
+            bytes.extend_from_slice(&[0, 0, 0, 1]);
+            bytes.extend_from_slice(nal_unit);
+        }
+        let hvcc = hvcc_box_from_annexb(&bytes).unwrap();
+        assert_eq!(&hvcc[4..8], b"hvcC");
+
+        let record = &hvcc[8..];
+        assert_eq!(&record[..13], &[1, 0x01, 0x60, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 93]);
+        assert_eq!(&record[13..22], &[0xf0, 0, 0xfc, 0xfd, 0xf8, 0xf8, 0, 0, 0x0f]);
+        assert_eq!(record[22], 3); // num_of_arrays
+        assert_eq!(&record[23..28], &[0xa0, 0, 1, 0, VPS.len() as u8]);
+        assert_eq!(&record[28..28 + VPS.len()], &VPS[..]);
+        assert_eq!(hvcc.len(), 8 + 23 + 3 * 5 + VPS.len() + SPS.len() + PPS.len());
+    }
+
+    #[test]
+    fn test_sub_layer_ordering_info() {
+        let sps = HevcSpsSummary::read_from(&SPS[2..]).unwrap();
+        assert_eq!(sps.max_dec_pic_buffering(0), Some(5));
+        assert_eq!(sps.max_num_reorder_pics(0), Some(2));
+        assert_eq!(sps.max_dec_pic_buffering(1), None);
+        assert_eq!(sps.max_num_reorder_pics(1), None);
+
+        // Two sub-layers with `sps_sub_layer_ordering_info_present_flag=1`
+        let sps = [
+            0x42, 0x01, 0x02, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5d, 0x00, 0x00, 0xa0, 0x02, 0x80, 0x80, 0x2d, 0x16, 0x5a, 0xca,
+            0xf2, 0x49, 0xb5, 0x90,
+        ];
+        let sps = HevcSpsSummary::read_from(&sps[2..]).unwrap();
+        assert_eq!((sps.width(), sps.height()), (1280, 720));
+        assert_eq!(sps.max_dec_pic_buffering(0), Some(2));
+        assert_eq!(sps.max_num_reorder_pics(0), Some(0));
+        assert_eq!(sps.max_dec_pic_buffering(1), Some(5));
+        assert_eq!(sps.max_num_reorder_pics(1), Some(2));
+
+        // Two sub-layers with `sps_sub_layer_ordering_info_present_flag=0`
+        let sps = [
+            0x42, 0x01, 0x02, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5d, 0x00, 0x00, 0xa0, 0x02, 0x80, 0x80, 0x2d, 0x16, 0x51, 0x5e,
+            0x49, 0x36, 0xb2,
+        ];
+        let sps = HevcSpsSummary::read_from(&sps[2..]).unwrap();
+        assert_eq!(sps.sub_layer_ordering_info.len(), 2);
+        assert_eq!(sps.max_dec_pic_buffering(0), Some(5));
+        assert_eq!(sps.max_num_reorder_pics(0), Some(2));
+        assert_eq!(sps.max_dec_pic_buffering(1), Some(5));
+        assert_eq!(sps.max_num_reorder_pics(1), Some(2));
+    }
+
+    #[test]
+    fn test_sps_profile_tier_level_range() {
+        // The general fields contain two emulation prevention bytes
+        let range = sps_profile_tier_level_range(&SPS).unwrap();
+        assert_eq!(range, 3..18);
+        let mut ptl = Vec::new();
+        RbspReader::new(&SPS[range]).read_to_end(&mut ptl).unwrap();
+        assert_eq!(ptl.len(), 12);
+
+        let record = HvcDecoderConfigurationRecord::from_parameter_sets(&VPS, &SPS, &PPS).unwrap();
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[1..13], &ptl[..]);
+
+        // Two sub-layers without sub-layer profiles and levels
+        let sps = [
+            0x42, 0x01, 0x02, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5d, 0x00, 0x00, 0xa0, 0x02, 0x80, 0x80, 0x2d, 0x16, 0x5a, 0xca,
+            0xf2, 0x49, 0xb5, 0x90,
+        ];
+        assert_eq!(sps_profile_tier_level_range(&sps).unwrap(), 3..20);
+
+        assert!(sps_profile_tier_level_range(&SPS[..10]).is_err());
+        assert!(sps_profile_tier_level_range(&PPS).is_err());
+    }
+
+    #[test]
+    fn test_is_append_compatible() {
+        let record = HvcDecoderConfigurationRecord::from_parameter_sets(&VPS, &SPS, &PPS).unwrap();
+        assert!(record.is_append_compatible(&record.clone()));
+
+        let mut other = record.clone();
+        other.general_level_idc = 120;
+        assert!(!record.is_append_compatible(&other));
+
+        let mut other = record.clone();
+        other.pps_data[0].push(0x80);
+        assert_eq!(record.codec_details(), other.codec_details());
+        assert!(!record.is_append_compatible(&other));
+
+        let mut broken = record.clone();
+        broken.general_profile_idc = 32;
+        assert!(!broken.is_append_compatible(&broken.clone()));
+    }
+
+    #[test]
+    fn test_read_access_unit_delimiter() {
+        let aud = [0x46, 0x01, 0x50]; // pic_type=2
+        let header = HevcNalUnit::read_from(&aud[..]).unwrap();
+        assert_eq!(header.nal_unit_type, HevcNalUnitType::AccessUnitDelimiter);
+        let aud = HevcAccessUnitDelimiter::read_from(&aud[2..]).unwrap();
+        assert_eq!(aud.pic_type, 2);
+
+        let aud = HevcAccessUnitDelimiter::read_from(&[0x10][..]).unwrap();
+        assert_eq!(aud.pic_type, 0);
+        assert!(HevcAccessUnitDelimiter::read_from(&[][..]).is_err());
+    }
+}
@@ -0,0 +1,474 @@
+//! Elementary stream inspection.
+use crate::avc::{
+    nal_unit_type_of, ByteStreamFormatNalUnits, NalUnit, NalUnitType, SliceHeaderSummary,
+    SliceType, SpsSummary,
+};
+use crate::codec::Codec;
+use crate::hevc::{HevcNalUnit, HevcNalUnitType, HevcSpsSummary, HevcVpsSummary};
+use crate::sei::has_recovery_point;
+use crate::{ErrorKind, Result};
+use std::collections::{BTreeMap, HashSet};
+
+/// Summary of an Annex B elementary stream.
+#[derive(Debug, Clone)]
+pub struct StreamReport {
+    /// The detected codec (`None` if no parameter set was found).
+    pub codec: Option<Codec>,
+
+    /// The summary of the first SPS (only available for H.264 streams).
+    pub sps: Option<SpsReport>,
+
+    /// The number of NAL units per `nal_unit_type`.
+    ///
+    /// The keys are the raw values of the codec specific `nal_unit_type`.
+    pub nal_unit_type_histogram: BTreeMap<u8, usize>,
+
+    /// The number of access units containing an IDR picture.
+    pub idr_access_units: usize,
+
+    /// Whether B slices are present (`None` if slice types are not determined for the codec).
+    pub has_b_slices: Option<bool>,
+
+    /// Whether pictures may be output in an order different from the decoding order
+    /// (`None` if this is not determined for the codec).
+    pub has_reordering: Option<bool>,
+}
+
+/// Summary of an SPS in a `StreamReport`.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub struct SpsReport {
+    pub profile_idc: u8,
+    pub level_idc: u8,
+    pub width: usize,
+    pub height: usize,
+
+    /// `chroma_format_idc` (`1` is assumed if it is not signaled).
+    pub chroma_format: u64,
+}
+impl SpsReport {
+    fn new(sps: &SpsSummary) -> Self {
+        SpsReport {
+            profile_idc: sps.profile_idc,
+            level_idc: sps.level_idc,
+            width: sps.width(),
+            height: sps.height(),
+            chroma_format: sps
+                .extended_configuration_data
+                .as_ref()
+                .map_or(1, |x| x.chroma_format),
+        }
+    }
+}
+
+/// Scans the given Annex B elementary stream and reports its characteristics.
+pub fn inspect(bytes: &[u8]) -> Result<StreamReport> {
+    let codec = detect_codec(bytes);
+    let mut report = StreamReport {
+        codec,
+        sps: None,
+        nal_unit_type_histogram: BTreeMap::new(),
+        idr_access_units: 0,
+        has_b_slices: None,
+        has_reordering: None,
+    };
+    match codec {
+        Some(Codec::H264) => track!(inspect_avc(bytes, &mut report))?,
+        Some(Codec::H265) => track!(inspect_hevc(bytes, &mut report))?,
+        None => {
+            for nal_unit in track!(ByteStreamFormatNalUnits::new(bytes))? {
+                if let Some(&b) = nal_unit.first() {
+                    *report.nal_unit_type_histogram.entry(b & 0b1_1111).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn inspect_avc(bytes: &[u8], report: &mut StreamReport) -> Result<()> {
+    let mut has_b_slices = false;
+    let mut has_reordering = false;
+    for nal_unit in track!(ByteStreamFormatNalUnits::new(bytes))? {
+        if nal_unit.is_empty() {
+            continue;
+        }
+        *report
+            .nal_unit_type_histogram
+            .entry(nal_unit[0] & 0b1_1111)
+            .or_insert(0) += 1;
+
+        let nal_unit_type = match nal_unit_type_of(nal_unit[0]) {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+        match nal_unit_type {
+            NalUnitType::SequenceParameterSet => {
+                let sps = track!(SpsSummary::read_from(&nal_unit[1..]))?;
+                has_reordering |= sps.max_num_reorder_frames().unwrap_or(0) > 0;
+                if report.sps.is_none() {
+                    report.sps = Some(SpsReport::new(&sps));
+                }
+            }
+            NalUnitType::CodedSliceOfANonIdrPicture | NalUnitType::CodedSliceOfAnIdrPicture => {
+                let slice_header = track!(SliceHeaderSummary::read_from(&nal_unit[1..]))?;
+                has_b_slices |= slice_header.slice_type == SliceType::B;
+                if nal_unit_type == NalUnitType::CodedSliceOfAnIdrPicture
+                    && slice_header.first_mb_in_slice == 0
+                {
+                    report.idr_access_units += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    report.has_b_slices = Some(has_b_slices);
+    report.has_reordering = Some(has_reordering || has_b_slices);
+    Ok(())
+}
+
+fn inspect_hevc(bytes: &[u8], report: &mut StreamReport) -> Result<()> {
+    for nal_unit in track!(ByteStreamFormatNalUnits::new(bytes))? {
+        if nal_unit.len() < 2 {
+            continue;
+        }
+        let nal_unit_type = (nal_unit[0] >> 1) & 0b11_1111;
+        *report
+            .nal_unit_type_histogram
+            .entry(nal_unit_type)
+            .or_insert(0) += 1;
+
+        let is_idr = matches!(
+            HevcNalUnitType::try_from_u8(nal_unit_type),
+            Some(HevcNalUnitType::IdrWRadl) | Some(HevcNalUnitType::IdrNLp)
+        );
+        // `first_slice_segment_in_pic_flag` is the first bit of the slice segment header.
+        if is_idr && nal_unit.len() > 2 && nal_unit[2] & 0b1000_0000 != 0 {
+            report.idr_access_units += 1;
+        }
+    }
+    Ok(())
+}
+
+/// The acceptance criteria of `validate_annexb_with()`.
+#[derive(Debug, Clone)]
+pub struct AnnexBRequirements {
+    /// The NAL unit types that H.264 streams must contain.
+    pub avc_nal_unit_types: Vec<NalUnitType>,
+
+    /// The NAL unit types that H.265 streams must contain.
+    pub hevc_nal_unit_types: Vec<HevcNalUnitType>,
+
+    /// Whether streams must contain a random access point
+    /// (an IDR picture, an IRAP picture for H.265, or a `recovery_point` SEI message).
+    pub random_access_point: bool,
+}
+impl Default for AnnexBRequirements {
+    /// Requires the parameter sets (SPS and PPS, plus VPS for H.265) and a random access point.
+    fn default() -> Self {
+        AnnexBRequirements {
+            avc_nal_unit_types: vec![
+                NalUnitType::SequenceParameterSet,
+                NalUnitType::PictureParameterSet,
+            ],
+            hevc_nal_unit_types: vec![
+                HevcNalUnitType::VideoParameterSet,
+                HevcNalUnitType::SequenceParameterSet,
+                HevcNalUnitType::PictureParameterSet,
+            ],
+            random_access_point: true,
+        }
+    }
+}
+
+/// Checks that the given Annex B elementary stream is well-formed
+/// under the default `AnnexBRequirements`.
+pub fn validate_annexb(bytes: &[u8]) -> Result<()> {
+    track!(validate_annexb_with(bytes, &AnnexBRequirements::default()))
+}
+
+/// Checks that the given Annex B elementary stream is well-formed.
+///
+/// The stream must start with a start code, its codec must be detected by `detect_codec()`,
+/// every NAL unit must have a valid header, and the NAL units required by `requirements`
+/// must be present.
+/// The first problem found is returned as an `ErrorKind::InvalidInput` error.
+pub fn validate_annexb_with(bytes: &[u8], requirements: &AnnexBRequirements) -> Result<()> {
+    track_assert!(
+        bytes.starts_with(&[0, 0, 1]) || bytes.starts_with(&[0, 0, 0, 1]),
+        ErrorKind::InvalidInput,
+        "The stream does not start with a start code"
+    );
+    let codec = track_assert_some!(
+        detect_codec(bytes),
+        ErrorKind::InvalidInput,
+        "Cannot detect the codec (no unambiguous SPS or VPS is found)"
+    );
+
+    let mut avc_nal_unit_types = HashSet::new();
+    let mut hevc_nal_unit_types = HashSet::new();
+    let mut has_random_access_point = false;
+    for (i, nal_unit) in track!(ByteStreamFormatNalUnits::new(bytes))?.enumerate() {
+        track_assert!(
+            matches!(nal_unit.first(), Some(&b) if b & 0x80 == 0),
+            ErrorKind::InvalidInput,
+            "NAL unit #{} is empty or its forbidden_zero_bit is set",
+            i
+        );
+        match codec {
+            Codec::H264 => {
+                let header = track!(NalUnit::read_from(nal_unit), "NAL unit #{}", i)?;
+                has_random_access_point |= match header.nal_unit_type {
+                    NalUnitType::CodedSliceOfAnIdrPicture => true,
+                    NalUnitType::SupplementalEnhancementInformation => {
+                        has_recovery_point(&nal_unit[1..])
+                    }
+                    _ => false,
+                };
+                avc_nal_unit_types.insert(header.nal_unit_type);
+            }
+            Codec::H265 => {
+                let header = track!(HevcNalUnit::read_from(nal_unit), "NAL unit #{}", i)?;
+                has_random_access_point |= match header.nal_unit_type {
+                    t if t.is_irap() => true,
+                    HevcNalUnitType::PrefixSupplementalEnhancementInformation => {
+                        has_recovery_point(&nal_unit[2..])
+                    }
+                    _ => false,
+                };
+                hevc_nal_unit_types.insert(header.nal_unit_type);
+            }
+        }
+    }
+
+    match codec {
+        Codec::H264 => {
+            for nal_unit_type in &requirements.avc_nal_unit_types {
+                track_assert!(
+                    avc_nal_unit_types.contains(nal_unit_type),
+                    ErrorKind::InvalidInput,
+                    "No {:?} NAL unit",
+                    nal_unit_type
+                );
+            }
+        }
+        Codec::H265 => {
+            for nal_unit_type in &requirements.hevc_nal_unit_types {
+                track_assert!(
+                    hevc_nal_unit_types.contains(nal_unit_type),
+                    ErrorKind::InvalidInput,
+                    "No {:?} NAL unit",
+                    nal_unit_type
+                );
+            }
+        }
+    }
+    track_assert!(
+        has_random_access_point || !requirements.random_access_point,
+        ErrorKind::InvalidInput,
+        "No random access point"
+    );
+    Ok(())
+}
+
+/// Returns the byte offsets in `bytes` of the NAL units that begin random accessible pictures.
+///
+/// These are the first slices of IDR pictures for H.264, and the first slice segments of
+/// IRAP (IDR, CRA and BLA) pictures in the base layer for H.265.
+/// Recovery point SEIs are not taken into account, so this also works for open-GOP streams
+/// that lack them. Each offset points to the NAL unit header (following its start code).
+/// An empty list is returned if `bytes` does not start with a start code.
+pub fn random_access_points(bytes: &[u8], codec: Codec) -> Vec<usize> {
+    let nal_units = match ByteStreamFormatNalUnits::new(bytes) {
+        Ok(nal_units) => nal_units,
+        Err(_) => return Vec::new(),
+    };
+    let mut offsets = Vec::new();
+    for nal_unit in nal_units {
+        // `first_mb_in_slice` is `0` (H.264) or `first_slice_segment_in_pic_flag` is `1` (H.265)
+        // if the first bit of the slice header is set.
+        let starts_picture = |header_size: usize| {
+            matches!(nal_unit.get(header_size), Some(&b) if b & 0x80 != 0)
+        };
+        let is_random_access_point = match codec {
+            Codec::H264 => {
+                let nal_unit_type = nal_unit.first().and_then(|&b| nal_unit_type_of(b).ok());
+                nal_unit_type == Some(NalUnitType::CodedSliceOfAnIdrPicture) && starts_picture(1)
+            }
+            Codec::H265 => match HevcNalUnit::read_from(nal_unit) {
+                Ok(header) => {
+                    header.nuh_layer_id == 0 && header.nal_unit_type.is_irap() && starts_picture(2)
+                }
+                Err(_) => false,
+            },
+        };
+        if is_random_access_point {
+            offsets.push(nal_unit.as_ptr() as usize - bytes.as_ptr() as usize);
+        }
+    }
+    offsets
+}
+
+/// The number of leading NAL units examined by `detect_codec()`.
+const MAX_DETECTION_NAL_UNITS: usize = 16;
+
+/// Detects the codec of an Annex B elementary stream from its parameter sets.
+///
+/// The first few NAL units are examined under both the H.264 (one byte header) and
+/// the H.265 (two bytes header) interpretations.
+/// Since the same header bits may be valid under both of them, a NAL unit is regarded
+/// as a parameter set only if its payload is successfully parsed as an H.264 SPS
+/// or an H.265 VPS/SPS.
+///
+/// `None` is returned if no parameter set is found or if both interpretations match
+/// (i.e., the stream is ambiguous).
+pub fn detect_codec(bytes: &[u8]) -> Option<Codec> {
+    let nal_units = ByteStreamFormatNalUnits::new(bytes).ok()?;
+    let mut is_avc = false;
+    let mut is_hevc = false;
+    for nal_unit in nal_units.take(MAX_DETECTION_NAL_UNITS) {
+        is_avc |= is_avc_sps(nal_unit);
+        is_hevc |= is_hevc_parameter_set(nal_unit);
+    }
+    match (is_avc, is_hevc) {
+        (true, false) => Some(Codec::H264),
+        (false, true) => Some(Codec::H265),
+        _ => None,
+    }
+}
+
+fn is_avc_sps(nal_unit: &[u8]) -> bool {
+    match nal_unit.first() {
+        Some(&b) if nal_unit_type_of(b).ok() == Some(NalUnitType::SequenceParameterSet) => {
+            SpsSummary::read_from(&nal_unit[1..]).is_ok()
+        }
+        _ => false,
+    }
+}
+
+fn is_hevc_parameter_set(nal_unit: &[u8]) -> bool {
+    let header = match HevcNalUnit::read_from(nal_unit) {
+        Ok(header) if header.nuh_layer_id == 0 => header,
+        _ => return false,
+    };
+    match header.nal_unit_type {
+        HevcNalUnitType::VideoParameterSet => HevcVpsSummary::read_from(&nal_unit[2..]).is_ok(),
+        HevcNalUnitType::SequenceParameterSet => {
+            HevcSpsSummary::read_from(&nal_unit[2..]).is_ok()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inspect_avc() {
+        let bytes = [
+            0, 0, 0, 1, 0x67, 0x42, 0xe0, 0x1e, 0xda, 0x02, 0xd0, 0xf6, 0xff, 0xc0, 0x02, 0x00,
+            0x02, 0x44, 0x00, 0x00, 0x2e, 0xec, 0x00, 0x0a, 0xfc, 0x82, 0x10, // SPS
+            0, 0, 0, 1, 0x68, 0xce, 0x33, 0xc8, // PPS
+            0, 0, 1, 0x65, 0b1000_1000, 0b1000_0000, // IDR (first_mb_in_slice=0, slice_type=7)
+            0, 0, 1, 0x41, 0b1001_1010, // non-IDR (first_mb_in_slice=0, slice_type=5)
+        ];
+        let report = inspect(&bytes).unwrap();
+        assert_eq!(report.codec, Some(Codec::H264));
+
+        let sps = report.sps.as_ref().unwrap();
+        assert_eq!((sps.profile_idc, sps.level_idc), (66, 30));
+        assert_eq!((sps.width, sps.height, sps.chroma_format), (720, 480, 1));
+
+        assert_eq!(report.nal_unit_type_histogram.get(&5), Some(&1));
+        assert_eq!(report.nal_unit_type_histogram.get(&1), Some(&1));
+        assert_eq!(report.idr_access_units, 1);
+        assert_eq!(report.has_b_slices, Some(false));
+        assert_eq!(report.has_reordering, Some(false));
+    }
+
+    #[test]
+    fn test_inspect_hevc() {
+        let bytes = [
+            0, 0, 0, 1, 0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00,
+            0x90, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x00, 0x5d, 0x95, 0xc0, 0x90, // VPS
+            0, 0, 0, 1, 0x26, 0x01, 0xaf, // IDR_W_RADL (first_slice_segment_in_pic_flag=1)
+        ];
+        let report = inspect(&bytes).unwrap();
+        assert_eq!(report.codec, Some(Codec::H265));
+        assert!(report.sps.is_none());
+        assert_eq!(report.idr_access_units, 1);
+        assert_eq!(report.has_b_slices, None);
+    }
+
+    #[test]
+    fn test_validate_annexb() {
+        let sps = [
+            0, 0, 0, 1, 0x67, 0x42, 0xe0, 0x1e, 0xda, 0x02, 0xd0, 0xf6, 0xff, 0xc0, 0x02, 0x00,
+            0x02, 0x44, 0x00, 0x00, 0x2e, 0xec, 0x00, 0x0a, 0xfc, 0x82, 0x10,
+        ];
+        let pps = [0, 0, 0, 1, 0x68, 0xce, 0x33, 0xc8];
+        let idr = [0, 0, 1, 0x65, 0b1000_1000, 0b1000_0000];
+        let recovery_point = [0, 0, 1, 0x06, 0x06, 0x01, 0b1000_0000, 0x80];
+        let non_idr = [0, 0, 1, 0x41, 0b1001_1010];
+
+        let bytes = [&sps[..], &pps, &idr].concat();
+        validate_annexb(&bytes).unwrap();
+        let bytes = [&sps[..], &pps, &recovery_point, &non_idr].concat();
+        validate_annexb(&bytes).unwrap();
+
+        let bytes = [&sps[..], &pps, &non_idr].concat();
+        assert!(validate_annexb(&bytes).is_err());
+        let requirements = AnnexBRequirements {
+            random_access_point: false,
+            ..AnnexBRequirements::default()
+        };
+        validate_annexb_with(&bytes, &requirements).unwrap();
+
+        let bytes = [&sps[..], &idr].concat();
+        assert!(validate_annexb(&bytes).is_err());
+        let bytes = [&sps[..], &pps, &idr, &[0, 0, 1, 0xe5, 0x88]].concat();
+        assert!(validate_annexb(&bytes).is_err());
+        assert!(validate_annexb(&[&[0xff][..], &sps, &pps, &idr].concat()).is_err());
+    }
+
+    #[test]
+    fn test_random_access_points() {
+        let sps = [0, 0, 0, 1, 0x67, 0x42, 0xe0, 0x1e];
+        let idr = [0, 0, 1, 0x65, 0b1000_1000, 0b1000_0000];
+        let idr_second_slice = [0, 0, 1, 0x65, 0b0100_1000];
+        let non_idr = [0, 0, 1, 0x41, 0b1001_1010];
+        let bytes = [&sps[..], &idr, &idr_second_slice, &non_idr, &idr].concat();
+        assert_eq!(random_access_points(&bytes, Codec::H264), [11, 27]);
+
+        let vps = [0, 0, 0, 1, 0x40, 0x01, 0x0c];
+        let idr_w_radl = [0, 0, 1, 0x26, 0x01, 0xaf];
+        let cra = [0, 0, 1, 0x2a, 0x01, 0xaf];
+        let bla_second_segment = [0, 0, 1, 0x20, 0x01, 0x2f];
+        let trail_r = [0, 0, 1, 0x02, 0x01, 0xd0];
+        let enhancement_layer_cra = [0, 0, 1, 0x2a, 0x09, 0xaf];
+        let bytes = [
+            &vps[..],
+            &idr_w_radl,
+            &trail_r,
+            &cra,
+            &bla_second_segment,
+            &enhancement_layer_cra,
+        ]
+        .concat();
+        assert_eq!(random_access_points(&bytes, Codec::H265), [10, 22]);
+
+        assert!(random_access_points(&[0xff, 0x65, 0x88], Codec::H264).is_empty());
+    }
+
+    #[test]
+    fn test_detect_codec() {
+        // An H.264 slice whose header byte is also valid as an H.265 VPS header
+        let bytes = [0, 0, 0, 1, 0x41, 0x01, 0x9a, 0x00];
+        assert_eq!(detect_codec(&bytes), None);
+
+        let bytes = [0, 0, 0, 1, 0x40, 0x01, 0x0c];
+        assert_eq!(detect_codec(&bytes), None);
+        assert_eq!(detect_codec(&[]), None);
+    }
+}
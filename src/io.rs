@@ -1,13 +1,8 @@
 //! I/O related constituent elements.
-use crate::Result;
+use crate::{ErrorKind, Result};
 use byteorder::{ReadBytesExt, WriteBytesExt};
 use std::io::{sink, Read, Result as IoResult, Sink, Write};
 
-const FIRST_U32_BYTE: u32 = 0xFF000000;
-const SECOND_U32_BYTE: u32 = 0x00FF0000;
-const THIRD_U32_BYTE: u32 = 0x0000FF00;
-const FOURTH_U32_BYTE: u32 = 0x000000FF;
-
 /// A trait for objects which can be written to byte-oriented sinks.
 pub trait WriteTo {
     /// Writes this object to the given byte-oriented sink.
@@ -57,6 +52,59 @@ impl<T: Write> Write for ByteCounter<T> {
     }
 }
 
+/// A reader that removes `emulation_prevention_three_byte`s from a NAL unit payload.
+#[derive(Debug)]
+pub(crate) struct RbspReader<R> {
+    inner: R,
+    zeros: usize,
+}
+impl<R: Read> RbspReader<R> {
+    pub fn new(inner: R) -> Self {
+        RbspReader { inner, zeros: 0 }
+    }
+}
+impl<R: Read> Read for RbspReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let mut size = 0;
+        while size < buf.len() {
+            let mut byte = [0];
+            if self.inner.read(&mut byte)? == 0 {
+                break;
+            }
+            if self.zeros >= 2 && byte[0] == 3 {
+                self.zeros = 0;
+                continue;
+            }
+            self.zeros = if byte[0] == 0 { self.zeros + 1 } else { 0 };
+            buf[size] = byte[0];
+            size += 1;
+        }
+        Ok(size)
+    }
+}
+
+/// Inserts `emulation_prevention_three_byte`s into the given RBSP.
+///
+/// If the escaped bytes end with a zero byte (e.g., the RBSP ends with `cabac_zero_word`s),
+/// a final `0x03` byte is appended so that the zero is not taken as
+/// a part of the following start code (7.4.1, ISO/IEC 14496-10).
+pub(crate) fn escape_rbsp(rbsp: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(rbsp.len());
+    let mut zeros = 0;
+    for &b in rbsp {
+        if zeros >= 2 && b <= 3 {
+            escaped.push(3);
+            zeros = 0;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        escaped.push(b);
+    }
+    if escaped.last() == Some(&0) {
+        escaped.push(3);
+    }
+    escaped
+}
+
 #[derive(Debug)]
 pub(crate) struct AvcBitReader<R> {
     stream: R,
@@ -82,28 +130,46 @@ impl<R: Read> AvcBitReader<R> {
         Ok(bit)
     }
 
+    pub fn read_bits(&mut self, n: usize) -> Result<u64> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = (value << 1) | u64::from(track!(self.read_bit())?);
+        }
+        Ok(value)
+    }
+
     pub fn read_byte(&mut self) -> Result<u8> {
         self.bit_offset = 0;
         self.byte = track_io!(self.stream.read_u8())?;
         Ok(self.byte)
     }
 
+    /// Returns the number of bits of the current byte that have not been read yet.
+    pub fn unread_bits_in_byte(&self) -> usize {
+        8 - self.bit_offset
+    }
+
     pub fn read_ue(&mut self) -> Result<u64> {
         track!(self.read_exp_golomb_code())
     }
 
+    pub fn read_se(&mut self) -> Result<i64> {
+        let n = track!(self.read_exp_golomb_code())?;
+        if n % 2 == 1 {
+            Ok((n / 2 + 1) as i64)
+        } else {
+            Ok(-((n / 2) as i64))
+        }
+    }
+
     fn read_exp_golomb_code(&mut self) -> Result<u64> {
         let mut leading_zeros = 0;
         while 0 == track!(self.read_bit())? {
             leading_zeros += 1;
+            track_assert!(leading_zeros < 64, ErrorKind::InvalidInput);
         }
-        let mut n = 0;
-        for _ in 0..leading_zeros {
-            let bit = track!(self.read_bit())?;
-            n = (n << 1) | u64::from(bit);
-        }
-        n += 2u64.pow(leading_zeros) - 1;
-        Ok(n)
+        let n = track!(self.read_bits(leading_zeros))?;
+        Ok(n + (1 << leading_zeros) - 1)
     }
 }
 
@@ -141,88 +207,37 @@ impl<W: Write> AvcBitWriter<W> {
         Ok(())
     }
 
-    pub fn write_byte(&mut self, value: u8) -> Result<()> {
-        self.bit_position = 0;
-        self.byte = 0;
-        track_io!(self.stream.write_u8(value))?;
-        Ok(())
-    }
-
-    pub fn write_n_bits(&mut self, n: u32, value: u32) -> Result<()> {
-        let bytes = [
-            ((value & FIRST_U32_BYTE) >> 24) as u8,
-            ((value & SECOND_U32_BYTE) >> 16) as u8,
-            ((value & THIRD_U32_BYTE) >> 8) as u8,
-            ((value & FOURTH_U32_BYTE) >> 0) as u8,
-        ];
-
-        let bytes_needed = (n as f64 / 8.0).ceil() as u32;
-        let start_index = 4 - bytes_needed;
-
-        for i in start_index..4 {
-            let n = if i == start_index && n != 8 { n % 8 } else { 8 };
-
-            let byte = bytes[i as usize];
-
-            if self.bit_position == 0 && n == 8 {
-                track_io!(self.stream.write_u8(byte))?;
-                return Ok(());
-            }
-
-            let mut remaining_bits = n as usize;
-
-            while remaining_bits > 0 {
-                let available_bits = 8 - self.bit_position;
-                let bits_to_use = std::cmp::min(available_bits, remaining_bits as usize);
-
-                let mut mask: u8 = (2_u8.pow(bits_to_use as u32) - 1) as u8;
-                let mask_shift = remaining_bits - bits_to_use;
-                mask = mask << mask_shift;
-
-                let mut push_value = (byte & mask) >> mask_shift;
-
-                push_value = push_value << (8 - self.bit_position - bits_to_use);
-
-                self.byte = self.byte | push_value;
-                self.bit_position = self.bit_position + bits_to_use;
-
-                if self.bit_position == 8 {
-                    track_io!(self.stream.write_u8(self.byte))?;
-                    self.byte = 0;
-                    self.bit_position = 0;
-                }
-
-                remaining_bits = remaining_bits - bits_to_use;
-            }
+    pub fn write_bits(&mut self, n: usize, value: u64) -> Result<()> {
+        for i in (0..n).rev() {
+            track!(self.write_bit(((value >> i) & 1) as u8))?;
         }
         Ok(())
     }
 
     pub fn write_ue(&mut self, value: u64) -> Result<()> {
-        let mut bits = 0;
-        let mut cuml = 0;
-
-        for i in 0..15 {
-            if value < cuml + (1 << i) {
-                bits = i;
-                break;
-            }
-            cuml = cuml + (1 << i);
-        }
-
-        self.write_n_bits(bits, 0)?;
-        self.write_bit(1)?;
-        self.write_n_bits(bits, (value - cuml) as u32)?;
-
+        track_assert_ne!(value, u64::MAX, ErrorKind::InvalidInput);
+        let bits = 63 - (value + 1).leading_zeros() as usize;
+        track!(self.write_bits(bits, 0))?;
+        track!(self.write_bits(bits + 1, value + 1))?;
         Ok(())
     }
 
-    pub fn flush(&mut self) -> Result<()> {
-        if self.bit_position > 0 {
-            track_io!(self.stream.write_u8(self.byte))?;
-        }
+    pub fn write_se(&mut self, value: i64) -> Result<()> {
+        let n = if value > 0 {
+            (value as u64) * 2 - 1
+        } else {
+            track_assert_some!(value.unsigned_abs().checked_mul(2), ErrorKind::InvalidInput)
+        };
+        track!(self.write_ue(n))
+    }
 
-        return Ok(());
+    /// Writes `rbsp_trailing_bits()`, a stop bit followed by zero bits up to the byte boundary.
+    pub fn write_rbsp_trailing_bits(&mut self) -> Result<()> {
+        track!(self.write_bit(1))?;
+        while self.bit_position != 0 {
+            track!(self.write_bit(0))?;
+        }
+        Ok(())
     }
 }
 
@@ -247,8 +262,6 @@ mod tests {
             }
         }
 
-        writer.flush().unwrap();
-
         assert_eq!(expected.len(), actual.len());
 
         for i in 0..expected.len() {
@@ -265,7 +278,7 @@ mod tests {
             writer.write_ue(i as u64).unwrap();
         }
 
-        writer.flush().unwrap();
+        writer.write_rbsp_trailing_bits().unwrap();
 
         let mut reader = AvcBitReader::new(buffer.as_slice());
         for i in 0..1_001 {
@@ -274,39 +287,94 @@ mod tests {
     }
 
     #[test]
-    fn test_write_n_bits() {
+    fn test_se() {
         let mut buffer = Vec::<u8>::new();
         let mut writer = AvcBitWriter::new(&mut buffer);
-        let expected: [u8; 2] = [0b00001111, 0b11110000];
-
-        writer.write_n_bits(4, 0).unwrap();
-        writer.write_n_bits(4, 0xFF).unwrap();
+        for i in -100..100 {
+            writer.write_se(i).unwrap();
+        }
+        writer.write_rbsp_trailing_bits().unwrap();
 
-        writer.write_n_bits(4, 0xFF).unwrap();
-        writer.write_n_bits(4, 0).unwrap();
-        writer.flush().unwrap();
+        let mut reader = AvcBitReader::new(buffer.as_slice());
+        for i in -100..100 {
+            assert_eq!(reader.read_se().unwrap(), i);
+        }
 
-        assert_eq!(expected.len(), expected.len());
+        let mut writer = AvcBitWriter::new(Vec::new());
+        assert!(writer.write_se(i64::MIN).is_err());
+    }
 
-        for i in 0..buffer.len() {
-            assert_eq!(expected[i], buffer[i]);
+    #[test]
+    fn test_write_rbsp_trailing_bits() {
+        let expected = [
+            0b1000_0000,
+            0b0100_0000,
+            0b0010_0000,
+            0b0001_0000,
+            0b0000_1000,
+            0b0000_0100,
+            0b0000_0010,
+            0b0000_0001,
+        ];
+        for (bits, &trailing_byte) in expected.iter().enumerate() {
+            let mut buffer = Vec::new();
+            let mut writer = AvcBitWriter::new(&mut buffer);
+            writer.write_bits(8 + bits, 0).unwrap();
+            writer.write_rbsp_trailing_bits().unwrap();
+            assert_eq!(buffer, [0, trailing_byte]);
         }
     }
 
     #[test]
-    fn test_write_n_bits_across_bounds() {
-        let mut buffer = Vec::<u8>::new();
-        let mut writer = AvcBitWriter::new(&mut buffer);
-        let expected: [u8; 2] = [0b00000111, 0b11000000];
+    fn test_escape_rbsp() {
+        let rbsp = [0, 0, 1, 0, 0, 0, 0, 0, 4];
+        let escaped = escape_rbsp(&rbsp);
+        assert_eq!(escaped, [0, 0, 3, 1, 0, 0, 3, 0, 0, 3, 0, 4]);
 
-        writer.write_n_bits(5, 0).unwrap();
-        writer.write_n_bits(5, 0xFF).unwrap();
-        writer.flush().unwrap();
+        let mut unescaped = Vec::new();
+        RbspReader::new(&escaped[..]).read_to_end(&mut unescaped).unwrap();
+        assert_eq!(unescaped, rbsp);
 
-        assert_eq!(expected.len(), expected.len());
+        // The last zero is escaped even if it is not preceded by two zeros
+        assert_eq!(escape_rbsp(&[0, 0, 0]), [0, 0, 3, 0, 3]);
+    }
 
-        for i in 0..buffer.len() {
-            assert_eq!(expected[i], buffer[i]);
+    #[test]
+    fn test_emulation_prevention_corpus() {
+        let corpus: &[&[u8]] = &[
+            &[],
+            &[0],
+            &[0, 0],
+            &[0, 0, 0],
+            &[0x80, 0],
+            &[0x80, 0, 0],
+            &[1, 0, 0, 0, 2],
+            &[1, 0, 0, 1, 2],
+            &[1, 0, 0, 2, 2],
+            &[1, 0, 0, 3, 2],
+            &[1, 0, 0, 3, 3],
+            &[0, 0, 3, 3, 0, 0, 3],
+            &[0, 0, 3, 0, 0, 3, 3],
+            &[0, 0, 4, 0, 0, 0, 0, 0, 0],
+            &[0xff, 0, 0, 3, 0, 0, 1, 0, 0, 2, 0, 0, 0, 0x80],
+        ];
+        for &rbsp in corpus {
+            let escaped = escape_rbsp(rbsp);
+            for w in escaped.windows(3) {
+                assert!(!(w[0] == 0 && w[1] == 0 && w[2] <= 2), "{:?}", escaped);
+            }
+            assert_ne!(escaped.last(), Some(&0), "{:?}", escaped);
+
+            // The final `0x03` following a single zero is not an emulation prevention byte
+            let mut unescaped = Vec::new();
+            RbspReader::new(&escaped[..]).read_to_end(&mut unescaped).unwrap();
+            if unescaped.len() > rbsp.len() {
+                assert_eq!(unescaped.pop(), Some(3), "{:?}", escaped);
+            }
+            assert_eq!(unescaped, rbsp, "{:?}", escaped);
         }
+
+        assert_eq!(escape_rbsp(&[1, 0, 0, 3, 3]), [1, 0, 0, 3, 3, 3]);
+        assert_eq!(escape_rbsp(&[0x80, 0, 0]), [0x80, 0, 0, 3]);
     }
 }
@@ -112,11 +112,17 @@ macro_rules! boxes_size {
 pub use error::{Error, ErrorKind};
 
 pub mod aac;
+pub mod access_unit;
 pub mod avc;
+pub mod codec;
 pub mod fmp4;
+pub mod hevc;
+pub mod inspect;
 pub mod io;
 pub mod mpeg2_ts;
 pub mod mpeg2_ts_video;
+pub mod sei;
+pub mod vvc;
 pub mod extended_configuration_data;
 mod error;
 
@@ -3,6 +3,7 @@ use crate::aac::{self, AdtsHeader};
 use crate::avc::{
     AvcDecoderConfigurationRecord, ByteStreamFormatNalUnits, NalUnit, NalUnitType, SpsSummary,
 };
+use crate::codec::LengthSize;
 use crate::fmp4::{
     AacSampleEntry, AvcConfigurationBox, AvcSampleEntry, InitializationSegment, MediaDataBox,
     MediaSegment, MovieExtendsHeaderBox, Mp4Box, Mpeg4EsDescriptorBox, Sample, SampleEntry,
@@ -10,7 +11,6 @@ use crate::fmp4::{
 };
 use crate::io::ByteCounter;
 use crate::{Error, ErrorKind, Result};
-use byteorder::{BigEndian, WriteBytesExt};
 use mpeg2ts;
 use mpeg2ts::es::{StreamId, StreamType};
 use mpeg2ts::pes::{PesPacketReader, ReadPesPacket};
@@ -18,7 +18,6 @@ use mpeg2ts::time::Timestamp;
 use mpeg2ts::ts::{Pid, ReadTsPacket, TsPacket, TsPayload};
 use std::cmp;
 use std::collections::HashMap;
-use std::io::Write;
 
 /// Reads TS packets from `reader`, and converts them into fragmented MP4 segments.
 pub fn to_fmp4<R: ReadTsPacket>(reader: R) -> Result<(InitializationSegment, MediaSegment)> {
@@ -73,7 +72,7 @@ fn make_initialization_segment(
         .stbl_box
         .stsd_box
         .sample_entries
-        .push(SampleEntry::Avc(avc_sample_entry));
+        .push(SampleEntry::Avc(Box::new(avc_sample_entry)));
     segment.moov_box.trak_boxes.push(track);
     segment
         .moov_box
@@ -263,6 +262,7 @@ fn read_avc_aac_stream<R: ReadTsPacket>(ts_reader: R) -> Result<(AvcStream, AacS
                         profile_idc: sps_summary.profile_idc,
                         constraint_set_flag: sps_summary.constraint_set_flag,
                         level_idc: sps_summary.level_idc,
+                        length_size: LengthSize::FOUR,
                         sequence_parameter_set: sps,
                         picture_parameter_set: pps,
                         extended_configuration_data: None,
@@ -276,12 +276,9 @@ fn read_avc_aac_stream<R: ReadTsPacket>(ts_reader: R) -> Result<(AvcStream, AacS
 
             let avc_stream = avc_stream.as_mut().expect("Never fails");
             let prev_data_len = avc_stream.data.len();
+            let length_size = avc_stream.configuration.length_size;
             for nal_unit in track!(ByteStreamFormatNalUnits::new(&pes.data))? {
-                avc_stream
-                    .data
-                    .write_u32::<BigEndian>(nal_unit.len() as u32)
-                    .unwrap();
-                avc_stream.data.write_all(nal_unit).unwrap();
+                track!(length_size.write_nal_unit(&mut avc_stream.data, nal_unit))?;
             }
 
             let sample_size = (avc_stream.data.len() - prev_data_len) as u32;
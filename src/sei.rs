@@ -0,0 +1,439 @@
+//! SEI (Supplemental Enhancement Information) related constituent elements.
+//!
+//! The SEI message syntax is shared by AVC and HEVC.
+use crate::avc::{HrdParameters, SpsSummary};
+use crate::io::{AvcBitReader, RbspReader};
+use crate::{ErrorKind, Result};
+use std::io::{Read, Write};
+
+/// SEI message.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeiMessage<'a> {
+    pub payload_type: u32,
+    pub payload: &'a [u8],
+}
+impl<'a> SeiMessage<'a> {
+    /// Writes the message (`sei_message()`) to the given writer.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        track!(write_ff_coded_value(&mut writer, self.payload_type))?;
+        track!(write_ff_coded_value(&mut writer, self.payload.len() as u32))?;
+        write_all!(writer, self.payload);
+        Ok(())
+    }
+}
+
+fn write_ff_coded_value<W: Write>(mut writer: W, mut value: u32) -> Result<()> {
+    while value >= 0xFF {
+        write_u8!(writer, 0xFF);
+        value -= 0xFF;
+    }
+    write_u8!(writer, value as u8);
+    Ok(())
+}
+
+/// Iterator over the SEI messages contained in an SEI RBSP.
+#[derive(Debug)]
+pub struct SeiMessages<'a> {
+    rbsp: &'a [u8],
+}
+impl<'a> SeiMessages<'a> {
+    /// Makes a new `SeiMessages` instance.
+    ///
+    /// `rbsp` is the payload of an SEI NAL unit without its header
+    /// and without emulation prevention bytes.
+    pub fn new(rbsp: &'a [u8]) -> Self {
+        SeiMessages { rbsp }
+    }
+
+    fn read_message(&mut self) -> Result<SeiMessage<'a>> {
+        let payload_type = track!(self.read_ff_coded_value())?;
+        let payload_size = track!(self.read_ff_coded_value())? as usize;
+        track_assert!(payload_size <= self.rbsp.len(), ErrorKind::InvalidInput);
+
+        let payload = &self.rbsp[..payload_size];
+        self.rbsp = &self.rbsp[payload_size..];
+        Ok(SeiMessage {
+            payload_type,
+            payload,
+        })
+    }
+
+    fn read_ff_coded_value(&mut self) -> Result<u32> {
+        let mut value: u32 = 0;
+        loop {
+            let byte = track_assert_some!(self.rbsp.first().cloned(), ErrorKind::InvalidInput);
+            self.rbsp = &self.rbsp[1..];
+            value = track_assert_some!(value.checked_add(u32::from(byte)), ErrorKind::InvalidInput);
+            if byte != 0xFF {
+                break;
+            }
+        }
+        Ok(value)
+    }
+}
+impl<'a> Iterator for SeiMessages<'a> {
+    type Item = Result<SeiMessage<'a>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        // `rbsp_trailing_bits` follows the last message.
+        if self.rbsp.is_empty() || self.rbsp == [0x80] {
+            return None;
+        }
+        let result = track!(self.read_message());
+        if result.is_err() {
+            self.rbsp = &[];
+        }
+        Some(result)
+    }
+}
+
+/// `user_data_registered_itu_t_t35` SEI message (ITU-T T.35 framing).
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserDataRegisteredItuTT35<'a> {
+    pub itu_t_t35_country_code: u8,
+    pub itu_t_t35_country_code_extension_byte: Option<u8>,
+
+    /// The bytes following the country code (starting at the terminal provider code).
+    pub payload: &'a [u8],
+}
+impl<'a> UserDataRegisteredItuTT35<'a> {
+    /// SEI payload type of `user_data_registered_itu_t_t35`.
+    pub const PAYLOAD_TYPE: u32 = 4;
+
+    /// Parses the payload of a `user_data_registered_itu_t_t35` SEI message.
+    pub fn from_payload(payload: &'a [u8]) -> Result<Self> {
+        track_assert!(!payload.is_empty(), ErrorKind::InvalidInput);
+        let itu_t_t35_country_code = payload[0];
+        let (itu_t_t35_country_code_extension_byte, payload) = if itu_t_t35_country_code == 0xFF {
+            track_assert!(payload.len() >= 2, ErrorKind::InvalidInput);
+            (Some(payload[1]), &payload[2..])
+        } else {
+            (None, &payload[1..])
+        };
+        Ok(UserDataRegisteredItuTT35 {
+            itu_t_t35_country_code,
+            itu_t_t35_country_code_extension_byte,
+            payload,
+        })
+    }
+}
+
+/// H.264 `recovery_point` SEI message (D.1.8, ISO/IEC 14496-10).
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryPoint {
+    pub recovery_frame_cnt: u64,
+    pub exact_match_flag: bool,
+    pub broken_link_flag: bool,
+    pub changing_slice_group_idc: u8,
+}
+impl RecoveryPoint {
+    /// SEI payload type of `recovery_point`.
+    pub const PAYLOAD_TYPE: u32 = 6;
+
+    /// Parses the payload of a `recovery_point` SEI message.
+    pub fn from_payload(payload: &[u8]) -> Result<Self> {
+        let mut reader = AvcBitReader::new(payload);
+        Ok(RecoveryPoint {
+            recovery_frame_cnt: track!(reader.read_ue())?,
+            exact_match_flag: track!(reader.read_bit())? == 1,
+            broken_link_flag: track!(reader.read_bit())? == 1,
+            changing_slice_group_idc: track!(reader.read_bits(2))? as u8,
+        })
+    }
+}
+
+/// Returns `true` if the given SEI payload (following the NAL unit header)
+/// contains a `recovery_point` message.
+pub(crate) fn has_recovery_point(payload: &[u8]) -> bool {
+    let mut rbsp = Vec::new();
+    if RbspReader::new(payload).read_to_end(&mut rbsp).is_err() {
+        return false;
+    }
+    SeiMessages::new(&rbsp)
+        .any(|m| matches!(m, Ok(m) if m.payload_type == RecoveryPoint::PAYLOAD_TYPE))
+}
+
+/// H.264 `frame_packing_arrangement` SEI message (D.1.26, ISO/IEC 14496-10).
+///
+/// It signals how the two views of stereoscopic content are packed into the decoded frames.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FramePackingArrangement {
+    pub frame_packing_arrangement_id: u64,
+
+    /// The arrangement (`None` if `frame_packing_arrangement_cancel_flag` is set,
+    /// i.e., the persistence of the previous arrangement is cancelled).
+    pub packing: Option<FramePacking>,
+}
+impl FramePackingArrangement {
+    /// SEI payload type of `frame_packing_arrangement`.
+    pub const PAYLOAD_TYPE: u32 = 45;
+
+    /// Parses the payload of a `frame_packing_arrangement` SEI message.
+    pub fn from_payload(payload: &[u8]) -> Result<Self> {
+        let mut reader = AvcBitReader::new(payload);
+        let frame_packing_arrangement_id = track!(reader.read_ue())?;
+        let frame_packing_arrangement_cancel_flag = track!(reader.read_bit())? == 1;
+        if frame_packing_arrangement_cancel_flag {
+            return Ok(FramePackingArrangement {
+                frame_packing_arrangement_id,
+                packing: None,
+            });
+        }
+
+        let frame_packing_arrangement_type = track!(reader.read_bits(7))? as u8;
+        let quincunx_sampling_flag = track!(reader.read_bit())? == 1;
+        let content_interpretation_type = track!(reader.read_bits(6))? as u8;
+        let spatial_flipping_flag = track!(reader.read_bit())? == 1;
+        let frame0_flipped_flag = track!(reader.read_bit())? == 1;
+        let field_views_flag = track!(reader.read_bit())? == 1;
+        let current_frame_is_frame0_flag = track!(reader.read_bit())? == 1;
+        let frame0_self_contained_flag = track!(reader.read_bit())? == 1;
+        let frame1_self_contained_flag = track!(reader.read_bit())? == 1;
+        let grid_positions = if !quincunx_sampling_flag && frame_packing_arrangement_type != 5 {
+            let mut positions = [0; 4];
+            for position in &mut positions {
+                *position = track!(reader.read_bits(4))? as u8;
+            }
+            Some(positions)
+        } else {
+            None
+        };
+        let _frame_packing_arrangement_reserved_byte = track!(reader.read_bits(8))?;
+        let frame_packing_arrangement_repetition_period = track!(reader.read_ue())?;
+        Ok(FramePackingArrangement {
+            frame_packing_arrangement_id,
+            packing: Some(FramePacking {
+                frame_packing_arrangement_type,
+                quincunx_sampling_flag,
+                content_interpretation_type,
+                spatial_flipping_flag,
+                frame0_flipped_flag,
+                field_views_flag,
+                current_frame_is_frame0_flag,
+                frame0_self_contained_flag,
+                frame1_self_contained_flag,
+                grid_positions,
+                frame_packing_arrangement_repetition_period,
+            }),
+        })
+    }
+}
+
+/// The fields of `FramePackingArrangement` present if `frame_packing_arrangement_cancel_flag`
+/// is not set.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FramePacking {
+    /// The packing type (Table D-8, ISO/IEC 14496-10):
+    /// `0` (checkerboard), `1` (column interleaving), `2` (row interleaving),
+    /// `3` (side-by-side), `4` (top-bottom), `5` (frame alternation) or `6` (2D, no packing).
+    pub frame_packing_arrangement_type: u8,
+    pub quincunx_sampling_flag: bool,
+
+    /// `1` if frame 0 is the left view, `2` if frame 0 is the right view,
+    /// and `0` if the relationship is unspecified (Table D-9, ISO/IEC 14496-10).
+    pub content_interpretation_type: u8,
+    pub spatial_flipping_flag: bool,
+    pub frame0_flipped_flag: bool,
+    pub field_views_flag: bool,
+    pub current_frame_is_frame0_flag: bool,
+    pub frame0_self_contained_flag: bool,
+    pub frame1_self_contained_flag: bool,
+
+    /// `frame0_grid_position_x`, `frame0_grid_position_y`, `frame1_grid_position_x` and
+    /// `frame1_grid_position_y` (`None` if `quincunx_sampling_flag` is set or
+    /// `frame_packing_arrangement_type` is `5`).
+    pub grid_positions: Option<[u8; 4]>,
+    pub frame_packing_arrangement_repetition_period: u64,
+}
+
+/// H.264 `buffering_period` SEI message (D.1.2, ISO/IEC 14496-10).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferingPeriod {
+    /// The id of the SPS that contains the HRD parameters.
+    pub seq_parameter_set_id: u64,
+
+    /// The delays for each CPB of the NAL HRD (empty if the SPS has no NAL HRD parameters).
+    pub nal_initial_cpb_removal_delays: Vec<InitialCpbRemovalDelay>,
+
+    /// The delays for each CPB of the VCL HRD (empty if the SPS has no VCL HRD parameters).
+    pub vcl_initial_cpb_removal_delays: Vec<InitialCpbRemovalDelay>,
+}
+impl BufferingPeriod {
+    /// SEI payload type of `buffering_period`.
+    pub const PAYLOAD_TYPE: u32 = 0;
+
+    /// Reads `seq_parameter_set_id` of a `buffering_period` SEI payload.
+    ///
+    /// This can be used to look up the SPS to be passed to `from_payload()`.
+    pub fn read_seq_parameter_set_id(payload: &[u8]) -> Result<u64> {
+        track!(AvcBitReader::new(payload).read_ue())
+    }
+
+    /// Parses the payload of a `buffering_period` SEI message.
+    ///
+    /// The bit widths of the delays are taken from the HRD parameters of `sps`,
+    /// which must be the SPS referred by the message.
+    pub fn from_payload(payload: &[u8], sps: &SpsSummary) -> Result<Self> {
+        let mut reader = AvcBitReader::new(payload);
+        let seq_parameter_set_id = track!(reader.read_ue())?;
+        track_assert_eq!(
+            seq_parameter_set_id,
+            sps.seq_parameter_set_id,
+            ErrorKind::InvalidInput
+        );
+
+        let vui = sps.vui_parameters.as_ref();
+        let mut read_delays = |hrd: Option<&HrdParameters>| -> Result<_> {
+            let hrd = match hrd {
+                Some(hrd) => hrd,
+                None => return Ok(Vec::new()),
+            };
+            let bits = usize::from(hrd.initial_cpb_removal_delay_length_minus1) + 1;
+            let mut delays = Vec::with_capacity(hrd.cpb_specs.len());
+            for _ in 0..hrd.cpb_specs.len() {
+                delays.push(InitialCpbRemovalDelay {
+                    initial_cpb_removal_delay: track!(reader.read_bits(bits))? as u32,
+                    initial_cpb_removal_delay_offset: track!(reader.read_bits(bits))? as u32,
+                });
+            }
+            Ok(delays)
+        };
+        let nal_initial_cpb_removal_delays =
+            track!(read_delays(vui.and_then(|x| x.nal_hrd_parameters.as_ref())))?;
+        let vcl_initial_cpb_removal_delays =
+            track!(read_delays(vui.and_then(|x| x.vcl_hrd_parameters.as_ref())))?;
+        Ok(BufferingPeriod {
+            seq_parameter_set_id,
+            nal_initial_cpb_removal_delays,
+            vcl_initial_cpb_removal_delays,
+        })
+    }
+}
+
+/// Initial CPB removal delay of a CPB in `BufferingPeriod`.
+///
+/// Both values are in units of a 90 kHz clock.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitialCpbRemovalDelay {
+    pub initial_cpb_removal_delay: u32,
+    pub initial_cpb_removal_delay_offset: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sei_messages() {
+        let rbsp = [
+            0xFF, 0x01, 0x02, 0xAA, 0xBB, // payload_type=256, payload_size=2
+            0x05, 0x00, // payload_type=5, payload_size=0
+            0x80, // rbsp_trailing_bits
+        ];
+        let messages = SeiMessages::new(&rbsp)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].payload_type, 256);
+        assert_eq!(messages[0].payload, &[0xAA, 0xBB][..]);
+        assert_eq!(messages[1].payload_type, 5);
+        assert!(messages[1].payload.is_empty());
+
+        let truncated = [0x04, 0x03, 0xB5];
+        let mut messages = SeiMessages::new(&truncated);
+        assert!(messages.next().unwrap().is_err());
+        assert!(messages.next().is_none());
+
+        // payload_type exceeding `u32::MAX`
+        let mut overflowed = vec![0xFF; (u32::MAX / 0xFF) as usize + 1];
+        overflowed.extend_from_slice(&[0x00, 0x00, 0x80]);
+        assert!(SeiMessages::new(&overflowed).next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_write_sei_message() {
+        let payload = [0xAA; 300];
+        let message = SeiMessage {
+            payload_type: 255,
+            payload: &payload,
+        };
+        let mut bytes = Vec::new();
+        message.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes[..4], [0xFF, 0x00, 0xFF, 0x2D]);
+
+        let actual = SeiMessages::new(&bytes).next().unwrap().unwrap();
+        assert_eq!(actual, message);
+    }
+
+    #[test]
+    fn test_recovery_point() {
+        // recovery_frame_cnt=2, exact_match_flag=1, broken_link_flag=0
+        let recovery_point = RecoveryPoint::from_payload(&[0b0111_0000]).unwrap();
+        assert_eq!(recovery_point.recovery_frame_cnt, 2);
+        assert!(recovery_point.exact_match_flag);
+        assert!(!recovery_point.broken_link_flag);
+        assert_eq!(recovery_point.changing_slice_group_idc, 0);
+    }
+
+    #[test]
+    fn test_frame_packing_arrangement() {
+        // Side-by-side, frame 0 is the left view
+        let x = FramePackingArrangement::from_payload(&[0x81, 0x81, 0x10, 0x00, 0x00, 0x01, 0x20])
+            .unwrap();
+        assert_eq!(x.frame_packing_arrangement_id, 0);
+        let packing = x.packing.unwrap();
+        assert_eq!(packing.frame_packing_arrangement_type, 3);
+        assert!(!packing.quincunx_sampling_flag);
+        assert_eq!(packing.content_interpretation_type, 1);
+        assert!(!packing.spatial_flipping_flag);
+        assert!(packing.current_frame_is_frame0_flag);
+        assert_eq!(packing.grid_positions, Some([0; 4]));
+        assert_eq!(packing.frame_packing_arrangement_repetition_period, 1);
+
+        // Top-bottom with quincunx sampling (no grid positions)
+        let x = FramePackingArrangement::from_payload(&[0x82, 0x42, 0x00, 0x02, 0x80]).unwrap();
+        let packing = x.packing.unwrap();
+        assert_eq!(packing.frame_packing_arrangement_type, 4);
+        assert!(packing.quincunx_sampling_flag);
+        assert_eq!(packing.content_interpretation_type, 2);
+        assert_eq!(packing.grid_positions, None);
+        assert_eq!(packing.frame_packing_arrangement_repetition_period, 0);
+
+        // Cancelled
+        let x = FramePackingArrangement::from_payload(&[0x74]).unwrap();
+        assert_eq!(x.frame_packing_arrangement_id, 2);
+        assert_eq!(x.packing, None);
+    }
+
+    #[test]
+    fn test_buffering_period() {
+        // Baseline profile, 320x240, with NAL HRD parameters
+        // (cpb_cnt_minus1=0, initial_cpb_removal_delay_length_minus1=23)
+        let sps = [
+            0x42, 0xc0, 0x1e, 0xda, 0x05, 0x07, 0xe8, 0x31, 0x20, 0x07, 0xd2, 0x00, 0x7d, 0x15,
+            0xef, 0x7c, 0x04,
+        ];
+        let sps = SpsSummary::read_from(&sps[..]).unwrap();
+
+        // seq_parameter_set_id=0, initial_cpb_removal_delay=90000,
+        // initial_cpb_removal_delay_offset=1234
+        let payload = [0x80, 0xaf, 0xc8, 0x00, 0x02, 0x69, 0x40];
+        assert_eq!(BufferingPeriod::read_seq_parameter_set_id(&payload).unwrap(), 0);
+        let buffering_period = BufferingPeriod::from_payload(&payload, &sps).unwrap();
+        assert_eq!(
+            buffering_period.nal_initial_cpb_removal_delays,
+            [InitialCpbRemovalDelay {
+                initial_cpb_removal_delay: 90_000,
+                initial_cpb_removal_delay_offset: 1234,
+            }]
+        );
+        assert!(buffering_period.vcl_initial_cpb_removal_delays.is_empty());
+
+        assert!(BufferingPeriod::from_payload(&payload[..3], &sps).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+//! VVC (H.266) related constituent elements.
+use crate::codec::LengthSize;
+use crate::{ErrorKind, Result};
+use std::io::Write;
+
+/// `nal_unit_type` of VPS NAL units (Table 5, ITU-T H.266).
+const VPS_NUT: u8 = 14;
+
+/// `nal_unit_type` of SPS NAL units.
+const SPS_NUT: u8 = 15;
+
+/// `nal_unit_type` of PPS NAL units.
+const PPS_NUT: u8 = 16;
+
+/// `nal_unit_type` of prefix APS NAL units.
+const PREFIX_APS_NUT: u8 = 17;
+
+/// VVC decoder configuration record (11.2.4.2, ISO/IEC 14496-15).
+///
+/// This is a write-only record built from the given fields and parameter sets;
+/// the parameter sets are not parsed.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VvcDecoderConfigurationRecord {
+    /// The size of the NAL unit length field of each sample.
+    pub length_size: LengthSize,
+
+    /// The profile, tier and level information (`None` if `ptl_present_flag` is `0`).
+    pub ptl: Option<VvcPtlInfo>,
+
+    /// VPS NAL unit (including the NAL unit header, empty if the VPS is absent).
+    pub vps_data: Vec<u8>,
+
+    /// SPS NAL unit (including the NAL unit header).
+    pub sps_data: Vec<u8>,
+
+    /// PPS NAL unit (including the NAL unit header).
+    pub pps_data: Vec<u8>,
+
+    /// Prefix APS NAL units (including their NAL unit headers).
+    pub aps_data: Vec<Vec<u8>>,
+}
+impl VvcDecoderConfigurationRecord {
+    /// Writes the record to the given writer.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        track_assert!(!self.sps_data.is_empty(), ErrorKind::InvalidInput, "No SPS");
+        track_assert!(!self.pps_data.is_empty(), ErrorKind::InvalidInput, "No PPS");
+
+        write_u8!(
+            writer,
+            0b1111_1000 | (self.length_size.length_size_minus_one() << 1) | self.ptl.is_some() as u8
+        );
+        if let Some(ref ptl) = self.ptl {
+            track!(ptl.write_to(&mut writer))?;
+        }
+
+        let mut arrays = Vec::with_capacity(4);
+        if !self.vps_data.is_empty() {
+            arrays.push((VPS_NUT, vec![&self.vps_data]));
+        }
+        arrays.push((SPS_NUT, vec![&self.sps_data]));
+        arrays.push((PPS_NUT, vec![&self.pps_data]));
+        if !self.aps_data.is_empty() {
+            arrays.push((PREFIX_APS_NUT, self.aps_data.iter().collect()));
+        }
+        write_u8!(writer, arrays.len() as u8); // num_of_arrays
+        for (nal_unit_type, nal_units) in arrays {
+            // array_completeness (1), reserved (0) and NAL_unit_type
+            write_u8!(writer, 0b1000_0000 | nal_unit_type);
+            write_u16!(writer, nal_units.len() as u16); // num_nalus
+            for nal_unit in nal_units {
+                track_assert!(nal_unit.len() <= 0xFFFF, ErrorKind::InvalidInput);
+                write_u16!(writer, nal_unit.len() as u16);
+                write_all!(writer, nal_unit);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The fields of `VvcDecoderConfigurationRecord` present if `ptl_present_flag` is `1`.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VvcPtlInfo {
+    /// The output layer set index of the operation point (`0..=511`).
+    pub ols_idx: u16,
+
+    /// The number of temporal sub-layers (`1..=7`).
+    pub num_sublayers: u8,
+    pub constant_frame_rate: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_minus8: u8,
+    pub native_ptl: VvcPtlRecord,
+    pub max_picture_width: u16,
+    pub max_picture_height: u16,
+    pub avg_frame_rate: u16,
+}
+impl VvcPtlInfo {
+    fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        track_assert!(self.ols_idx <= 0x1FF, ErrorKind::InvalidInput);
+        track_assert!(
+            1 <= self.num_sublayers && self.num_sublayers <= 7,
+            ErrorKind::InvalidInput,
+            "num_sublayers={}",
+            self.num_sublayers
+        );
+        track_assert!(self.constant_frame_rate <= 3, ErrorKind::InvalidInput);
+        track_assert!(self.chroma_format_idc <= 3, ErrorKind::InvalidInput);
+        track_assert!(self.bit_depth_minus8 <= 7, ErrorKind::InvalidInput);
+
+        write_u16!(
+            writer,
+            (self.ols_idx << 7)
+                | (u16::from(self.num_sublayers) << 4)
+                | (u16::from(self.constant_frame_rate) << 2)
+                | u16::from(self.chroma_format_idc)
+        );
+        write_u8!(writer, (self.bit_depth_minus8 << 5) | 0b1_1111);
+        track!(self.native_ptl.write_to(&mut writer, self.num_sublayers))?;
+        write_u16!(writer, self.max_picture_width);
+        write_u16!(writer, self.max_picture_height);
+        write_u16!(writer, self.avg_frame_rate);
+        Ok(())
+    }
+}
+
+/// VVC profile, tier and level record (`VvcPTLRecord`).
+///
+/// The sub-layer level information is not supported;
+/// all `ptl_sublayer_level_present_flag`s are written as `0`.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VvcPtlRecord {
+    pub general_profile_idc: u8,
+    pub general_tier_flag: bool,
+    pub general_level_idc: u8,
+
+    /// The `num_bytes_constraint_info` bytes (`1..=63`) following `general_level_idc`.
+    ///
+    /// The two most significant bits are `ptl_frame_only_constraint_flag`
+    /// and `ptl_multilayer_enabled_flag`.
+    pub general_constraint_info: Vec<u8>,
+    pub general_sub_profile_idcs: Vec<u32>,
+}
+impl VvcPtlRecord {
+    fn write_to<W: Write>(&self, mut writer: W, num_sublayers: u8) -> Result<()> {
+        track_assert!(self.general_profile_idc <= 0x7F, ErrorKind::InvalidInput);
+        track_assert!(
+            !self.general_constraint_info.is_empty() && self.general_constraint_info.len() <= 63,
+            ErrorKind::InvalidInput,
+            "num_bytes_constraint_info={}",
+            self.general_constraint_info.len()
+        );
+        track_assert!(self.general_sub_profile_idcs.len() <= 0xFF, ErrorKind::InvalidInput);
+
+        write_u8!(writer, self.general_constraint_info.len() as u8);
+        write_u8!(
+            writer,
+            (self.general_profile_idc << 1) | self.general_tier_flag as u8
+        );
+        write_u8!(writer, self.general_level_idc);
+        write_all!(writer, &self.general_constraint_info);
+        if num_sublayers > 1 {
+            // ptl_sublayer_level_present_flag[i] and ptl_reserved_zero_bit
+            write_u8!(writer, 0);
+        }
+        write_u8!(writer, self.general_sub_profile_idcs.len() as u8);
+        for &idc in &self.general_sub_profile_idcs {
+            write_u32!(writer, idc);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_vvc_decoder_configuration_record() {
+        let mut record = VvcDecoderConfigurationRecord {
+            length_size: LengthSize::FOUR,
+            ptl: Some(VvcPtlInfo {
+                ols_idx: 0,
+                num_sublayers: 2,
+                constant_frame_rate: 1,
+                chroma_format_idc: 1,
+                bit_depth_minus8: 2,
+                native_ptl: VvcPtlRecord {
+                    general_profile_idc: 1,
+                    general_tier_flag: false,
+                    general_level_idc: 83,
+                    general_constraint_info: vec![0x40],
+                    general_sub_profile_idcs: Vec::new(),
+                },
+                max_picture_width: 1920,
+                max_picture_height: 1080,
+                avg_frame_rate: 0,
+            }),
+            vps_data: Vec::new(),
+            sps_data: vec![0x00, 0x79, 0xaa],
+            pps_data: vec![0x00, 0x81, 0xbb],
+            aps_data: Vec::new(),
+        };
+        let mut bytes = Vec::new();
+        record.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0xff, // reserved, LengthSizeMinusOne and ptl_present_flag
+                0x00, 0x25, // ols_idx, num_sublayers, constant_frame_rate, chroma_format_idc
+                0x5f, // bit_depth_minus8 and reserved
+                0x01, 0x02, 0x53, 0x40, 0x00, 0x00, // native_ptl
+                0x07, 0x80, 0x04, 0x38, 0x00, 0x00, // width, height, avg_frame_rate
+                0x02, // num_of_arrays
+                0x8f, 0x00, 0x01, 0x00, 0x03, 0x00, 0x79, 0xaa, // SPS
+                0x90, 0x00, 0x01, 0x00, 0x03, 0x00, 0x81, 0xbb, // PPS
+            ][..]
+        );
+
+        record.ptl = None;
+        record.sps_data.clear();
+        assert!(record.write_to(Vec::new()).is_err());
+    }
+}